@@ -1,12 +1,63 @@
 import test from 'ava'
 
-import { MuseDevice } from '../index.js'
+import { MuseDevice, MuseManager } from '../index.js'
 
 test('MuseDevice creation', (t) => {
   const device = new MuseDevice({})
   t.truthy(device)
   t.is(device.isConnected, false)
   t.is(device.isStreaming, false)
+  t.is(device.isScanning, false)
+  t.is(device.lastCommandAgeMs, null)
+  t.is(device.lastSampleAgeMs, null)
+  t.is(device.supportsSleepMode, false)
+  t.is(device.outletUids, null)
+  t.is(device.lastError, null)
+  t.deepEqual(device.silentChannels, [])
+  t.deepEqual(device.getStreamInfo(), [])
+})
+
+test('device commands reject when not connected', async (t) => {
+  const commands = [
+    (device) => device.enableSleepMode(),
+    (device) => device.setPreset('p21'),
+    (device) => device.measureCommandLatency(),
+    (device) => device.softRestart(),
+    (device) => device.ping(),
+    (device) => device.resync(),
+    (device) => device.runDiagnostics(100),
+    (device) => device.waitForBattery(50, 1000),
+  ]
+  for (const command of commands) {
+    await t.throwsAsync(() => command(new MuseDevice({})), { message: 'Device not connected' }, command.toString())
+  }
+})
+
+test('connect rejects an unknown device handle', async (t) => {
+  const device = new MuseDevice({})
+  await t.throwsAsync(() => device.connect('muse-0'), {
+    message: 'Failed to find Muse device: Unknown device handle muse-0',
+  })
+  t.is(device.lifecycle.state, 'error')
+})
+
+test('shutdown is safe when not connected and repeatable', async (t) => {
+  const device = new MuseDevice({})
+  device.onEeg(() => {})
+  await device.shutdown()
+  await device.shutdown()
+  t.deepEqual(device.registeredCallbacks(), [])
+  t.is(device.lifecycle.state, 'idle')
+})
+
+test('runtimeStats reports no tasks before streaming', async (t) => {
+  const device = new MuseDevice({})
+  t.deepEqual(await device.runtimeStats(), {
+    activeStreamingTasks: 0,
+    lslThreads: 0,
+    notificationTaskRunning: false,
+    keepaliveRunning: false,
+  })
 })
 
 test('MuseDevice with options', (t) => {
@@ -19,11 +70,401 @@ test('MuseDevice with options', (t) => {
   t.is(device.isConnected, false)
 })
 
+test('eegChannels drops AUX when includeAux is false', (t) => {
+  t.deepEqual(new MuseDevice({}).eegChannels, ['EEG_TP9', 'EEG_AF7', 'EEG_AF8', 'EEG_TP10', 'EEG_AUX'])
+  t.deepEqual(new MuseDevice({ includeAux: false }).eegChannels, [
+    'EEG_TP9',
+    'EEG_AF7',
+    'EEG_AF8',
+    'EEG_TP10',
+  ])
+})
+
+test('getSignalQuality throws when not streaming', (t) => {
+  t.throws(() => new MuseDevice({}).getSignalQuality(), { message: 'Not streaming' })
+})
+
+test('pushSyncPulse pairs external times with LSL time and keeps the last 100', (t) => {
+  const device = new MuseDevice({})
+  t.deepEqual(device.syncPulses, [])
+  const first = device.pushSyncPulse(1000.5)
+  t.is(first.externalTime, 1000.5)
+  t.is(typeof first.localTime, 'number')
+
+  for (let i = 1; i <= 100; i++) {
+    device.pushSyncPulse(1000.5 + i)
+  }
+  const pulses = device.syncPulses
+  t.is(pulses.length, 100)
+  t.is(pulses[0].externalTime, 1001.5)
+  t.true(pulses[99].localTime >= first.localTime)
+})
+
+test('getLatencyStats is null until measureLatency has something to report', (t) => {
+  t.is(new MuseDevice({}).getLatencyStats(), null)
+  const device = new MuseDevice({ measureLatency: true })
+  t.is(device.getLatencyStats(), null)
+  t.is(device.getEffectiveOptions().options.measureLatency, true)
+})
+
+test('lslActive is false without outlets, including after stopStreaming', async (t) => {
+  const device = new MuseDevice({})
+  t.false(device.lslActive)
+  await device.stopStreaming()
+  t.false(device.lslActive)
+})
+
+test('readCharacteristic validates the UUID before needing a connection', async (t) => {
+  const device = new MuseDevice({})
+  await t.throwsAsync(() => device.readCharacteristic('not-a-uuid'), { message: /^Invalid UUID "not-a-uuid"/ })
+  await t.throwsAsync(() => device.readCharacteristic(MuseDevice.knownUuids().control), {
+    message: 'Device not connected',
+  })
+})
+
+test('hardwareRevision is null until read, and reading needs a connection', async (t) => {
+  const device = new MuseDevice({})
+  t.is(device.hardwareRevision, null)
+  await t.throwsAsync(() => device.readHardwareRevision(), { message: 'Device not connected' })
+})
+
+test('includeSampleIndex appends a SampleIndex channel to EEG and PPG', (t) => {
+  const device = new MuseDevice({ includeAux: false, includeSampleIndex: true })
+  t.deepEqual(device.eegChannels, ['EEG_TP9', 'EEG_AF7', 'EEG_AF8', 'EEG_TP10', 'SampleIndex'])
+  t.deepEqual(device.ppgChannels, ['PPG_AMBIENT', 'PPG_INFRARED', 'PPG_RED', 'SampleIndex'])
+  t.is(device.getProcessingConfig().includeSampleIndex, true)
+  t.is(new MuseDevice({}).getProcessingConfig().includeSampleIndex, false)
+})
+
+test('lifecycle starts idle and rejected calls leave it there', async (t) => {
+  const device = new MuseDevice({})
+  const states = []
+  device.onStateChange((state) => states.push(state))
+  t.is(device.lifecycle.state, 'idle')
+  t.falsy(device.lifecycle.lastError)
+
+  await t.throwsAsync(() => device.startStreaming(), { message: 'Device not connected' })
+  await device.disconnect()
+  t.is(device.lifecycle.state, 'idle')
+  t.deepEqual(states, [])
+})
+
+test('autoConnectOnStream reports a failed implicit connect', async (t) => {
+  const device = new MuseDevice({ autoConnectOnStream: true, adapter: 'no such adapter' })
+  await t.throwsAsync(() => device.startStreaming(), { message: /^Auto-connect failed: / })
+  t.is(device.lifecycle.state, 'error')
+  t.is(device.isConnected, false)
+})
+
+test('getProcessingConfig reports the configured filter chains', (t) => {
+  const shared = new MuseDevice({ ppgSmoothingWindow: 4, clampCounts: 500 }).getProcessingConfig()
+  t.like(shared.lsl, { ppgSmoothingWindow: 4, ppgSubtractAmbient: false, clampCounts: 500 })
+  t.falsy(shared.js)
+  t.is(shared.includeAux, true)
+
+  const split = new MuseDevice({ jsProcessing: { ppgSubtractAmbient: true } }).getProcessingConfig()
+  t.like(split.lsl, { ppgSmoothingWindow: 0, ppgSubtractAmbient: false })
+  t.like(split.js, { ppgSmoothingWindow: 0, ppgSubtractAmbient: true })
+})
+
+test('getEffectiveOptions fills in the defaults', (t) => {
+  const { options, processing } = new MuseDevice({ bleUuid: 'abc', clampCounts: 500 }).getEffectiveOptions()
+  t.like(options, {
+    bleUuid: 'abc',
+    clampCounts: 500,
+    includeAux: true,
+    chunkedPush: true,
+    lslOutletRetries: 2,
+    silentChannelTimeoutMs: 5000,
+    serviceReadyMs: 3000,
+    controlCommandGapMs: 20,
+    scanPollIntervalMs: 100,
+    previewOutlet: false,
+    autoConnectOnStream: false,
+    timestampSource: 'lsl',
+    ppgOutputRate: 64,
+  })
+  t.is(options.keepaliveIntervalMs, undefined)
+  t.is(options.idleDisconnectMs, undefined)
+  t.like(processing.lsl, { clampCounts: 500 })
+})
+
+test('filterExcludeChannels is checked against the active EEG channels', (t) => {
+  const device = new MuseDevice({ clampCounts: 500, filterExcludeChannels: ['EEG_AUX'] })
+  t.deepEqual(device.getProcessingConfig().filterExcludeChannels, ['EEG_AUX'])
+  t.deepEqual(new MuseDevice({}).getProcessingConfig().filterExcludeChannels, [])
+
+  t.throws(() => new MuseDevice({ filterExcludeChannels: ['EEG_FPZ'] }), {
+    message: /^Unknown filterExcludeChannels label "EEG_FPZ"/,
+  })
+  t.throws(() => new MuseDevice({ includeAux: false, filterExcludeChannels: ['EEG_AUX'] }), {
+    message: /^Unknown filterExcludeChannels label "EEG_AUX"/,
+  })
+})
+
+test('ppgOutputRate must divide 64 Hz evenly', (t) => {
+  t.is(new MuseDevice({}).getProcessingConfig().ppgOutputRate, 64)
+  t.is(new MuseDevice({ ppgOutputRate: 16 }).getProcessingConfig().ppgOutputRate, 16)
+  t.throws(() => new MuseDevice({ ppgOutputRate: 48 }), { message: /^ppgOutputRate must divide 64 Hz evenly/ })
+  t.throws(() => new MuseDevice({ ppgOutputRate: 0 }), { message: /^ppgOutputRate must divide 64 Hz evenly/ })
+})
+
+test('channelFormat accepts float32 and int16 EEG', (t) => {
+  t.is(new MuseDevice({}).getEffectiveOptions().options.channelFormat, 'float32')
+  t.is(new MuseDevice({ channelFormat: 'int16' }).getEffectiveOptions().options.channelFormat, 'int16')
+  t.throws(() => new MuseDevice({ channelFormat: 'int32' }), { message: /^channelFormat must be/ })
+  t.throws(() => new MuseDevice({ channelFormat: 'int16', includeSampleIndex: true }), {
+    message: 'channelFormat "int16" can\'t carry includeSampleIndex',
+  })
+})
+
+test('timestampSource rejects unknown clocks', (t) => {
+  t.is(new MuseDevice({ timestampSource: 'unix' }).getEffectiveOptions().options.timestampSource, 'unix')
+  t.throws(() => new MuseDevice({ timestampSource: 'utc' }), {
+    message: 'timestampSource must be "lsl", "unix" or "monotonic", got utc',
+  })
+})
+
+test('ppgChannels follow ppgSubtractAmbient and ppgDropAmbient', (t) => {
+  t.deepEqual(new MuseDevice({}).ppgChannels, ['PPG_AMBIENT', 'PPG_INFRARED', 'PPG_RED'])
+  t.deepEqual(new MuseDevice({ ppgSubtractAmbient: true }).ppgChannels, [
+    'PPG_AMBIENT',
+    'PPG_INFRARED_MINUS_AMBIENT',
+    'PPG_RED_MINUS_AMBIENT',
+  ])
+  t.deepEqual(new MuseDevice({ ppgSubtractAmbient: true, ppgDropAmbient: true }).ppgChannels, [
+    'PPG_INFRARED_MINUS_AMBIENT',
+    'PPG_RED_MINUS_AMBIENT',
+  ])
+  t.deepEqual(new MuseDevice({ ppgDropAmbient: true }).ppgChannels, ['PPG_AMBIENT', 'PPG_INFRARED', 'PPG_RED'])
+})
+
 test('MuseDevice getters throw when not connected', (t) => {
   const device = new MuseDevice({})
 
   t.throws(() => device.bleName, { message: 'Device not connected' })
   t.throws(() => device.bleUuid, { message: 'Device not connected' })
+  t.throws(() => device.packetSizes, { message: 'Device not connected' })
+})
+
+test('callbacks can be registered and removed', (t) => {
+  const device = new MuseDevice({})
+  device.onEeg(() => {})
+  device.onPpg(() => {})
+  t.deepEqual(device.registeredCallbacks(), ['eeg', 'ppg'])
+
+  device.onSample(() => {})
+  device.onLslError(() => {})
+  t.deepEqual(device.registeredCallbacks(), ['eeg', 'ppg', 'sample', 'lsl_error'])
+
+  device.offEeg()
+  device.offSample()
+  device.offLslError()
+  t.deepEqual(device.registeredCallbacks(), ['ppg'])
+
+  device.clearCallbacks()
+  t.deepEqual(device.registeredCallbacks(), [])
+})
+
+test('a removed callback is not invoked', async (t) => {
+  const device = new MuseDevice({})
+  const tick = () => new Promise((resolve) => setImmediate(resolve))
+  let removed = 0
+  device.onStateChange(() => removed++)
+  device.offStateChange()
+
+  // An unknown handle still walks the lifecycle, so callbacks fire without a headband
+  await t.throwsAsync(() => device.connect('muse-0'))
+  await tick()
+  t.is(device.lifecycle.state, 'error')
+  t.is(removed, 0)
+
+  const states = []
+  device.onStateChange((state) => states.push(state))
+  await t.throwsAsync(() => device.connect('muse-0'))
+  await tick()
+  t.deepEqual(states, ['scanning', 'error'])
+  t.is(removed, 0)
+})
+
+test('idleDisconnectMs does nothing without a connection', async (t) => {
+  const device = new MuseDevice({ idleDisconnectMs: 10 })
+  const reasons = []
+  device.onAutoDisconnect((reason) => reasons.push(reason))
+  t.deepEqual(device.registeredCallbacks(), ['auto_disconnect'])
+
+  // Nothing is connected, so there is nothing to time out
+  await new Promise((resolve) => setTimeout(resolve, 50))
+  t.deepEqual(reasons, [])
+  device.offAutoDisconnect()
+  t.deepEqual(device.registeredCallbacks(), [])
+})
+
+test('consumer callbacks register under their own names', (t) => {
+  const device = new MuseDevice({})
+  device.onConsumerConnected(() => {})
+  device.onConsumerDisconnected(() => {})
+  t.deepEqual(device.registeredCallbacks(), ['consumer_connected', 'consumer_disconnected'])
+
+  device.offConsumerConnected()
+  t.deepEqual(device.registeredCallbacks(), ['consumer_disconnected'])
+  device.offConsumerDisconnected()
+  t.deepEqual(device.registeredCallbacks(), [])
+})
+
+test('onPpgRaw registers alongside onPpg', (t) => {
+  const device = new MuseDevice({})
+  device.onPpg(() => {})
+  device.onPpgRaw(() => {})
+  t.deepEqual(device.registeredCallbacks(), ['ppg', 'ppg_raw'])
+  device.offPpgRaw()
+  t.deepEqual(device.registeredCallbacks(), ['ppg'])
+})
+
+test('streamTypes override the outlet types', (t) => {
+  t.deepEqual(new MuseDevice({}).getEffectiveOptions().options.streamTypes, { eeg: 'EEG', ppg: 'PPG' })
+  const device = new MuseDevice({ bracketMarkers: true, streamTypes: { ppg: 'Pleth', markers: 'Markers' } })
+  t.deepEqual(device.getEffectiveOptions().options.streamTypes, {
+    eeg: 'EEG',
+    ppg: 'Pleth',
+    markers: 'Markers',
+  })
+})
+
+test('getLatestSample is null before any sample', (t) => {
+  const device = new MuseDevice({})
+  t.is(device.getLatestSample('eeg'), null)
+  t.is(device.getLatestSample('ppg'), null)
+  t.is(device.getLatestSample('accel'), null)
+  t.is(device.getLatestSample('gyro'), null)
+  t.throws(() => device.getLatestSample('imu'), { message: 'kind must be "eeg", "ppg", "accel" or "gyro", got imu' })
+})
+
+test('sampleStream is async iterable and ends on return', async (t) => {
+  const device = new MuseDevice({})
+  const stream = device.sampleStream({ kind: 'eeg', overflow: 'dropNewest' })
+  t.is(stream[Symbol.asyncIterator](), stream)
+  t.deepEqual(device.registeredCallbacks(), ['sample_stream'])
+  t.deepEqual(stream.return(), { done: true })
+  t.deepEqual(device.registeredCallbacks(), [])
+  const samples = []
+  for await (const sample of stream) samples.push(sample)
+  t.deepEqual(samples, [])
+  t.is(stream.dropped, 0)
+  t.throws(() => device.sampleStream({ overflow: 'block' }))
+})
+
+test('encodeCommand frames commands like the TypeScript implementation', (t) => {
+  t.deepEqual([...MuseDevice.encodeCommand('p50')], [4, 0x70, 0x35, 0x30, 0x0a])
+  t.deepEqual([...MuseDevice.encodeCommand('h')], [2, 0x68, 0x0a])
+  t.throws(() => MuseDevice.encodeCommand(''), { message: 'Command is empty' })
+  t.throws(() => MuseDevice.encodeCommand('p 50'))
+})
+
+test('burst fills in its duty cycle and checks it', (t) => {
+  const device = new MuseDevice({ burst: { onMs: 5000 } })
+  t.deepEqual(device.getEffectiveOptions().options.burst, { onMs: 5000, everyMs: 60000 })
+  t.is(device.burstState, 'off')
+  t.throws(() => new MuseDevice({ burst: { onMs: 60000 } }), {
+    message: 'burst.onMs must be shorter than burst.everyMs',
+  })
+})
+
+test('epochQuality fills in its thresholds and registers onEpochQuality', (t) => {
+  const device = new MuseDevice({ epochQuality: { maxRangeCounts: 100 } })
+  t.deepEqual(device.getEffectiveOptions().options.epochQuality, {
+    epochMs: 1000,
+    maxRangeCounts: 100,
+    maxStepCounts: 50,
+  })
+  t.is(new MuseDevice({}).getEffectiveOptions().options.epochQuality, undefined)
+  t.throws(() => new MuseDevice({ epochQuality: { epochMs: 1 } }), { message: /^epochQuality.epochMs must cover/ })
+
+  device.onEpochQuality(() => {})
+  t.deepEqual(device.registeredCallbacks(), ['epoch_quality'])
+  device.offEpochQuality()
+  t.deepEqual(device.registeredCallbacks(), [])
+})
+
+test('pauseSink and resumeSink toggle sinks by name', (t) => {
+  const device = new MuseDevice({})
+  t.deepEqual(device.pausedSinks, [])
+
+  device.pauseSink('udp')
+  device.pauseSink('lsl')
+  t.deepEqual(device.pausedSinks, ['lsl', 'udp'])
+
+  device.resumeSink('lsl')
+  t.deepEqual(device.pausedSinks, ['udp'])
+  t.throws(() => device.pauseSink('xdf'), { message: /Unknown sink "xdf"/ })
+})
+
+test('knownUuids lists the service and characteristics by name', (t) => {
+  const uuids = MuseDevice.knownUuids()
+  t.is(uuids.service, '0000fe8d-0000-1000-8000-00805f9b34fb')
+  t.is(uuids.control, '273e0001-4c4d-454d-96be-f03bac821358')
+  t.is(uuids.eeg_tp9, '273e0003-4c4d-454d-96be-f03bac821358')
+  t.is(uuids.ppg_red, '273e0011-4c4d-454d-96be-f03bac821358')
+})
+
+test('source_id check is off by default and never renames', (t) => {
+  const options = new MuseDevice({}).getEffectiveOptions().options
+  t.is(options.sourceIdCheckMs, 0)
+  t.is(options.disambiguateSourceIds, false)
+  const device = new MuseDevice({ sourceIdCheckMs: 500, disambiguateSourceIds: true })
+  t.is(device.getEffectiveOptions().options.sourceIdCheckMs, 500)
+  device.onSourceIdConflict(() => {})
+  t.deepEqual(device.registeredCallbacks(), ['source_id_conflict'])
+})
+
+test('batteryLevel throws until telemetry arrives', (t) => {
+  const device = new MuseDevice({})
+  t.throws(() => device.batteryLevel, { message: 'Telemetry not available' })
+  t.is(device.telemetry, null)
+  t.is(MuseDevice.knownUuids().telemetry, '273e000b-4c4d-454d-96be-f03bac821358')
+})
+
+test('imu adds the accelerometer and gyroscope outlets', (t) => {
+  t.is(MuseDevice.knownUuids().accelerometer, '273e000a-4c4d-454d-96be-f03bac821358')
+  t.is(MuseDevice.knownUuids().gyro, '273e0009-4c4d-454d-96be-f03bac821358')
+  t.is(new MuseDevice({}).getEffectiveOptions().options.imu, false)
+  const device = new MuseDevice({ imu: true })
+  t.deepEqual(device.getEffectiveOptions().options.streamTypes, {
+    eeg: 'EEG',
+    ppg: 'PPG',
+    accelerometer: 'Accelerometer',
+    gyroscope: 'Gyroscope',
+  })
+})
+
+test('orientation needs imu and fills in its gain', (t) => {
+  t.throws(() => new MuseDevice({ orientation: {} }), { message: 'orientation needs imu, which provides its samples' })
+  t.throws(() => new MuseDevice({ imu: true, orientation: { gain: 0 } }), {
+    message: 'orientation.gain must be a positive number, got 0',
+  })
+  const device = new MuseDevice({ imu: true, orientation: {} })
+  const { options } = device.getEffectiveOptions()
+  t.deepEqual(options.orientation, { gain: 0.1 })
+  t.is(options.streamTypes.orientation, 'Orientation')
+  t.is(device.orientation, null)
+})
+
+test('MuseManager indexes devices and suffixes their source_ids', async (t) => {
+  const manager = new MuseManager()
+  t.is(manager.addDevice({}), 0)
+  t.is(manager.addDevice({ bleUuid: 'AA:BB' }), 1)
+  t.like(manager.devices(), [
+    { index: 0, lifecycle: 'idle', sourceIdSuffix: '-0' },
+    { index: 1, lifecycle: 'idle', sourceIdSuffix: '-1' },
+  ])
+  await t.throwsAsync(() => manager.startStreaming(2), { message: 'No device at index 2' })
+  await manager.shutdown()
+})
+
+test('checkEnvironment reports adapters and LSL without a device', async (t) => {
+  const report = await MuseDevice.checkEnvironment()
+  t.is(typeof report.lslAvailable, 'boolean')
+  t.is(report.bleAdapterPresent, report.adapterCount > 0)
 })
 
 test('MuseDevice connect attempt (will fail without real device)', async (t) => {
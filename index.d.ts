@@ -8,18 +8,1143 @@ export interface DeviceAdapterOptions {
   rssiIntervalMs?: number
   /** If present, this will record the XDF to this path */
   xdfRecordPath?: string
+  /**
+   * Moving-average window (in samples) applied to each PPG channel before
+   * pushing to LSL. The outlet still advertises 64 Hz. Defaults to no smoothing
+   */
+  ppgSmoothingWindow?: number
+  /**
+   * Subtract the ambient reading from the infrared and red PPG channels to
+   * reduce ambient-light interference, pushing IR-ambient and RED-ambient.
+   * Defaults to false (raw values)
+   */
+  ppgSubtractAmbient?: boolean
+  /**
+   * With `ppgSubtractAmbient`, leave the ambient channel out so the PPG
+   * outlet and `onPpg` carry only the two corrected channels. Defaults to false
+   */
+  ppgDropAmbient?: boolean
+  /**
+   * Bytes to skip at the start of each EEG packet. Defaults to 2 (the
+   * sequence counter); falls back to 2 if larger than the packet
+   */
+  eegHeaderBytes?: number
+  /**
+   * Bytes to skip at the start of each PPG packet. Defaults to 2 (the
+   * sequence counter); falls back to 2 if larger than the packet
+   */
+  ppgHeaderBytes?: number
+  /**
+   * If present, sends the Muse keep-alive command (`k`) at this interval
+   * while connected so idle links aren't dropped by the platform
+   */
+  keepaliveIntervalMs?: number
+  /**
+   * Extra attempts at creating the LSL outlets (500 ms apart) before
+   * `startStreaming` fails. Defaults to 2
+   */
+  lslOutletRetries?: number
+  /**
+   * Deviation (in percent) of the measured EEG rate from 256 Hz that fires
+   * `onRateWarning`. Defaults to 10
+   */
+  rateWarningPct?: number
+  /**
+   * Sign-extend the 24-bit PPG values (so 0xFFFFFF is -1) instead of
+   * treating them as unsigned. Defaults to false
+   */
+  ppgSigned?: boolean
+  /**
+   * Restore the Muse's factory preset on `disconnect` by sending halt (`h`)
+   * then `p21`, so the next user doesn't inherit a custom preset. Write
+   * failures are ignored and the link is still closed. Defaults to false
+   */
+  resetOnDisconnect?: boolean
+  /**
+   * Keep the AUX electrode as the 5th EEG channel. When false the EEG
+   * outlet and `onEeg` carry only TP9, AF7, AF8, TP10. Defaults to true
+   */
+  includeAux?: boolean
+  /**
+   * Append a `SampleIndex` channel to the EEG and PPG outputs (LSL, UDP
+   * and JS) counting samples from the start of the stream. Samples lost
+   * in transit still use up their indices, so a jump of more than 1 is a
+   * gap. Wraps at 2^24 to stay exact as float32. Defaults to false
+   */
+  includeSampleIndex?: boolean
+  /**
+   * Push each BLE chunk (12 EEG / 6 PPG samples) to LSL at once, stamped
+   * with its last sample's time so liblsl deduces the rest at the nominal
+   * rate. False pushes each sample with its own timestamp. PPG decimated
+   * by `ppgOutputRate` is always pushed per sample. Defaults to true
+   */
+  chunkedPush?: boolean
+  /**
+   * How long after `startStreaming` an EEG channel may go without a single
+   * packet before `onChannelSilent` reports it. Defaults to 5000
+   */
+  silentChannelTimeoutMs?: number
+  /**
+   * Largest absolute EEG value pushed, in the decoder's raw counts (0-255
+   * before filtering); beyond it values are clamped to the limit and
+   * counted in `StreamStats.clampedEegValues`. Applies to the values as
+   * pushed to the EEG outlet. Defaults to no clamping
+   */
+  clampCounts?: number
+  /** Replace out-of-range EEG values with NaN instead of the limit */
+  clampToNan?: boolean
+  /**
+   * EEG packet loss (in percent, measured over 5 s windows) above which the
+   * BLE link is dropped and re-established while the outlets stay open.
+   * `onLossReconnect` fires first; relinks are at least 60 s apart.
+   * Defaults to never reconnecting
+   */
+  reconnectOnLossPct?: number
+  /**
+   * Open a "Markers" outlet with the streams and push `stream_start` when
+   * streaming starts and `stream_stop` just before the outlets close, so
+   * recordings have unambiguous session boundaries. Defaults to false
+   */
+  bracketMarkers?: boolean
+  /**
+   * Holds each sample this long after its timestamp before pushing to LSL,
+   * so BLE bursts go out at their nominal spacing and stalls shorter than
+   * this don't show up as gaps. Adds exactly this much latency, which
+   * doesn't grow if a stall persists. Defaults to 0 (push immediately)
+   */
+  lslJitterBufferMs?: number
+  /**
+   * EEG samples aggregated per `onEegChunk` call. The default 12 (one BLE
+   * packet, ~47 ms) has the lowest latency; larger values such as 256 (1 s)
+   * cost fewer JS calls but delay each sample by up to that long
+   */
+  jsChunkSamples?: number
+  /**
+   * Clock for the timestamps passed to `onSample` and `onEegChunk`: "lsl"
+   * (`local_clock()`, the default), "unix" (seconds since the epoch) or
+   * "monotonic" (seconds since the stream started). The LSL outlets always
+   * use LSL time
+   */
+  timestampSource?: string
+  /**
+   * Give the JS callbacks (`onEeg`, `onPpg`, `onSample`, `onEegChunk`) their
+   * own filter chain, run on a copy of each raw sample, instead of the
+   * output of the LSL chain set by the options above. Settings left out
+   * are off. Costs one extra 3-5 float copy per sample and a second set of
+   * filter state (e.g. the smoothing window). Defaults to sharing the LSL chain
+   */
+  jsProcessing?: ProcessingOptions
+  /**
+   * Replace the receipt-based sample timestamps, which bunch up with BLE
+   * bursts, with ones fitted to the regular sampling clock (a regression of
+   * timestamp on sample index over roughly the last 30 s). Used for LSL,
+   * the JS callbacks and UDP; the fitted EEG rate is reported as
+   * `StreamStats.dejitteredEegRate`. Defaults to false
+   */
+  dejitterTimestamps?: boolean
+  /**
+   * `host:port` to also send each sample to as one UDP datagram, for
+   * low-latency consumers on a LAN that can tolerate loss. The layout, all
+   * little-endian: a kind byte (0 = EEG, 1 = PPG), the LSL timestamp as an
+   * f64, then the channels as f32s, as pushed to LSL (so the count is
+   * `(length - 9) / 4`). The socket is closed when streaming stops
+   */
+  udpTarget?: string
+  /**
+   * Info string of the BLE adapter to scan and connect through, as listed
+   * by `listDevices` and `checkEnvironment`. Defaults to the first adapter
+   * that isn't powered off and can start a scan
+   */
+  adapter?: string
+  /**
+   * Weakest signal (in dBm, e.g. -70) a Muse may have for `connect` to
+   * pick it. If only weaker ones are found, `connect` fails with an error
+   * suggesting moving closer. Skipped, with a logged note, where the
+   * platform doesn't report RSSI. Defaults to accepting any signal
+   */
+  minRssi?: number
+  /**
+   * How long `connect` waits, re-discovering services, for the Muse
+   * characteristics to appear. Some platforms briefly report an empty or
+   * incomplete service list (or fail discovery) right after connecting; each
+   * retry is logged on stderr. If the characteristics never show up,
+   * `connect` fails rather than leaving a device that can't stream.
+   * Defaults to 3000
+   */
+  serviceReadyMs?: number
+  /**
+   * Scan for every BLE device and pick Muses by name alone, instead of
+   * filtering on the Muse service UUID. Needed for units whose
+   * advertisements leave the service out (it only shows up after
+   * connecting), which the filtered scan can't see on macOS/iOS
+   * (CoreBluetooth filters on advertised services) and on some BlueZ
+   * setups. Slower and noisier in busy environments. Defaults to false
+   */
+  scanAll?: boolean
+  /**
+   * EEG channels, by label (e.g. `["EEG_AUX"]`), that skip the filter
+   * chains (currently the `clampCounts` clamp) and are passed on raw,
+   * e.g. when the AUX port carries a custom sensor. Each label must be one
+   * of `eegChannels`, or the constructor throws. Defaults to none
+   */
+  filterExcludeChannels?: Array<string>
+  /**
+   * Disconnect automatically once the device has been connected but not
+   * streaming for this long, e.g. to free a shared headband and save its
+   * battery. Streaming, `stopStreaming` and `ping` restart the countdown.
+   * Fires `onAutoDisconnect` with "idle". Defaults to off
+   */
+  idleDisconnectMs?: number
+  /**
+   * PPG rate (in Hz) to stream at, for bandwidth-constrained setups. The
+   * 64 Hz PPG is low-pass filtered below the new Nyquist frequency and
+   * decimated, and the PPG outlet advertises the reduced rate. Must divide
+   * 64 evenly (32, 16, ...), or the constructor throws. `ppgSmoothingWindow`
+   * then counts samples at the reduced rate. Defaults to 64
+   */
+  ppgOutputRate?: number
+  /**
+   * Pause (in ms) between the commands `startStreaming` sends to start
+   * the device. The commands are written without response, and sent
+   * back-to-back some platforms' BLE controllers silently drop one, leaving
+   * a connected device that never streams. Raise it if that still happens,
+   * 0 sends them back-to-back. Defaults to 20
+   */
+  controlCommandGapMs?: number
+  /**
+   * How often (in ms) `connect` checks the scan results for a Muse. Slower
+   * polling costs less CPU but can add up to this much to connecting.
+   * There's no active/passive choice: btleplug always scans actively on
+   * Windows and leaves it to BlueZ and CoreBluetooth, which scan actively
+   * by default. Defaults to 100
+   */
+  scanPollIntervalMs?: number
+  /**
+   * Measure how long each BLE chunk's last sample takes from receipt to
+   * just before its LSL push, for `getLatencyStats`. This is the crate's
+   * own processing delay (decoding, filters, queueing to the LSL thread),
+   * not the BLE link's or the consumer's, and it includes all of
+   * `lslJitterBufferMs`. Costs a clock read and a lock per chunk.
+   * Defaults to false
+   */
+  measureLatency?: boolean
+  /**
+   * Also open a 32 Hz EEG outlet ("Muse S Gen 2 EEG Preview", type
+   * "EEGPreview") for live plots, so a UI can subscribe to it cheaply
+   * while a recorder takes the full-rate "EEG" outlet. It carries the raw
+   * EEG, low-pass filtered and decimated, before `clampCounts` and
+   * without the sample index. The filter smooths and slightly delays the
+   * signal: it's for visualization only, not analysis. Defaults to false
+   */
+  previewOutlet?: boolean
+  /**
+   * Sample format of the EEG outlet: "float32" (default) or "int16", which
+   * halves its bandwidth. int16 samples are 1/32 of a raw EEG count,
+   * spanning ±1024; larger values saturate. Each channel's `scale` in the
+   * StreamInfo gives the raw counts per int16 count. The PPG outlet stays
+   * float32. Can't be combined with `includeSampleIndex`
+   */
+  channelFormat?: string
+  /**
+   * Let `startStreaming` connect first (as `connect()` would, with
+   * `bleUuid` and the other options) when the device isn't connected,
+   * instead of throwing "Device not connected". Defaults to false
+   */
+  autoConnectOnStream?: boolean
+  /**
+   * Judge the raw EEG in fixed-length epochs, aligned to the first sample
+   * of the stream, and flag each one clean or not on a "Muse S Gen 2
+   * Epoch Quality" outlet (1 = clean, 0 = artifact, stamped with the
+   * epoch's first sample) and through `onEpochQuality`. An epoch has an
+   * artifact when any of TP9, AF7, AF8 and TP10 exceeds the range or step
+   * threshold; AUX is ignored. Unlike `getSignalQuality` this is meant for
+   * rejecting analysis windows. Off by default
+   */
+  epochQuality?: EpochQualityOptions
+  /**
+   * Content types to give the outlets instead of their defaults, which
+   * recorders and viewers use to categorize streams. A type outside the
+   * XDF conventions ("EEG", "PPG", "Markers", "Accelerometer", ...) is
+   * used anyway, with a warning on stderr
+   */
+  streamTypes?: StreamTypeOptions
+  /**
+   * Stream in bursts for low duty-cycle logging: `onMs` of data every
+   * `everyMs`, with the device halted in between. The BLE link stays up
+   * while resting, so this saves the sensors' and notifications' power
+   * but not the connection's. The outlets stay open across rests, with
+   * `burst_start`/`burst_stop` markers (see `bracketMarkers`) around each
+   * burst; rate warnings are off. Anything computed over time (signal
+   * quality, epochs, dejittered timestamps) sees the gaps, so it isn't
+   * continuous. `onMs` must be at least `silentChannelTimeoutMs`. Off by
+   * default
+   */
+  burst?: BurstOptions
+  /**
+   * Subscribe to the accelerometer and gyroscope and push them to their
+   * own outlets ("Accelerometer" in g and "Gyroscope" in deg/s, X, Y, Z
+   * at 52 Hz), e.g. to regress head movement out of the EEG offline. The
+   * samples go to LSL only, not to the JS callbacks. Defaults to false
+   */
+  imu?: boolean
+  /**
+   * Fuse the accelerometer and gyroscope into head orientation, pushed as
+   * a unit quaternion (W, X, Y, Z) per gyroscope sample to a "Muse S Gen 2
+   * Orientation" outlet and kept for the `orientation` getter. It's an
+   * estimate, not ground truth: it starts level and takes a few seconds
+   * (longer with a lower `gain`) to settle on the head's tilt, and with no
+   * magnetometer the heading is relative to the start and drifts. Needs
+   * `imu`. Off by default
+   */
+  orientation?: OrientationOptions
+  /**
+   * How long `startStreaming` looks on the network for an outlet already
+   * using this device's EEG source_id (e.g. a second headband run without
+   * distinct ones), which LSL consumers would mix up with this one. A
+   * conflict is logged and reported to `onSourceIdConflict`. When there's
+   * none the whole wait is spent, delaying the start, so it's opt-in: 500
+   * is usually enough on a LAN. Defaults to 0, which skips the check
+   */
+  sourceIdCheckMs?: number
+  /**
+   * When the source_id check finds a conflict, append the headband's name
+   * to every outlet's source_id for this stream instead of keeping the
+   * conflicting ones, as `getStreamInfo` then shows.
+   * Defaults to false, since consumers may be looking for the usual ids
+   */
+  disambiguateSourceIds?: boolean
+}
+/** Filters for one sink, as in `DeviceAdapterOptions` */
+export interface ProcessingOptions {
+  ppgSmoothingWindow?: number
+  ppgSubtractAmbient?: boolean
+  ppgDropAmbient?: boolean
+  clampCounts?: number
+  clampToNan?: boolean
+}
+/** One sample from any sensor, as delivered to `onSample` */
+export interface Sample {
+  /**
+   * `"eeg"` (5 channels, 4 without AUX), `"ppg"` (3 channels, 2 with
+   * `ppgDropAmbient`), or with `imu` on `"accel"` (x/y/z in g) and
+   * `"gyro"` (x/y/z in deg/s)
+   */
+  kind: string
+  timestamp: number
+  channels: Array<number>
+}
+/** EEG samples aggregated for `onEegChunk`, oldest first */
+export interface EegChunk {
+  timestamps: Array<number>
+  samples: Array<Array<number>>
+}
+/** Counters for the current streaming session, reset by `startStreaming` */
+export interface StreamStats {
+  eegSamples: number
+  ppgSamples: number
+  /** EEG rate measured over the last monitoring window, 0 until the first window completes */
+  measuredEegRate: number
+  /** EEG rate estimated by `dejitterTimestamps`, 0 when that's off */
+  dejitteredEegRate: number
+  /** Windows where the measured EEG rate deviated beyond `rateWarningPct` */
+  rateWarnings: number
+  /** Latest EEG timestamp minus latest PPG timestamp, in milliseconds */
+  eegPpgOffsetMs?: number
+  /** EEG channels that sent no packets within `silentChannelTimeoutMs` */
+  silentEegChannels: Array<string>
+  /** EEG values that exceeded `clampCounts` */
+  clampedEegValues: number
+  /** EEG packet loss (in percent) over the last 5 s window, from the sequence counters */
+  packetLossPct: number
+  /** Packets dropped for repeating the previous packet's sequence number */
+  duplicatePackets: number
+  /** Times the link was re-established because of `reconnectOnLossPct` */
+  lossReconnects: number
+  /** Notifications from characteristics the crate doesn't decode */
+  unhandledNotifications: number
+  /** Times the start commands were resent because no EEG arrived at all */
+  resyncs: number
+  /**
+   * Packets dropped because they couldn't be decoded, e.g. a PPG payload
+   * that isn't whole 24-bit samples, pointing at a wrong header size
+   */
+  malformedPackets: number
+}
+/**
+ * Approximate task/thread counts for debugging the streaming lifecycle.
+ * Each stream gets its own LSL thread rather than sharing a runtime
+ */
+export interface RuntimeStats {
+  /** LSL threads plus BLE notification tasks still running */
+  activeStreamingTasks: number
+  /** Should be at most 1; more means an earlier stream didn't shut down */
+  lslThreads: number
+  notificationTaskRunning: boolean
+  keepaliveRunning: boolean
+}
+/** A notification from a characteristic the crate doesn't decode */
+export interface UnhandledNotification {
+  uuid: string
+  data: Array<number>
+}
+/** The verdict on one EEG epoch, as delivered to `onEpochQuality` */
+export interface EpochQuality {
+  /** When the epoch's first sample was taken */
+  timestamp: number
+  clean: boolean
+  /** Labels of the channels that broke a threshold, empty when clean */
+  artifactChannels: Array<string>
+}
+/**
+ * The latest reading from the telemetry characteristic, which the Muse
+ * sends every few seconds while streaming
+ */
+export interface Telemetry {
+  batteryPercent: number
+  fuelGaugeMillivolts: number
+  /** Undocumented ADC reading, in raw counts */
+  adcVoltage: number
+  /** In raw counts, the unit isn't documented */
+  temperature: number
+}
+/**
+ * An outlet found on the network with the source_id this device's EEG
+ * outlet was about to use, as delivered to `onSourceIdConflict`
+ */
+export interface SourceIdConflict {
+  sourceId: string
+  /** Host the other outlet runs on */
+  hostname: string
+  /**
+   * The source_id this device's EEG outlet uses instead, with
+   * `disambiguateSourceIds`; undefined when it keeps the conflicting one
+   */
+  renamedTo?: string
+}
+/**
+ * One step of a `SampleStream`, shaped like the async iterator protocol's
+ * results: `value` is undefined once `done`
+ */
+export interface SampleIteration {
+  done: boolean
+  value?: Sample
+}
+export interface RateWarning {
+  measuredRate: number
+  nominalRate: number
+  deviationPct: number
+}
+/** A channel as listed in an outlet's `<channels>` metadata */
+export interface ChannelDescription {
+  label: string
+  unit: string
+  channelType: string
+  /**
+   * 10-20 position of an EEG electrode (e.g. "TP9"), null for AUX and
+   * non-EEG channels
+   */
+  location?: string
+}
+/** The metadata an outlet's StreamInfo is built from */
+export interface StreamDescription {
+  name: string
+  streamType: string
+  sourceId: string
+  nominalRate: number
+  /** Samples per chunk the outlet transmits */
+  chunkSize: number
+  manufacturer: string
+  model: string
+  channels: Array<ChannelDescription>
+  /** Channel units per count when samples are pushed as int16, null for floats */
+  scale?: number
+}
+/** Duty cycle for `burst` */
+export interface BurstOptions {
+  /** How long each burst streams, defaults to 10000 */
+  onMs?: number
+  /** Time from the start of one burst to the next, defaults to 60000 */
+  everyMs?: number
+}
+/** Content type of each outlet, see `streamTypes` */
+export interface StreamTypeOptions {
+  /** Defaults to "EEG" */
+  eeg?: string
+  /** Defaults to "PPG" */
+  ppg?: string
+  /** Defaults to "Markers" */
+  markers?: string
+  /** Defaults to "EEGPreview", so it isn't mistaken for the full-rate EEG */
+  preview?: string
+  /** Defaults to "EpochQuality" */
+  epochQuality?: string
+  /** Defaults to "Accelerometer" */
+  accelerometer?: string
+  /** Defaults to "Gyroscope" */
+  gyroscope?: string
+  /** Defaults to "Orientation" */
+  orientation?: string
+}
+/** Buffering of a `sampleStream` */
+export interface SampleStreamOptions {
+  /** Only samples of this kind, `"eeg"`, `"ppg"`, `"accel"` or `"gyro"`; all by default */
+  kind?: string
+  /** Samples held for a reader that falls behind, defaults to 1024 */
+  bufferSamples?: number
+  /**
+   * What a full buffer drops: `"dropOldest"` (the default) keeps the
+   * freshest data, `"dropNewest"` keeps a gapless start
+   */
+  overflow?: string
+}
+/** Thresholds for `epochQuality` */
+export interface EpochQualityOptions {
+  /** Epoch length, defaults to 1000 */
+  epochMs?: number
+  /**
+   * Largest peak-to-peak range (raw counts) a clean epoch may have on a
+   * channel, defaults to 150
+   */
+  maxRangeCounts?: number
+  /**
+   * Largest change (raw counts) between consecutive samples a clean epoch
+   * may have on a channel, defaults to 50
+   */
+  maxStepCounts?: number
+}
+/** Tuning for `orientation` */
+export interface OrientationOptions {
+  /**
+   * How hard each step is pulled toward the accelerometer's gravity, in
+   * rad/s: higher settles faster but lets head acceleration tilt the
+   * estimate more. Defaults to 0.1
+   */
+  gain?: number
+}
+export interface ProcessingConfig {
+  /** Filters applied before pushing to LSL */
+  lsl: ProcessingOptions
+  /**
+   * The JS callbacks' own chain (`jsProcessing`), or null when they get
+   * the `lsl` chain's output
+   */
+  js?: ProcessingOptions
+  includeAux: boolean
+  includeSampleIndex: boolean
+  filterExcludeChannels: Array<string>
+  ppgOutputRate: number
+}
+/** A Muse found by `listDevices` */
+export interface DiscoveredDevice {
+  name: string
+  /** Pass as `bleUuid` to connect to this headband */
+  uuid: string
+  /** Pass as `adapter` to connect through the adapter that saw it */
+  adapter: string
+  rssi?: number
+  /** Pass to `connect` within 60 s to connect without scanning again */
+  handle: string
+}
+/** The result of `getEffectiveOptions` */
+export interface EffectiveOptions {
+  options: DeviceAdapterOptions
+  processing: ProcessingConfig
+}
+export interface EnvironmentReport {
+  bleAdapterPresent: boolean
+  /** Info string of the adapter that `connect` will use */
+  bleAdapterName?: string
+  lslAvailable: boolean
+  adapterCount: number
+}
+export interface GattService {
+  uuid: string
+  primary: boolean
+  characteristics: Array<GattCharacteristic>
+}
+export interface GattCharacteristic {
+  uuid: string
+  read: boolean
+  write: boolean
+  writeWithoutResponse: boolean
+  notify: boolean
+  indicate: boolean
+}
+/**
+ * The device's lifecycle state: "idle", "scanning", "connecting",
+ * "connected", "streaming", "paused" (restarting on a new preset),
+ * "reconnecting" (relinking after packet loss or a dropped notification
+ * stream), "disconnecting" or "error"
+ */
+export interface Lifecycle {
+  state: string
+  lastError?: string
+}
+/** What `runDiagnostics` found over its run */
+export interface DiagnosticsReport {
+  /** How long the stream actually ran */
+  durationMs: number
+  eegSamples: number
+  /** EEG samples received per second of the run, nominally 256 */
+  measuredEegRate: number
+  /** EEG packet loss over the last complete 5 s window */
+  packetLossPct: number
+  channels: Array<ChannelDiagnostics>
+}
+/** One EEG channel in a `DiagnosticsReport`, in the decoder's raw units */
+export interface ChannelDiagnostics {
+  label: string
+  /** Mean value over the run */
+  dcOffset: number
+  /** RMS around the mean; flat near 0 or very large points at poor contact */
+  rmsNoise: number
+  /** Samples at the smallest or largest value the decoder produces */
+  saturatedSamples: number
+}
+/** What `getSignalQuality` found in the last second of EEG */
+export interface SignalQuality {
+  /** EEG samples the metrics cover, one second's worth */
+  windowSamples: number
+  channels: Array<ChannelQuality>
+}
+/** One EEG channel in a `SignalQuality`, in the decoder's raw units */
+export interface ChannelQuality {
+  label: string
+  /** "ok", "flat" or "saturated" */
+  quality: string
+  dcOffset: number
+  /** Variance around the mean, i.e. `rmsNoise` squared */
+  variance: number
+  rmsNoise: number
+  saturatedSamples: number
+}
+/** An external clock reading paired with LSL time by `pushSyncPulse` */
+export interface SyncPulse {
+  /** The time passed to `pushSyncPulse`, in the external clock's units */
+  externalTime: number
+  /** `local_clock()` when the pulse was recorded, in seconds */
+  localTime: number
+}
+/** Receipt-to-push latency percentiles from `getLatencyStats` */
+export interface LatencyStats {
+  /** Chunks measured */
+  samples: number
+  minMs: number
+  medianMs: number
+  p99Ms: number
+  maxMs: number
+}
+export interface PacketSizes {
+  /** Negotiated ATT MTU, null where the platform doesn't report it */
+  mtu?: number
+  /** Expected size of each EEG notification */
+  eegBytes: number
+  /** Expected size of each PPG notification */
+  ppgBytes: number
+}
+export interface OutletUids {
+  eeg: string
+  ppg: string
+}
+export interface ManagedDevice {
+  index: number
+  /** Null until the device has connected */
+  name?: string
+  uuid?: string
+  lifecycle: string
+  /** What the device's outlets' source_ids end in */
+  sourceIdSuffix: string
 }
 export declare class MuseDevice {
+  /**
+   * The device is also halted and disconnected when the Node environment
+   * is torn down (see `shutdown`)
+   */
   constructor(options: DeviceAdapterOptions)
-  connect(): Promise<void>
+  /** Preflight check of the BLE adapter and liblsl, no device required */
+  static checkEnvironment(): Promise<EnvironmentReport>
+  /**
+   * Scans on every BLE adapter at once for `scanMs` (default 3000) and lists
+   * the Muses each one sees; a headband in range of several adapters is
+   * listed once per adapter. To run several headbands from one process,
+   * create one `MuseDevice` per headband with its `adapter` and `bleUuid`
+   * set: each device scans and connects only through its own adapter, so
+   * they don't contend for one radio. `scanAll` picks Muses by name alone,
+   * as the `scanAll` option does for `connect`
+   */
+  static listDevices(scanMs?: number | undefined | null, scanAll?: boolean | undefined | null): Promise<Array<DiscoveredDevice>>
+  /**
+   * The Muse service and characteristic UUIDs the crate uses, keyed by
+   * name (e.g. `control`, `eeg_tp9`, `ppg_red`)
+   */
+  static knownUuids(): Record<string, string>
+  /**
+   * The bytes a control command is written as (`X{cmd}\n`, with the first
+   * byte replaced by the frame's length - 1), without sending anything
+   * @throws if the command is empty, not printable ASCII or over 18 bytes
+   */
+  static encodeCommand(cmd: string): Buffer
+  /**
+   * With a `handle` from `listDevices`, connects straight to that
+   * headband without scanning again, ignoring `bleUuid` and `minRssi`.
+   * Handles last 60 s; an expired or unknown handle throws, as does a
+   * headband that has since gone out of range
+   * @throws if already connected, or when no Muse is found or the
+   * connection fails (the lifecycle then moves to "error")
+   */
+  connect(handle?: string | undefined | null): Promise<void>
+  /**
+   * With `autoConnectOnStream`, connects first when not connected
+   * @throws if not connected, or with "Auto-connect failed: ..." when the
+   * implicit connect fails (the lifecycle then moves to "error")
+   */
   startStreaming(): Promise<void>
   stopStreaming(): Promise<void>
   restartStreaming(): Promise<void>
+  /**
+   * Switches a Muse S to its sleep preset. When already streaming the
+   * device is restarted on the new preset and the outlets stay open
+   * @throws if not connected or the device has no sleep preset
+   */
+  enableSleepMode(): Promise<void>
+  /**
+   * Median round-trip time in milliseconds of the status command (`s`),
+   * measured to the first fragment of each response over 5 runs
+   * @throws if not connected or the device doesn't answer within 2 s
+   */
+  measureCommandLatency(): Promise<number>
+  /**
+   * Checks the link by sending a status command, resolving whether the
+   * device answered within 2 s. Safe to call while streaming. Also
+   * restarts the `idleDisconnectMs` countdown
+   * @throws if not connected or the command can't be written
+   */
+  ping(): Promise<boolean>
+  /**
+   * Brings a device that stopped following its commands (e.g. streaming
+   * the wrong preset after a lost write) back to a known configuration
+   * without disconnecting: halts it, re-applies the current preset,
+   * checks that it answers a status query, and restarts streaming if it
+   * was streaming. The start commands are also resent automatically,
+   * counted in `StreamStats.resyncs`, when no EEG at all arrives within
+   * `silentChannelTimeoutMs` of starting a stream
+   * @throws if not connected or the device doesn't answer
+   */
+  resync(): Promise<void>
+  /**
+   * Recovers a stream whose device side is stuck while keeping the LSL
+   * outlets (and their uids) open, so consumers stay attached and only
+   * see a short gap in the data: the EEG and PPG subscriptions are
+   * renewed and the start commands resent. Unlike `resync` it doesn't wait
+   * for the device to answer, and unlike `restartStreaming` the outlets
+   * aren't recreated
+   * @throws if not streaming or a command can't be written
+   */
+  softRestart(): Promise<void>
+  /**
+   * Asks the device for its hardware revision (e.g. "4.4"), which is
+   * distinct from the firmware version and tells apart headbands that
+   * behave differently. Resolves null if the firmware doesn't report one.
+   * The result is kept for `hardwareRevision` until disconnect
+   * @throws if not connected or no response arrives
+   */
+  readHardwareRevision(): Promise<string | null>
+  /**
+   * Polls the battery every 5 s until it reaches `minPercent`, resolving
+   * false if `timeoutMs` passes first. Useful for rigs that charge between
+   * subjects
+   * @throws if not connected or the battery can't be read
+   */
+  waitForBattery(minPercent: number, timeoutMs: number): Promise<boolean>
+  /**
+   * Headband health check: streams for `durationMs`, then stops and
+   * reports per-channel DC offset, RMS noise and saturation of the raw EEG,
+   * with the measured rate and packet loss. The outlets are only open for
+   * the run. Loss is measured over 5 s windows, so shorter runs report 0
+   * @throws if not connected, already streaming, or streaming fails to start
+   */
+  runDiagnostics(durationMs: number): Promise<DiagnosticsReport>
+  /**
+   * Per-channel signal quality over the last complete second of raw EEG,
+   * with the metrics behind each classification so tooling can apply its
+   * own thresholds. A channel is "saturated" when over 5% of its samples
+   * hit the decoder's rails, "flat" when its RMS noise is under 0.5, and
+   * "ok" otherwise. Null during the first second of a stream
+   * @throws if not streaming
+   */
+  getSignalQuality(): SignalQuality | null
+  /**
+   * Correlates a reading of an external clock (e.g. a lab-wide time-sync
+   * source, or a GPIO pulse relayed through JS) with LSL's `local_clock()`,
+   * for aligning devices after the fact. Call it as close to the external
+   * event as possible: the pairing is only as accurate as the delay between
+   * the event and this call, typically well under a millisecond but more
+   * when the event loop is busy, so fit a line through many pulses rather
+   * than trusting any single one. The last 100 pulses are kept in
+   * `syncPulses`, and while streaming with `bracketMarkers` each is also
+   * pushed to the markers outlet as `sync_pulse <externalTime>`, stamped
+   * with its LSL time
+   */
+  pushSyncPulse(externalTime: number): SyncPulse
+  /** The most recent `pushSyncPulse` correlations, oldest first */
+  get syncPulses(): Array<SyncPulse>
+  /**
+   * Receipt-to-push latency over the last 4096 chunks of the current (or
+   * last) stream, with `measureLatency` on. The median is the typical
+   * processing cost; p99 and max show stalls, e.g. from the event loop or
+   * heavy filtering, and a p99 near the jitter buffer delay is expected
+   * when it's on. Null when `measureLatency` is off or nothing has been
+   * pushed yet
+   */
+  getLatencyStats(): LatencyStats | null
+  /**
+   * Switches the device preset (`p20`, `p21`, `p50`, `p51`, `p1034`,
+   * `p1035`). While streaming the device is restarted on the new preset and
+   * the outlets stay open, so EEG continues across the switch.
+   *
+   * Only presets streaming the same sensors switch live (p50, p1034 and
+   * p1035 among themselves). Turning PPG (p50/p51 vs p20/p21) or AUX on or
+   * off changes what the subscriptions and outlets carry, so it needs
+   * `stopStreaming` first; the outlets' channel layout itself only changes
+   * with `includeAux`
+   * @throws if not connected, the preset is unknown, or it streams other
+   * sensors than the current one while streaming
+   */
+  setPreset(preset: string): Promise<void>
+  /**
+   * Lists the discovered GATT services and characteristics with their flags
+   * @throws if its not connected
+   */
+  dumpGatt(): Promise<Array<GattService>>
+  /**
+   * Reads a characteristic's current value with a GATT read, for one-shot
+   * values (device info, telemetry) that aren't worth subscribing to.
+   * `uuid` is any characteristic from `dumpGatt`, e.g. from `knownUuids`
+   * @throws if `uuid` isn't a UUID, not connected, or the characteristic
+   * is missing or not readable
+   */
+  readCharacteristic(uuid: string): Promise<Buffer>
+  /**
+   * Called with each EEG sample (TP9, AF7, AF8, TP10, AUX) as it's pushed
+   * to LSL. AUX is left out when `includeAux` is false, and `includeSampleIndex`
+   * appends the sample index
+   */
+  onEeg(callback: (sample: number[]) => void): void
+  /** Called with each PPG sample (AMBIENT, INFRARED, RED) as it's pushed to LSL */
+  onPpg(callback: (sample: number[]) => void): void
+  /**
+   * Called with `jsChunkSamples` EEG samples at a time. Whatever is left
+   * when streaming stops is delivered as a final, shorter chunk
+   */
+  onEegChunk(callback: (chunk: EegChunk) => void): void
+  offEegChunk(): void
+  /**
+   * Called with every sample from every sensor, tagged with its `kind`.
+   * The call rate is the sum of the sensor rates (256 Hz EEG + 64 Hz PPG,
+   * plus 52 Hz each of accel and gyro with `imu` on), and samples of
+   * different kinds interleave in chunk-sized bursts, so filter on `kind`
+   */
+  onSample(callback: (sample: Sample) => void): void
+  offSample(): void
+  /**
+   * The newest sample of `kind` ("eeg", "ppg", or with `imu` on "accel"
+   * and "gyro") that `onSample` got in the current or last stream, for UIs
+   * that draw on their own clock rather than per sample. Null until one
+   * arrives in a stream
+   * @throws if `kind` isn't one of those
+   */
+  getLatestSample(kind: string): Sample | null
+  /**
+   * The newest head orientation from `orientation`, as a unit quaternion
+   * `[w, x, y, z]`, or null until the first gyroscope sample of a stream
+   */
+  get orientation(): Array<number> | null
+  /**
+   * The samples `onSample` gets (of one `kind` if set), pulled instead of
+   * pushed: `for await (const sample of device.sampleStream())`, or
+   * `Readable.from(device.sampleStream())` for an object-mode Readable.
+   * Samples wait in a buffer of `bufferSamples` until read; when a slow
+   * reader lets it fill up, `overflow` decides whether the oldest queued
+   * sample or the incoming one is dropped (counted in `dropped`), so the
+   * device is never held up. The stream ends once the current stream (or
+   * the next, if not streaming) stops, after what's buffered is read
+   * @throws if an option is invalid
+   */
+  sampleStream(options?: SampleStreamOptions | undefined | null): SampleStream
+  /**
+   * Called when the EEG rate measured over a 5 s window deviates from the
+   * nominal 256 Hz by more than `rateWarningPct`
+   */
+  onRateWarning(callback: (warning: RateWarning) => void): void
+  /**
+   * Called once per stream with the labels of the EEG channels that sent no
+   * packets at all within `silentChannelTimeoutMs`. That points at the
+   * preset or hardware, whereas bad contact still delivers (noisy) data
+   */
+  onChannelSilent(callback: (channels: string[]) => void): void
+  offChannelSilent(): void
+  /**
+   * Called with the measured packet loss (in percent) just before a
+   * `reconnectOnLossPct` relink. If the relink fails the stream stops and
+   * `lastError` says why
+   */
+  onLossReconnect(callback: (lossPct: number) => void): void
+  offLossReconnect(): void
+  /**
+   * Called with each notification from a characteristic the crate doesn't
+   * decode. They're otherwise only counted in
+   * `StreamStats.unhandledNotifications`
+   */
+  onUnhandledNotification(callback: (notification: UnhandledNotification) => void): void
+  offUnhandledNotification(): void
+  /** Called with the new lifecycle state (see `lifecycle`) on every transition */
+  onStateChange(callback: (state: string) => void): void
+  offStateChange(): void
+  /**
+   * Called when the EEG or PPG outlet gets its first LSL consumer (e.g. a
+   * recorder opens an inlet). Changes lasting under a second are ignored
+   */
+  onConsumerConnected(callback: () => void): void
+  offConsumerConnected(): void
+  /**
+   * Called when the last LSL consumer goes away, including when streaming
+   * stops while one is connected
+   */
+  onConsumerDisconnected(callback: () => void): void
+  offConsumerDisconnected(): void
+  /**
+   * Called with a message when the LSL side stops taking samples mid-stream.
+   * The device is halted and `isStreaming` turns false before this fires
+   */
+  onLslError(callback: (error: string) => void): void
+  offLslError(): void
+  offRateWarning(): void
+  offEeg(): void
+  offPpg(): void
+  /**
+   * Called with each PPG sample's raw 24-bit words (AMBIENT, INFRARED,
+   * RED) as received, for doing your own decoding. Each is the big-endian
+   * 24-bit value from the packet as an unsigned integer (0 to 0xFFFFFF),
+   * taken before `ppgSigned` sign extension and before any filtering or
+   * `ppgOutputRate` decimation, so it fires at 64 Hz
+   */
+  onPpgRaw(callback: (sample: number[]) => void): void
+  offPpgRaw(): void
+  /**
+   * Called when `startStreaming` finds another outlet with this device's
+   * EEG source_id, see `sourceIdCheckMs`
+   */
+  onSourceIdConflict(callback: (conflict: SourceIdConflict) => void): void
+  offSourceIdConflict(): void
+  /** Called with the verdict on each EEG epoch, see `epochQuality` */
+  onEpochQuality(callback: (epoch: EpochQuality) => void): void
+  offEpochQuality(): void
+  /**
+   * Removes every registered callback. Calls already queued are dropped,
+   * so none of them fire after this returns
+   */
+  clearCallbacks(): void
+  /**
+   * Stops one sink from receiving samples without affecting the BLE stream
+   * or the other sinks: "lsl" (the outlets stay open but get nothing), "js"
+   * (the sample callbacks) or "udp". Lasts until `resumeSink`, across streams
+   * @throws if the sink name is unknown
+   */
+  pauseSink(name: string): void
+  /** @throws if the sink name is unknown */
+  resumeSink(name: string): void
+  /** Names of the sinks paused by `pauseSink` */
+  get pausedSinks(): Array<string>
+  /** Names of the callbacks currently registered, e.g. `["eeg", "ppg"]` */
+  registeredCallbacks(): Array<string>
+  /**
+   * Halts and disconnects the device and removes every callback, so nothing
+   * is left streaming or calling into JS. Safe to call more than once and
+   * when not connected. Runs automatically on normal exit and
+   * `process.exit()`, but not when the process is killed
+   */
+  shutdown(): Promise<void>
+  /**
+   * Also ends a live stream without a `stopStreaming` call first, and
+   * like `stopStreaming` resolves only once its outlets are closed (with
+   * `bracketMarkers`, after the `stream_stop` marker has gone out)
+   */
   disconnect(): Promise<void>
+  /**
+   * Called with the reason when the device is disconnected without a
+   * `disconnect` call. Currently only "idle", see `idleDisconnectMs`. The
+   * lifecycle has already gone back to "idle" when this fires
+   */
+  onAutoDisconnect(callback: (reason: string) => void): void
+  offAutoDisconnect(): void
+  /**
+   * Packet sizes for diagnosing truncated notifications. btleplug doesn't
+   * expose the negotiated MTU on any platform, so `mtu` is null and the
+   * sizes are the ones the Muse sends, which fit the 23-byte default MTU
+   * @throws if its not connected
+   */
+  get packetSizes(): PacketSizes
   /** @throws if its not connected */
   get bleName(): string
   /** @throws if its not connected */
   get bleUuid(): string
   get isStreaming(): boolean
+  /** "sampling" or "resting" while streaming with `burst`, otherwise "off" */
+  get burstState(): string
+  /** True while `connect` is scanning for the headband */
+  get isScanning(): boolean
+  /**
+   * Milliseconds since a control command was last written successfully on
+   * this connection, or null if none has been. Compare with
+   * `lastSampleAgeMs`: a device that takes commands but sends no samples has
+   * a stalled data characteristic rather than a dead link
+   */
+  get lastCommandAgeMs(): number | null
+  /**
+   * Milliseconds since a sample was last pushed to LSL in this stream, or
+   * null if none has been
+   */
+  get lastSampleAgeMs(): number | null
   get isConnected(): boolean
+  /** Counters for the current (or last) streaming session */
+  getStats(): StreamStats
+  /**
+   * Counts of the threads and tasks behind streaming, for debugging leaks
+   * across start/stop cycles. Approximate: tasks may be finishing as it's read
+   */
+  runtimeStats(): Promise<RuntimeStats>
+  /**
+   * Latest EEG timestamp minus latest PPG timestamp in milliseconds, or null
+   * until both streams have samples. Both streams are stamped from the same
+   * BLE receipt clock, so this stays within one PPG chunk (~94 ms) as chunks
+   * complete; alignment of the timestamps themselves is bounded by the BLE
+   * connection interval jitter (typically within ~15 ms)
+   */
+  get eegPpgOffsetMs(): number | null
+  /**
+   * The filters and channel selection applied to the samples, for display
+   * or for storing alongside a recording. Fixed when the device is created
+   */
+  getProcessingConfig(): ProcessingConfig
+  /**
+   * Every option as resolved by the constructor, with the defaults filled
+   * in, alongside the processing config. Flags are always present; only
+   * optional values that weren't set and have no default (e.g.
+   * `keepaliveIntervalMs`, `idleDisconnectMs`, `udpTarget`) are left out.
+   * Options are fixed when the device is created, so this is what every
+   * connection and stream of this device uses
+   */
+  getEffectiveOptions(): EffectiveOptions
+  /**
+   * Metadata of the live EEG, PPG and (with `bracketMarkers`,
+   * `previewOutlet` and `epochQuality`) markers, preview and epoch quality
+   * outlets, the same values their StreamInfo was built from. Empty when
+   * not streaming
+   */
+  getStreamInfo(): Array<StreamDescription>
+  /** EEG channels reported silent in the current (or last) stream */
+  get silentChannels(): Array<string>
+  /**
+   * The uids of the live EEG/PPG outlets, or null when not streaming.
+   * liblsl assigns a new uid to every outlet instance, so integration tests
+   * should read them here rather than expecting a fixed value
+   */
+  get outletUids(): OutletUids | null
+  /**
+   * Whether LSL outlets currently exist. Turns false only once they've
+   * been destroyed, which `stopStreaming` waits for
+   */
+  get lslActive(): boolean
+  /** Labels of the EEG channels, in the order they're pushed */
+  get eegChannels(): Array<string>
+  /** Labels of the PPG channels, in the order they're pushed to LSL */
+  get ppgChannels(): Array<string>
+  get supportsSleepMode(): boolean
+  /**
+   * Battery charge in percent from the last telemetry packet. The Muse
+   * only sends telemetry while streaming (every few seconds); use
+   * `waitForBattery` to query it otherwise
+   * @throws "Telemetry not available" until a packet has arrived since
+   * connecting
+   */
+  get batteryLevel(): number
+  /** The last telemetry packet since connecting, or null before the first */
+  get telemetry(): Telemetry | null
+  /**
+   * Whether the headband is on its charger. Not supported: the telemetry
+   * packet has no charging flag and the battery charge is too jittery to
+   * tell, so this is always null. Use `telemetry.batteryPercent` instead
+   */
+  get isCharging(): boolean | null
+  /**
+   * The hardware revision from the last `readHardwareRevision`, or null
+   * if it hasn't been read since connecting
+   */
+  get hardwareRevision(): string | null
+  /**
+   * Why the last stream stopped on its own or the last connect failed,
+   * cleared by `connect` and `startStreaming`
+   */
+  get lastError(): string | null
+  /**
+   * Where the device is in its lifecycle, with the error that put it in
+   * the "error" state
+   */
+  get lifecycle(): Lifecycle
+}
+/**
+ * Runs several headbands from one object, addressing them by the index
+ * `addDevice` returned. Each device gets its own connection (give each its
+ * own `adapter` to spread them over several radios), and its outlets'
+ * source_ids end in `-<index>` so LSL consumers can tell them apart. Each
+ * device streams through its own LSL thread; there is no runtime shared
+ * between them to coordinate
+ */
+export declare class MuseManager {
+  constructor()
+  /** Same as `MuseDevice.listDevices`, to pick handles or `bleUuid`s from */
+  static discover(scanMs?: number | undefined | null, scanAll?: boolean | undefined | null): Promise<Array<DiscoveredDevice>>
+  /** Creates a device from `options` and returns its index */
+  addDevice(options: DeviceAdapterOptions): number
+  /** Every device added, in index order */
+  devices(): Array<ManagedDevice>
+  /** See `MuseDevice.connect` */
+  connect(index: number, handle?: string | undefined | null): Promise<void>
+  /**
+   * Connects every device that isn't connected yet, one at a time since
+   * devices sharing an adapter would otherwise scan over each other
+   * @throws on the first device that fails, naming its index
+   */
+  connectAll(): Promise<void>
+  startStreaming(index: number): Promise<void>
+  stopStreaming(index: number): Promise<void>
+  disconnect(index: number): Promise<void>
+  getStats(index: number): StreamStats
+  /**
+   * Shuts every device down (see `MuseDevice.shutdown`), carrying on past
+   * failures so one stuck headband doesn't keep the others streaming.
+   * Devices are also shut down when the Node environment is torn down
+   * @throws with the first failure once all have been tried
+   */
+  shutdown(): Promise<void>
+}
+/**
+ * The samples of one `sampleStream`, in order. It's an async iterable, so
+ * read it with `for await`, or wrap it in `Readable.from()` for an
+ * object-mode Node stream
+ */
+export declare class SampleStream {
+  /**
+   * Resolves with the next sample, waiting for one if none is queued, or
+   * with `done` once the stream has ended and been drained
+   */
+  next(): Promise<SampleIteration>
+  /**
+   * Ends the stream and drops whatever is queued. `for await` calls this
+   * when the loop is left early
+   */
+  return(): SampleIteration
+  /** Samples discarded because the reader fell `bufferSamples` behind */
+  get dropped(): number
+  [Symbol.asyncIterator](): SampleStream
 }
@@ -310,6 +310,8 @@ if (!nativeBinding) {
   throw new Error(`Failed to load native binding`)
 }
 
-const { MuseDevice } = nativeBinding
+const { MuseDevice, MuseManager, SampleStream } = nativeBinding
 
 module.exports.MuseDevice = MuseDevice
+module.exports.MuseManager = MuseManager
+module.exports.SampleStream = SampleStream
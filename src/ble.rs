@@ -1,11 +1,20 @@
-use btleplug::api::{Central, Characteristic, Manager as _, Peripheral, ScanFilter};
+use crate::device_state::Telemetry;
+use crate::processing::{LossMonitor, SequenceTracker};
+use btleplug::api::{
+  Central, CentralState, CharPropFlags, Characteristic, Manager as _, Peripheral, ScanFilter,
+  Service, ValueNotification,
+};
 use btleplug::platform::{Adapter, Manager, Peripheral as PlatformPeripheral};
-use futures::stream::StreamExt;
+use futures::stream::{Stream, StreamExt};
+use std::collections::BTreeSet;
 use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::{Mutex, RwLock};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc;
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+use tokio::task::JoinHandle;
 use tokio::time::timeout;
 use uuid::{Uuid, uuid};
 
@@ -28,10 +37,95 @@ const PPG_AMBIENT_UUID: Uuid = uuid!("273e000f-4c4d-454d-96be-f03bac821358");
 const PPG_INFRARED_UUID: Uuid = uuid!("273e0010-4c4d-454d-96be-f03bac821358");
 const PPG_RED_UUID: Uuid = uuid!("273e0011-4c4d-454d-96be-f03bac821358");
 
+// Telemetry Characteristic UUID
+const TELEMETRY_UUID: Uuid = uuid!("273e000b-4c4d-454d-96be-f03bac821358");
+
+// IMU Characteristic UUIDs
+const GYRO_UUID: Uuid = uuid!("273e0009-4c4d-454d-96be-f03bac821358");
+const ACCELEROMETER_UUID: Uuid = uuid!("273e000a-4c4d-454d-96be-f03bac821358");
+
+// Channel order of the EEG and PPG samples
+const EEG_UUIDS: [Uuid; 5] = [
+  EEG_TP9_UUID,
+  EEG_AF7_UUID,
+  EEG_AF8_UUID,
+  EEG_TP10_UUID,
+  EEG_AUX_UUID,
+];
+const PPG_UUIDS: [Uuid; 3] = [PPG_AMBIENT_UUID, PPG_INFRARED_UUID, PPG_RED_UUID];
+const IMU_UUIDS: [Uuid; 2] = [ACCELEROMETER_UUID, GYRO_UUID];
+
+/// The service and every characteristic the crate uses, by name
+pub const KNOWN_UUIDS: [(&str, Uuid); 13] = [
+  ("service", MUSE_SERVICE_UUID),
+  ("control", CONTROL_UUID),
+  ("eeg_tp9", EEG_TP9_UUID),
+  ("eeg_af7", EEG_AF7_UUID),
+  ("eeg_af8", EEG_AF8_UUID),
+  ("eeg_tp10", EEG_TP10_UUID),
+  ("eeg_aux", EEG_AUX_UUID),
+  ("ppg_ambient", PPG_AMBIENT_UUID),
+  ("ppg_infrared", PPG_INFRARED_UUID),
+  ("ppg_red", PPG_RED_UUID),
+  ("gyro", GYRO_UUID),
+  ("accelerometer", ACCELEROMETER_UUID),
+  ("telemetry", TELEMETRY_UUID),
+];
+
+// Default preset: 5 EEG channels + PPG
+const DEFAULT_PRESET: &str = "p50";
+
+// The preset a Muse boots into, restored by `reset_on_disconnect`
+const FACTORY_PRESET: &str = "p21";
+
+// How long a control command may take to get a response
+const CONTROL_RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What a preset streams besides the four EEG electrodes
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PresetLayout {
+  aux: bool,
+  ppg: bool,
+}
+
+fn preset_layout(preset: &str) -> Option<PresetLayout> {
+  let (aux, ppg) = match preset {
+    // EEG + AUX, with (p50) or without (p20) PPG
+    "p20" => (true, false),
+    "p50" => (true, true),
+    // Four EEG channels, with (p51) or without (p21) PPG
+    "p21" => (false, false),
+    "p51" => (false, true),
+    // Sleep presets, streamed the same way as p50
+    "p1034" | "p1035" => (true, true),
+    _ => return None,
+  };
+  Some(PresetLayout { aux, ppg })
+}
+
+// Muse S sleep preset. It keeps EEG plus the PPG ambient/infrared/red
+// channels (the SpO2 inputs), so the existing outlets cover it
+pub const SLEEP_PRESET: &str = "p1035";
+
 #[derive(Debug, Clone)]
 pub enum DataType {
-  Eeg([f32; 5]), // 5 EEG channels: TP9, AF7, AF8, TP10, AUX
-  Ppg([f32; 3]), // 3 PPG channels: AMBIENT, INFRARED, RED
+  Eeg([f32; 5]),           // 5 EEG channels: TP9, AF7, AF8, TP10, AUX
+  Ppg([f32; 3]),           // 3 PPG channels: AMBIENT, INFRARED, RED
+  Accelerometer([f32; 3]), // X, Y, Z in g
+  Gyro([f32; 3]),          // X, Y, Z in deg/s
+}
+
+/// A sample with its LSL timestamp (`lsl::local_clock()` domain)
+#[derive(Debug, Clone)]
+pub struct TimestampedData {
+  pub data: DataType,
+  pub timestamp: f64,
+  /// Whether this is the last sample of its BLE chunk
+  pub last_in_chunk: bool,
+  /// Position in the stream, counting the samples of lost packets too, so
+  /// gaps show up as jumps. Continues across relinks, which can't see what
+  /// was lost while the link was down
+  pub index: u64,
 }
 
 // Data structures for chunking like TypeScript implementation
@@ -39,6 +133,20 @@ const EEG_CHUNK_SIZE: usize = 12;
 const PPG_CHUNK_SIZE: usize = 6;
 const EEG_CHANNEL_COUNT: usize = 5;
 const PPG_CHANNEL_COUNT: usize = 3;
+/// EEG notification size: the 2-byte sequence number and 12 12-bit samples
+pub const EEG_PACKET_BYTES: u32 = 2 + EEG_CHUNK_SIZE as u32 * 3 / 2;
+/// PPG notification size: the 2-byte sequence number and 6 24-bit samples
+pub const PPG_PACKET_BYTES: u32 = 2 + PPG_CHUNK_SIZE as u32 * 3;
+/// Smallest and largest EEG values the decoder produces
+pub const EEG_RAILS: (f32, f32) = (0.0, u8::MAX as f32);
+const EEG_SAMPLE_RATE: f64 = 256.0;
+const PPG_SAMPLE_RATE: f64 = 64.0;
+// Each IMU packet holds 3 samples of X, Y and Z as big-endian i16
+const IMU_SAMPLES_PER_PACKET: usize = 3;
+pub const IMU_SAMPLE_RATE: f64 = 52.0;
+// Full scale of ±2 g and ±245 deg/s over the i16 range
+const ACCELEROMETER_SCALE: f32 = 0.0000610352;
+const GYRO_SCALE: f32 = 0.0074768;
 
 #[derive(Clone)]
 struct ChannelChunks {
@@ -67,38 +175,402 @@ impl ChannelChunks {
   }
 }
 
+// Bytes before the sample payload (the 16-bit sequence counter)
+const DEFAULT_HEADER_BYTES: usize = 2;
+
+// Longest command whose frame (length byte, command, newline) fits the 20
+// bytes of a write at the default 23-byte MTU
+const MAX_COMMAND_BYTES: usize = 18;
+
+// The Muse's own keep-alive command (same as muselsl); it doesn't change the
+// preset or the streaming state, so it's safe to send at any time
+const KEEPALIVE_COMMAND: &[u8] = b"k";
+
+/// What the notification task reports about the stream
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+  /// The sample receiver has gone away; sent once, after the device is halted
+  SinkClosed,
+  /// Indices of the EEG channels that sent no packets within the silence timeout
+  ChannelsSilent(Vec<usize>),
+  /// EEG packet loss (in percent) over the last monitoring window
+  PacketLoss(f64),
+  /// Loss (in percent) went over `reconnect_on_loss_pct`; the link is being re-established
+  Relinking(f64),
+  /// The link was re-established and streaming resumed
+  Relinked,
+  /// Re-establishing the link failed, so the stream has stopped
+  RelinkFailed(String),
+  /// A packet repeated the previous one's sequence number on its channel and was dropped
+  DuplicatePacket,
+  /// A notification from a characteristic the crate doesn't decode
+  Unhandled { uuid: Uuid, data: Vec<u8> },
+  /// The notification stream ended while streaming; the link is being re-established
+  StreamEnded,
+  /// No EEG arrived within the silence timeout, so the start sequence was sent again
+  Resynced,
+  /// A packet that couldn't be decoded (too short, or a PPG payload that
+  /// isn't whole 24-bit samples) was dropped
+  MalformedPacket { uuid: Uuid, len: usize },
+  /// A telemetry packet arrived
+  Telemetry(Telemetry),
+}
+
+/// Receives the notification task's `StreamEvent`s
+pub type StreamEvents = Arc<dyn Fn(StreamEvent) + Send + Sync>;
+
+type NotificationStream = Pin<Box<dyn Stream<Item = ValueNotification> + Send>>;
+
+// Packet loss is measured over this window
+const LOSS_MONITOR_WINDOW: Duration = Duration::from_secs(5);
+// Minimum time between loss-triggered relinks, so a bad room doesn't thrash the link
+const RELINK_COOLDOWN: Duration = Duration::from_secs(60);
+
+// Several chunks' worth even at a degraded rate
+const DEFAULT_SILENT_CHANNEL_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Characteristics every Muse has, which `connect` waits for before succeeding
+const REQUIRED_UUIDS: [Uuid; 5] = [
+  CONTROL_UUID,
+  EEG_TP9_UUID,
+  EEG_AF7_UUID,
+  EEG_AF8_UUID,
+  EEG_TP10_UUID,
+];
+const DEFAULT_SERVICE_READY_TIMEOUT: Duration = Duration::from_secs(3);
+// Pause between re-discoveries while waiting for the characteristics
+const SERVICE_READY_POLL: Duration = Duration::from_millis(250);
+
+// How often `find_device` checks the scan results
+const DEFAULT_SCAN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Control writes go out without response, so nothing stops them from
+// piling up in the controller's write queue. Sent back-to-back, some
+// platforms drop part of the start sequence without an error and the
+// device stays connected but never streams
+const DEFAULT_CONTROL_COMMAND_GAP: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone)]
+pub struct BleOptions {
+  /// Header bytes to skip in each EEG packet before the samples
+  pub eeg_header_bytes: usize,
+  /// Header bytes to skip in each PPG packet before the samples
+  pub ppg_header_bytes: usize,
+  /// Interval between keep-alive writes while connected, `None` disables them
+  pub keepalive_interval_ms: Option<u64>,
+  /// Sign-extend the 24-bit PPG values instead of treating them as unsigned
+  pub ppg_signed: bool,
+  /// Halt and restore the factory preset before disconnecting
+  pub reset_on_disconnect: bool,
+  /// How long after starting a stream an EEG channel may go without packets
+  /// before it's reported as silent
+  pub silent_channel_timeout: Duration,
+  /// EEG packet loss (in percent) over a monitoring window that triggers
+  /// re-establishing the link, `None` disables it
+  pub reconnect_on_loss_pct: Option<f64>,
+  /// Info string of the adapter to connect through, `None` uses the first usable one
+  pub adapter: Option<String>,
+  /// Weakest signal (in dBm) a Muse may have to be connected to, `None` accepts any
+  pub min_rssi: Option<i16>,
+  /// How long `connect` keeps re-discovering services for the Muse
+  /// characteristics to appear before failing
+  pub service_ready_timeout: Duration,
+  /// Scan without the Muse service filter, matching on the name alone
+  pub scan_all: bool,
+  /// Pause between the commands of the start sequence
+  pub control_command_gap: Duration,
+  /// Interval between checks of the scan results while looking for a Muse
+  pub scan_poll_interval: Duration,
+  /// Subscribe to the accelerometer and gyroscope as well
+  pub imu: bool,
+}
+
+impl Default for BleOptions {
+  fn default() -> Self {
+    Self {
+      eeg_header_bytes: DEFAULT_HEADER_BYTES,
+      ppg_header_bytes: DEFAULT_HEADER_BYTES,
+      keepalive_interval_ms: None,
+      ppg_signed: false,
+      reset_on_disconnect: false,
+      silent_channel_timeout: DEFAULT_SILENT_CHANNEL_TIMEOUT,
+      reconnect_on_loss_pct: None,
+      adapter: None,
+      min_rssi: None,
+      service_ready_timeout: DEFAULT_SERVICE_READY_TIMEOUT,
+      scan_all: false,
+      control_command_gap: DEFAULT_CONTROL_COMMAND_GAP,
+      scan_poll_interval: DEFAULT_SCAN_POLL_INTERVAL,
+      imu: false,
+    }
+  }
+}
+
+/// Link state that can be read without waiting on the connector
+#[derive(Default)]
+pub struct LinkStatus {
+  /// True while `connect` is scanning
+  pub scanning: AtomicBool,
+  /// When a control command was last written successfully on this connection
+  pub last_command: std::sync::Mutex<Option<Instant>>,
+}
+
+impl LinkStatus {
+  fn command_succeeded(&self) {
+    *self.last_command.lock().unwrap() = Some(Instant::now());
+  }
+}
+
+/// Whether samples are being forwarded, read per notification without a lock
+#[derive(Default)]
+struct StreamingFlag {
+  on: AtomicBool,
+}
+
+impl StreamingFlag {
+  fn get(&self) -> bool {
+    self.on.load(Ordering::Acquire)
+  }
+
+  fn set(&self, on: bool) {
+    self.on.store(on, Ordering::Release);
+  }
+}
+
 pub struct BleConnector<P: Peripheral> {
   adapter: Adapter,
+  options: BleOptions,
   device: Option<P>,
   characteristics: Mutex<HashMap<Uuid, Characteristic>>,
-  streaming: Arc<RwLock<bool>>,
-  data_tx: Option<mpsc::Sender<DataType>>,
+  streaming: Arc<StreamingFlag>,
+  data_tx: Option<mpsc::Sender<TimestampedData>>,
+  keepalive: Option<JoinHandle<()>>,
+  notification_task: Option<JoinHandle<()>>,
+  status: Arc<LinkStatus>,
+  preset: Arc<RwLock<String>>,
+  /// Whether the preset streams AUX, which decides the channel that completes an EEG chunk
+  eeg_aux: Arc<AtomicBool>,
+}
+
+impl<P: Peripheral> Drop for BleConnector<P> {
+  fn drop(&mut self) {
+    if let Some(keepalive) = self.keepalive.take() {
+      keepalive.abort();
+    }
+    if let Some(task) = self.notification_task.take() {
+      task.abort();
+    }
+  }
+}
+
+/// Returns the info string of every BLE adapter on this machine
+pub async fn list_adapters() -> Result<Vec<String>> {
+  let manager = Manager::new().await?;
+  let mut names = Vec::new();
+  for adapter in manager.adapters().await? {
+    names.push(adapter_name(&adapter).await);
+  }
+  Ok(names)
+}
+
+async fn adapter_name(adapter: &Adapter) -> String {
+  adapter
+    .adapter_info()
+    .await
+    .unwrap_or_else(|_| "Unknown adapter".to_string())
+}
+
+/// Some machines list a disabled or virtual adapter first, which fails every
+/// scan, so each is probed in turn: it must not be powered off and must be
+/// able to start a scan
+async fn first_usable_adapter(adapters: Vec<Adapter>) -> Result<Adapter> {
+  let count = adapters.len();
+  for adapter in adapters {
+    let name = adapter_name(&adapter).await;
+    if matches!(adapter.adapter_state().await, Ok(CentralState::PoweredOff)) {
+      eprintln!("Skipping BLE adapter {}: powered off", name);
+      continue;
+    }
+    if let Err(e) = adapter.start_scan(ScanFilter::default()).await {
+      eprintln!("Skipping BLE adapter {}: can't scan: {}", name, e);
+      continue;
+    }
+    let _ = adapter.stop_scan().await;
+    if count > 1 {
+      eprintln!("Using BLE adapter {}", name);
+    }
+    return Ok(adapter);
+  }
+  Err(match count {
+    0 => "No BLE adapter found".into(),
+    _ => format!(
+      "None of the {} BLE adapters is usable, set `adapter` to pick one",
+      count
+    )
+    .into(),
+  })
+}
+
+fn is_muse_name(name: &str) -> bool {
+  name.contains("Muse")
+}
+
+/// A Muse seen while scanning, with the adapter that saw it
+#[derive(Debug, Clone)]
+pub struct DiscoveredMuse {
+  pub name: String,
+  pub uuid: String,
+  pub adapter: String,
+  pub rssi: Option<i16>,
+  /// Connects to this peripheral without scanning, see `discovered_peripheral`
+  pub handle: String,
+}
+
+// How long a scan's handles can be connected to. Platforms forget
+// peripherals that stop advertising, so older ones are likely stale
+const DISCOVERY_HANDLE_TTL: Duration = Duration::from_secs(60);
+
+/// Peripherals found by `scan_all_adapters`, by handle, with when they were seen
+static DISCOVERED: LazyLock<std::sync::Mutex<HashMap<String, (PlatformPeripheral, Instant)>>> =
+  LazyLock::new(Default::default);
+static NEXT_HANDLE: AtomicU64 = AtomicU64::new(1);
+
+/// The peripheral a recent scan listed under `handle`
+pub fn discovered_peripheral(handle: &str) -> Result<PlatformPeripheral> {
+  let discovered = DISCOVERED.lock().unwrap();
+  match discovered.get(handle) {
+    Some((peripheral, seen)) if seen.elapsed() < DISCOVERY_HANDLE_TTL => Ok(peripheral.clone()),
+    Some(_) => Err(format!("Device handle {} has expired, list devices again", handle).into()),
+    None => Err(format!("Unknown device handle {}", handle).into()),
+  }
+}
+
+fn register_discovered(peripheral: &PlatformPeripheral) -> String {
+  let handle = format!("muse-{}", NEXT_HANDLE.fetch_add(1, Ordering::Relaxed));
+  let mut discovered = DISCOVERED.lock().unwrap();
+  discovered.retain(|_, (_, seen)| seen.elapsed() < DISCOVERY_HANDLE_TTL);
+  discovered.insert(handle.clone(), (peripheral.clone(), Instant::now()));
+  handle
+}
+
+/// Some units only expose the service once connected, so an unfiltered scan
+/// is the only way to see them; callers still check the name
+fn muse_scan_filter(scan_all: bool) -> ScanFilter {
+  ScanFilter {
+    services: if scan_all {
+      Vec::new()
+    } else {
+      vec![MUSE_SERVICE_UUID]
+    },
+  }
+}
+
+/// Scans on every adapter at once for `duration`. A headband in range of
+/// several adapters is listed once for each. `scan_all` drops the service
+/// filter as in `BleOptions`, leaving the name check to pick out Muses
+pub async fn scan_all_adapters(duration: Duration, scan_all: bool) -> Result<Vec<DiscoveredMuse>> {
+  let manager = Manager::new().await?;
+  let adapters = manager.adapters().await?;
+  let scans = adapters.iter().map(|adapter| async move {
+    (
+      adapter_name(adapter).await,
+      scan_adapter(adapter, duration, scan_all).await,
+    )
+  });
+
+  // A disabled or virtual adapter fails its scan, which shouldn't hide
+  // the Muses the others found
+  let mut found = Vec::new();
+  for (name, muses) in futures::future::join_all(scans).await {
+    match muses {
+      Ok(muses) => found.extend(muses),
+      Err(e) => eprintln!("Skipping BLE adapter {}: can't scan: {}", name, e),
+    }
+  }
+  Ok(found)
+}
+
+async fn scan_adapter(
+  adapter: &Adapter,
+  duration: Duration,
+  scan_all: bool,
+) -> Result<Vec<DiscoveredMuse>> {
+  let adapter_name = adapter_name(adapter).await;
+  adapter.start_scan(muse_scan_filter(scan_all)).await?;
+  tokio::time::sleep(duration).await;
+  let peripherals = adapter.peripherals().await;
+  // Stop before bailing out so the adapter doesn't keep scanning
+  let stopped = adapter.stop_scan().await;
+
+  let mut found = Vec::new();
+  for peripheral in peripherals? {
+    if let Some(properties) = peripheral.properties().await?
+      && let Some(name) = properties.local_name
+      && is_muse_name(&name)
+    {
+      found.push(DiscoveredMuse {
+        name,
+        uuid: peripheral.id().to_string(),
+        adapter: adapter_name.clone(),
+        rssi: properties.rssi,
+        handle: register_discovered(&peripheral),
+      });
+    }
+  }
+  stopped?;
+  Ok(found)
 }
 
 impl BleConnector<PlatformPeripheral> {
-  pub async fn new() -> Result<Self> {
+  /// `status` is kept up to date as the link is used, so it can be read
+  /// without waiting on the connector
+  pub async fn new(options: BleOptions, status: Arc<LinkStatus>) -> Result<Self> {
     let manager = Manager::new().await?;
     let adapters = manager.adapters().await?;
-    let adapter = adapters.into_iter().next().ok_or("No BLE adapter found")?;
+    let adapter = match &options.adapter {
+      Some(wanted) => {
+        let mut matching = None;
+        for adapter in adapters {
+          if adapter_name(&adapter).await == *wanted {
+            matching = Some(adapter);
+            break;
+          }
+        }
+        matching.ok_or_else(|| format!("BLE adapter \"{}\" not found", wanted))?
+      }
+      None => first_usable_adapter(adapters).await?,
+    };
 
     Ok(Self {
       adapter,
+      options,
       device: None,
       characteristics: Mutex::new(HashMap::new()),
-      streaming: Arc::new(RwLock::new(false)),
+      streaming: Arc::new(StreamingFlag::default()),
       data_tx: None,
+      keepalive: None,
+      notification_task: None,
+      status,
+      preset: Arc::new(RwLock::new(DEFAULT_PRESET.to_string())),
+      eeg_aux: Arc::new(AtomicBool::new(true)),
     })
   }
 
-  pub async fn connect(&mut self, target_uuid: Option<String>) -> Result<(String, String)> {
-    let service_uuid = MUSE_SERVICE_UUID;
-    let filter = ScanFilter {
-      services: vec![service_uuid],
-    };
-
-    self.adapter.start_scan(filter).await?;
+  /// Scans for a Muse, or for `target_uuid` when given, for up to 10 s.
+  /// With `min_rssi` set, Muses with a weaker signal are passed over
+  pub async fn find_device(&mut self, target_uuid: Option<String>) -> Result<PlatformPeripheral> {
+    self
+      .adapter
+      .start_scan(muse_scan_filter(self.options.scan_all))
+      .await?;
+    self.status.scanning.store(true, Ordering::Relaxed);
 
-    let device = timeout(Duration::from_secs(10), async {
+    let min_rssi = self.options.min_rssi;
+    let poll_interval = self.options.scan_poll_interval;
+    // Strongest signal among the Muses passed over for being too weak
+    let mut too_weak: Option<i16> = None;
+    let mut noted_missing_rssi = false;
+    let found = timeout(Duration::from_secs(10), async {
       loop {
         let peripherals = self
           .adapter
@@ -110,30 +582,65 @@ impl BleConnector<PlatformPeripheral> {
             .properties()
             .await
             .map_err(|e| -> Box<dyn std::error::Error + Send + Sync> { Box::new(e) })?;
-          if let Some(props) = properties {
-            if let Some(name) = &props.local_name {
-              if name.contains("Muse") || name == "MuseS" {
-                if let Some(target) = &target_uuid {
-                  if peripheral.id().to_string() != *target {
-                    continue;
-                  }
+          if let Some(props) = properties
+            && let Some(name) = &props.local_name
+            && is_muse_name(name)
+          {
+            if let Some(target) = &target_uuid
+              && peripheral.id().to_string() != *target
+            {
+              continue;
+            }
+            if let Some(min_rssi) = min_rssi {
+              match props.rssi {
+                Some(rssi) if rssi < min_rssi => {
+                  too_weak = too_weak.max(Some(rssi));
+                  continue;
+                }
+                Some(_) => {}
+                None if !noted_missing_rssi => {
+                  eprintln!("RSSI unavailable for {}, skipping the minRssi check", name);
+                  noted_missing_rssi = true;
                 }
-                return Ok::<PlatformPeripheral, Box<dyn std::error::Error + Send + Sync>>(
-                  peripheral,
-                );
+                None => {}
               }
             }
+            return Ok::<PlatformPeripheral, Box<dyn std::error::Error + Send + Sync>>(peripheral);
           }
         }
-        tokio::time::sleep(Duration::from_millis(100)).await;
+        tokio::time::sleep(poll_interval).await;
       }
     })
-    .await??;
+    .await;
 
-    self.adapter.stop_scan().await?;
+    // Stop on every path, including timeouts and errors, so the adapter
+    // doesn't keep scanning in the background
+    let stopped = self.adapter.stop_scan().await;
+    self.status.scanning.store(false, Ordering::Relaxed);
+    let device = match (found, too_weak, min_rssi) {
+      (Err(_), Some(rssi), Some(min_rssi)) => {
+        return Err(
+          format!(
+            "Found a Muse, but its signal ({} dBm) is weaker than minRssi ({} dBm); move closer to the headband",
+            rssi, min_rssi
+          )
+          .into(),
+        );
+      }
+      (found, _, _) => found??,
+    };
+    stopped?;
+    Ok(device)
+  }
 
+  /// Connects to a device returned by `find_device`, returning its name and uuid
+  pub async fn connect(&mut self, device: PlatformPeripheral) -> Result<(String, String)> {
     device.connect().await?;
-    device.discover_services().await?;
+    if let Err(e) = self.discover_characteristics(&device).await {
+      // Don't leave a link up that can't stream
+      let _ = device.disconnect().await;
+      return Err(e);
+    }
 
     let properties = device
       .properties()
@@ -145,13 +652,78 @@ impl BleConnector<PlatformPeripheral> {
     let device_uuid = device.id().to_string();
 
     self.device = Some(device);
+    *self.status.last_command.lock().unwrap() = None;
+    self.start_keepalive();
 
     Ok((device_name, device_uuid))
   }
 
+  /// Discovers services until the Muse service and the characteristics in
+  /// `REQUIRED_UUIDS` are present, since some platforms report an empty or
+  /// incomplete GATT table (or fail discovery outright) for a moment after
+  /// connecting. A device that never shows them is something merely named
+  /// like a Muse
+  async fn discover_characteristics(&self, device: &PlatformPeripheral) -> Result<()> {
+    let deadline = Instant::now() + self.options.service_ready_timeout;
+    let mut attempt = 1;
+    loop {
+      if let Err(e) = device.discover_services().await {
+        if Instant::now() >= deadline {
+          return Err(format!("Service discovery failed after {} attempts: {}", attempt, e).into());
+        }
+        eprintln!("Service discovery attempt {} failed: {}", attempt, e);
+        attempt += 1;
+        tokio::time::sleep(SERVICE_READY_POLL).await;
+        continue;
+      }
+
+      let services = device.services();
+      let missing = missing_characteristics(&services);
+      if missing.is_empty() {
+        return Ok(());
+      }
+      if Instant::now() >= deadline {
+        if services.is_empty() {
+          return Err(
+            format!(
+              "No services discovered after {} attempts in {} ms (serviceReadyMs)",
+              attempt,
+              self.options.service_ready_timeout.as_millis()
+            )
+            .into(),
+          );
+        }
+        return Err(
+          format!(
+            "Not a supported Muse, missing after connecting: {}",
+            missing.join(", ")
+          )
+          .into(),
+        );
+      }
+      eprintln!(
+        "Service discovery attempt {} found {} services, missing {}; retrying",
+        attempt,
+        services.len(),
+        missing.join(", ")
+      );
+      attempt += 1;
+      tokio::time::sleep(SERVICE_READY_POLL).await;
+    }
+  }
+
   pub async fn disconnect(&mut self) -> Result<()> {
-    // Stop streaming first
-    self.stop_streaming().await?;
+    self.stop_keepalive();
+
+    // Stop streaming first, but a headband that won't take the halt
+    // command must still be released
+    if let Err(e) = self.stop_streaming().await {
+      eprintln!("Failed to stop streaming before disconnecting: {}", e);
+    }
+
+    if self.options.reset_on_disconnect {
+      self.reset_to_factory_preset().await;
+    }
 
     if let Some(device) = &self.device {
       device.disconnect().await?;
@@ -164,7 +736,60 @@ impl BleConnector<PlatformPeripheral> {
     self.device.is_some()
   }
 
-  pub async fn start_streaming(&mut self, data_tx: mpsc::Sender<DataType>) -> Result<()> {
+  /// Services and characteristics found during discovery on connect
+  pub fn services(&self) -> Option<BTreeSet<Service>> {
+    self.device.as_ref().map(|device| device.services())
+  }
+
+  /// Whether the (notification, keep-alive) tasks are still running
+  pub fn task_status(&self) -> (bool, bool) {
+    let running =
+      |task: &Option<JoinHandle<()>>| task.as_ref().is_some_and(|task| !task.is_finished());
+    (running(&self.notification_task), running(&self.keepalive))
+  }
+
+  /// Whether the connected device, named `device_name` as `connect`
+  /// reported, has the sleep presets
+  pub fn supports_sleep_mode(&self, device_name: &str) -> bool {
+    self
+      .device
+      .as_ref()
+      .is_some_and(|device| has_sleep_presets(device_name, &device.characteristics()))
+  }
+
+  /// Selects the preset used when streaming starts, re-sending the start
+  /// sequence if the device is already streaming. The outlets and the
+  /// notification task carry on, so EEG continues across the switch; that
+  /// only holds for a preset streaming the same channels, others need a restart
+  pub async fn set_preset(&mut self, preset: &str) -> Result<()> {
+    if self.streaming.get() {
+      let current = self.preset.read().await.clone();
+      let layout = preset_layout(preset).ok_or_else(|| format!("Unknown preset {}", preset))?;
+      if preset_layout(&current) != Some(layout) {
+        return Err(
+          format!(
+            "{} streams different channels than {}, stop streaming to switch",
+            preset, current
+          )
+          .into(),
+        );
+      }
+    }
+    self.select_preset(preset).await?;
+
+    if self.streaming.get() {
+      self.send_start_commands().await?;
+    }
+    Ok(())
+  }
+
+  /// Streams samples into `data_tx`, reporting on the stream through `events`.
+  /// If the receiver is dropped while streaming, the device is halted
+  pub async fn start_streaming(
+    &mut self,
+    data_tx: mpsc::Sender<TimestampedData>,
+    events: StreamEvents,
+  ) -> Result<()> {
     if !self.is_connected() {
       return Err("Device not connected".into());
     }
@@ -172,14 +797,18 @@ impl BleConnector<PlatformPeripheral> {
     self.data_tx = Some(data_tx);
 
     // Discover and setup characteristics for notifications
-    self.setup_notifications().await?;
-
-    // Send device control commands like TypeScript implementation
-    for command in &["h", "p50", "s", "d"] {
-      self.send_control_command(command.as_bytes()).await?;
+    let started = match self.setup_notifications(events).await {
+      Ok(()) => self.send_start_commands().await,
+      Err(e) => Err(e),
+    };
+    if started.is_err() {
+      // Don't leave the task (and through its sender the LSL thread)
+      // running for a stream that never started
+      self.end_notifications().await;
     }
+    started?;
 
-    *self.streaming.write().await = true;
+    self.streaming.set(true);
     Ok(())
   }
 
@@ -187,76 +816,276 @@ impl BleConnector<PlatformPeripheral> {
     // Send halt command like TypeScript implementation
     self.send_control_command("h".as_bytes()).await?;
 
-    *self.streaming.write().await = false;
+    self.streaming.set(false);
+    self.end_notifications().await;
+    Ok(())
+  }
 
-    // Stop notifications on all characteristics
+  /// Unsubscribes from the streamed characteristics and ends the
+  /// notification task
+  async fn end_notifications(&mut self) {
     if let Some(device) = &self.device {
-      let eeg_uuids = [
-        EEG_TP9_UUID,
-        EEG_AF7_UUID,
-        EEG_AF8_UUID,
-        EEG_TP10_UUID,
-        EEG_AUX_UUID,
-      ];
-      let ppg_uuids = [PPG_AMBIENT_UUID, PPG_INFRARED_UUID, PPG_RED_UUID];
-
-      for uuid in eeg_uuids.iter().chain(ppg_uuids.iter()) {
+      for uuid in EEG_UUIDS
+        .iter()
+        .chain(&PPG_UUIDS)
+        .chain(&IMU_UUIDS)
+        .chain([&TELEMETRY_UUID])
+      {
         if let Some(char) = self.get_characteristic(uuid).await {
           let _ = device.unsubscribe(&char).await; // Ignore errors
         }
       }
     }
 
-    // Clear data sender
-    self.data_tx = None;
+    end_notification_task(&mut self.notification_task, &mut self.data_tx);
+  }
+
+  /// Times `runs` status commands (`s`) until the first fragment of each
+  /// response arrives, returning the median in milliseconds
+  pub async fn measure_command_latency(&self, runs: usize) -> Result<f64> {
+    let mut responses = self.control_responses().await?;
+
+    let mut latencies = Vec::with_capacity(runs);
+    for _ in 0..runs {
+      let sent_at = Instant::now();
+      self.send_control_command(b"s").await?;
+
+      let mut response = ControlResponse::default();
+      let mut fragment = next_control_fragment(&mut responses).await?;
+      latencies.push(sent_at.elapsed().as_secs_f64() * 1000.0);
+
+      // Drain the rest so it isn't mistaken for the next run's response
+      while response.push(&fragment).is_none() {
+        fragment = next_control_fragment(&mut responses).await?;
+      }
+    }
+
+    median(latencies).ok_or_else(|| "No latency runs requested".into())
+  }
+
+  /// Battery charge in percent, from the `bp` field of the status response
+  pub async fn battery_percent(&self) -> Result<f64> {
+    let status = self.query_control(b"s").await?;
+    json_number(&status, "bp").ok_or_else(|| "Status response has no battery level".into())
+  }
+
+  /// Hardware revision (e.g. "4.4"), from the `hw` field of the version
+  /// response (`v1`). None when the firmware doesn't report one
+  pub async fn hardware_revision(&self) -> Result<Option<String>> {
+    let version = self.query_control(b"v1").await?;
+    Ok(json_string(&version, "hw"))
+  }
+
+  /// Sends `cmd` and waits for its whole JSON response
+  async fn query_control(&self, cmd: &[u8]) -> Result<String> {
+    let mut responses = self.control_responses().await?;
+    self.send_control_command(cmd).await?;
+
+    let mut response = ControlResponse::default();
+    loop {
+      let fragment = next_control_fragment(&mut responses).await?;
+      if let Some(json) = response.push(&fragment) {
+        return Ok(json);
+      }
+    }
+  }
+
+  /// Whether the device answers a status command (`s`) within the control
+  /// response timeout. Streaming is unaffected, like the `s` in the start sequence
+  pub async fn ping(&self) -> Result<bool> {
+    let mut responses = self.control_responses().await?;
+    self.send_control_command(b"s").await?;
+    Ok(control_answered(&mut responses, CONTROL_RESPONSE_TIMEOUT).await)
+  }
+
+  /// Brings a device that stopped following its commands back to a known
+  /// configuration without reconnecting: halts it, re-applies the current
+  /// preset, confirms with a status query that it answers again, and
+  /// resumes streaming if it was streaming
+  pub async fn resync(&self) -> Result<()> {
+    let preset = self.preset.read().await.clone();
+    for (i, cmd) in resync_commands(&preset, self.streaming.get())
+      .into_iter()
+      .enumerate()
+    {
+      if i > 0 {
+        tokio::time::sleep(self.options.control_command_gap).await;
+      }
+      if cmd == "s" {
+        self.query_control(cmd.as_bytes()).await?;
+      } else {
+        self.send_control_command(cmd.as_bytes()).await?;
+      }
+    }
     Ok(())
   }
 
-  async fn send_control_command(&self, cmd: &[u8]) -> Result<()> {
+  /// Halts the device between bursts, leaving the stream set up
+  pub async fn rest(&self) -> Result<()> {
+    self.send_control_command(b"h").await
+  }
+
+  /// Restarts the device after `rest`, on the current preset
+  pub async fn wake(&self) -> Result<()> {
+    let device = self.device.as_ref().ok_or("Device not connected")?;
+    let preset = self.preset.read().await.clone();
+    send_start_sequence(
+      device,
+      &preset,
+      self.options.control_command_gap,
+      &self.status,
+    )
+    .await
+  }
+
+  /// Restarts a stuck stream without touching the sample channel: renews
+  /// the EEG and PPG subscriptions and sends the whole start sequence again.
+  /// Unlike `resync` nothing waits for an answer, so it also works on a
+  /// device too hung to reply to a status query
+  pub async fn soft_restart(&self) -> Result<()> {
+    if !self.streaming.get() {
+      return Err("Not streaming".into());
+    }
+    let device = self.device.as_ref().ok_or("Device not connected")?;
+    for char in device.characteristics() {
+      if is_streamed(char.uuid, self.options.imu) {
+        device.subscribe(&char).await?;
+      }
+    }
+    let preset = self.preset.read().await.clone();
+    send_start_sequence(
+      device,
+      &preset,
+      self.options.control_command_gap,
+      &self.status,
+    )
+    .await
+  }
+
+  /// Reads the current value of `uuid` with a GATT read, without subscribing
+  pub async fn read_characteristic(&self, uuid: Uuid) -> Result<Vec<u8>> {
     let device = self.device.as_ref().ok_or("Device not connected")?;
-    let control_char = self
-      .get_characteristic(&CONTROL_UUID)
-      .await
-      .ok_or("Control characteristic not found")?;
+    let char = find_characteristic(device, uuid)
+      .ok_or_else(|| format!("Characteristic {} not found", uuid))?;
+    if !char.properties.contains(CharPropFlags::READ) {
+      return Err(format!("Characteristic {} is not readable", uuid).into());
+    }
+    Ok(device.read(&char).await?)
+  }
+
+  /// Subscribes to the control characteristic, which carries command responses
+  async fn control_responses(&self) -> Result<NotificationStream> {
+    let device = self.device.as_ref().ok_or("Device not connected")?;
+    let control_char =
+      find_characteristic(device, CONTROL_UUID).ok_or("Control characteristic not found")?;
+    device.subscribe(&control_char).await?;
+    Ok(device.notifications().await?)
+  }
+
+  /// Best effort: a failed write shouldn't keep the link open
+  async fn reset_to_factory_preset(&mut self) {
+    for command in ["h", FACTORY_PRESET] {
+      if let Err(e) = self.send_control_command(command.as_bytes()).await {
+        eprintln!("Failed to reset device preset: {}", e);
+        return;
+      }
+    }
+    let _ = self.select_preset(DEFAULT_PRESET).await;
+  }
 
-    // Create command buffer like TypeScript implementation: X{cmd}\n
-    let mut buffer = Vec::with_capacity(cmd.len() + 2);
-    buffer.push(b'X');
-    buffer.extend_from_slice(cmd);
-    buffer.push(b'\n');
+  async fn select_preset(&self, preset: &str) -> Result<()> {
+    let layout = preset_layout(preset).ok_or_else(|| format!("Unknown preset {}", preset))?;
+    *self.preset.write().await = preset.to_string();
+    self.eeg_aux.store(layout.aux, Ordering::Relaxed);
+    Ok(())
+  }
+
+  /// Sends device control commands like TypeScript implementation
+  async fn send_start_commands(&self) -> Result<()> {
+    let preset = self.preset.read().await.clone();
+    for (i, command) in ["h", preset.as_str(), "s", "d"].into_iter().enumerate() {
+      if i > 0 {
+        tokio::time::sleep(self.options.control_command_gap).await;
+      }
+      self.send_control_command(command.as_bytes()).await?;
+    }
+    Ok(())
+  }
 
-    // Set first byte to length - 1 (like TypeScript encoded[0] = encoded.length - 1)
-    buffer[0] = (buffer.len() - 1) as u8;
+  async fn send_control_command(&self, cmd: &[u8]) -> Result<()> {
+    let device = self.device.as_ref().ok_or("Device not connected")?;
+    // The characteristic map is only filled once streaming starts
+    let control_char = match self.get_characteristic(&CONTROL_UUID).await {
+      Some(char) => char,
+      None => {
+        find_characteristic(device, CONTROL_UUID).ok_or("Control characteristic not found")?
+      }
+    };
 
     device
       .write(
         &control_char,
-        &buffer,
+        &encode_command(cmd),
         btleplug::api::WriteType::WithoutResponse,
       )
       .await?;
+    self.status.command_succeeded();
     Ok(())
   }
 
-  async fn setup_notifications(&mut self) -> Result<()> {
+  /// Periodically writes the keep-alive command so idle links aren't dropped
+  fn start_keepalive(&mut self) {
+    self.stop_keepalive();
+
+    let (Some(interval_ms), Some(device)) = (self.options.keepalive_interval_ms, &self.device)
+    else {
+      return;
+    };
+    // Looked up directly since the characteristic map is only filled when streaming starts
+    let Some(control_char) = find_characteristic(device, CONTROL_UUID) else {
+      return;
+    };
+    let device = device.clone();
+    let status = self.status.clone();
+
+    self.keepalive = Some(tokio::spawn(async move {
+      let mut ticker = tokio::time::interval(Duration::from_millis(interval_ms));
+      ticker.tick().await; // The first tick completes immediately
+
+      loop {
+        ticker.tick().await;
+        let command = encode_command(KEEPALIVE_COMMAND);
+        match device
+          .write(
+            &control_char,
+            &command,
+            btleplug::api::WriteType::WithoutResponse,
+          )
+          .await
+        {
+          Ok(()) => status.command_succeeded(),
+          Err(e) => eprintln!("Failed to send keepalive: {}", e),
+        }
+      }
+    }));
+  }
+
+  fn stop_keepalive(&mut self) {
+    if let Some(keepalive) = self.keepalive.take() {
+      keepalive.abort();
+    }
+  }
+
+  async fn setup_notifications(&mut self, events: StreamEvents) -> Result<()> {
     let device = self.device.as_ref().ok_or("Device not connected")?;
 
     // Discover characteristics
-    let eeg_uuids = [
-      EEG_TP9_UUID,
-      EEG_AF7_UUID,
-      EEG_AF8_UUID,
-      EEG_TP10_UUID,
-      EEG_AUX_UUID,
-    ];
-    let ppg_uuids = [PPG_AMBIENT_UUID, PPG_INFRARED_UUID, PPG_RED_UUID];
-
     let mut chars = self.characteristics.lock().await;
 
     for service in device.services() {
       for char in service.characteristics {
         let char_uuid = char.uuid;
-        if eeg_uuids.contains(&char_uuid) || ppg_uuids.contains(&char_uuid) {
+        if is_streamed(char_uuid, self.options.imu) {
           chars.insert(char_uuid, char.clone());
 
           // Subscribe to characteristic notifications
@@ -270,80 +1099,150 @@ impl BleConnector<PlatformPeripheral> {
 
     // Start a task to read notifications and send them through the channel
     if let Some(data_tx) = &self.data_tx {
-      let tx = data_tx.clone();
+      // Taken here so a failure fails `start_streaming` instead of the task
+      let mut notifications = device
+        .notifications()
+        .await
+        .map_err(|e| format!("Failed to get the notification stream: {}", e))?;
+      let mut handler = NotificationHandler::new(
+        data_tx.clone(),
+        self.options.clone(),
+        self.eeg_aux.clone(),
+        events.clone(),
+      );
       let device_clone = device.clone();
       let streaming = self.streaming.clone();
+      let preset = self.preset.clone();
+      let status = self.status.clone();
+
+      let silence_timeout = self.options.silent_channel_timeout;
+      let reconnect_on_loss_pct = self.options.reconnect_on_loss_pct;
+      let command_gap = self.options.control_command_gap;
+      let imu = self.options.imu;
 
-      tokio::spawn(async move {
-        let mut notifications = device_clone.notifications().await.unwrap();
-        let mut chunks = ChannelChunks::new();
-
-        while let Some(notification) = notifications.next().await {
-          let streaming_guard = streaming.read().await;
-          if *streaming_guard {
-            let char_uuid = notification.uuid;
-            let data = notification.value;
-
-            if eeg_uuids.contains(&char_uuid) {
-              // Handle EEG data - parse as raw bytes for chunking
-              if let Ok(channel_values) = parse_eeg_data(&data) {
-                let channel_idx = eeg_uuids
-                  .iter()
-                  .position(|&uuid| uuid == char_uuid)
-                  .unwrap();
-
-                // Store chunk data for this channel
-                if channel_values.len() >= EEG_CHUNK_SIZE {
-                  chunks.eeg_chunks[channel_idx].copy_from_slice(&channel_values[..EEG_CHUNK_SIZE]);
+      let task = tokio::spawn(async move {
+        let silence_check = tokio::time::sleep(silence_timeout);
+        tokio::pin!(silence_check);
+        let mut silence_checked = false;
+        let mut last_relink: Option<Instant> = None;
+
+        loop {
+          let notification = tokio::select! {
+            notification = notifications.next() => match notification {
+              Some(notification) => notification,
+              None => match stream_end(streaming.get(), last_relink) {
+                StreamEnd::Finished => break,
+                StreamEnd::GiveUp => {
+                  fail_relink(
+                    &streaming,
+                    &events,
+                    "Notification stream ended again right after reconnecting".to_string(),
+                  );
+                  break;
                 }
+                StreamEnd::Reconnect => {
+                  events(StreamEvent::StreamEnded);
+                  last_relink = Some(Instant::now());
 
-                // Check if this is the last channel (AUX = index 4)
-                if channel_idx == 4 {
-                  // Push all samples for this chunk
-                  for sample_idx in 0..EEG_CHUNK_SIZE {
-                    let sample: [f32; 5] = [
-                      chunks.eeg_chunks[0][sample_idx] as f32,
-                      chunks.eeg_chunks[1][sample_idx] as f32,
-                      chunks.eeg_chunks[2][sample_idx] as f32,
-                      chunks.eeg_chunks[3][sample_idx] as f32,
-                      chunks.eeg_chunks[4][sample_idx] as f32,
-                    ];
-                    let _ = tx.send(DataType::Eeg(sample));
+                  let preset = preset.read().await.clone();
+                  match resume_link(&device_clone, &preset, command_gap, &status, imu).await {
+                    Ok(stream) => {
+                      notifications = stream;
+                      handler.reset();
+                      events(StreamEvent::Relinked);
+                    }
+                    Err(e) => {
+                      fail_relink(&streaming, &events, format!("Failed to reconnect: {}", e));
+                      break;
+                    }
                   }
-                  chunks.reset_eeg();
+                  continue;
                 }
+              },
+            },
+            // Checked on a timer since a fully silent device sends nothing to react to
+            _ = &mut silence_check, if !silence_checked => {
+              silence_checked = true;
+              let silent = handler.silent_eeg_channels();
+              // No EEG at all usually means the device missed the start
+              // sequence, so it gets one more try
+              let no_eeg = REQUIRED_UUIDS
+                .iter()
+                .filter_map(|uuid| EEG_UUIDS.iter().position(|eeg| eeg == uuid))
+                .all(|channel_idx| silent.contains(&channel_idx));
+              if !silent.is_empty() {
+                events(StreamEvent::ChannelsSilent(silent));
               }
-            } else if ppg_uuids.contains(&char_uuid) {
-              // Handle PPG data - decode 24-bit values
-              if let Ok(decoded_values) = parse_ppg_data(&data) {
-                let channel_idx = ppg_uuids
-                  .iter()
-                  .position(|&uuid| uuid == char_uuid)
-                  .unwrap();
-
-                // Store chunk data for this channel
-                if decoded_values.len() >= PPG_CHUNK_SIZE {
-                  chunks.ppg_chunks[channel_idx].copy_from_slice(&decoded_values[..PPG_CHUNK_SIZE]);
+              if no_eeg && streaming.get() {
+                let preset = preset.read().await.clone();
+                match send_start_sequence(&device_clone, &preset, command_gap, &status).await {
+                  Ok(()) => events(StreamEvent::Resynced),
+                  Err(e) => eprintln!("Failed to resend the start commands: {}", e),
                 }
+              }
+              continue;
+            }
+          };
 
-                // Check if this is the last channel (RED = index 2)
-                if channel_idx == 2 {
-                  // Push all samples for this chunk
-                  for sample_idx in 0..PPG_CHUNK_SIZE {
-                    let sample: [f32; 3] = [
-                      chunks.ppg_chunks[0][sample_idx],
-                      chunks.ppg_chunks[1][sample_idx],
-                      chunks.ppg_chunks[2][sample_idx],
-                    ];
-                    let _ = tx.send(DataType::Ppg(sample));
-                  }
-                  chunks.reset_ppg();
-                }
+          // Stamp on receipt so EEG and PPG share one clock reference
+          let receipt_time = lsl::local_clock();
+
+          // Only off briefly around start and stop, the task is ended on stop
+          if !streaming.get() {
+            continue;
+          }
+          if !is_handled(notification.uuid) {
+            events(StreamEvent::Unhandled {
+              uuid: notification.uuid,
+              data: notification.value,
+            });
+            continue;
+          }
+
+          if handler.is_duplicate(notification.uuid, &notification.value) {
+            events(StreamEvent::DuplicatePacket);
+            continue;
+          }
+
+          let Ok(loss_pct) = handler.handle(notification.uuid, &notification.value, receipt_time)
+          else {
+            // Nothing is reading the samples anymore, so stop the device
+            // rather than streaming into a closed channel
+            streaming.set(false);
+            halt_device(&device_clone, &status).await;
+            events(StreamEvent::SinkClosed);
+            break;
+          };
+
+          let Some(loss_pct) = loss_pct else {
+            continue;
+          };
+          events(StreamEvent::PacketLoss(loss_pct));
+
+          let over_threshold = reconnect_on_loss_pct.is_some_and(|limit| loss_pct > limit);
+          let cooled_down = last_relink.is_none_or(|at| at.elapsed() >= RELINK_COOLDOWN);
+          if over_threshold && cooled_down {
+            events(StreamEvent::Relinking(loss_pct));
+            last_relink = Some(Instant::now());
+
+            let preset = preset.read().await.clone();
+            match resume_link(&device_clone, &preset, command_gap, &status, imu).await {
+              Ok(stream) => {
+                notifications = stream;
+                handler.reset();
+                events(StreamEvent::Relinked);
+              }
+              Err(e) => {
+                fail_relink(&streaming, &events, format!("Failed to reconnect: {}", e));
+                break;
               }
             }
           }
         }
       });
+      if let Some(previous) = self.notification_task.replace(task) {
+        previous.abort();
+      }
     }
 
     Ok(())
@@ -354,37 +1253,1102 @@ impl BleConnector<PlatformPeripheral> {
   }
 }
 
-fn parse_eeg_data(data: &[u8]) -> Result<Vec<u8>> {
-  // EEG data: slice from index 2 like TypeScript (Array.from(data).slice(2))
-  if data.len() < 2 {
-    return Err("EEG data too short".into());
+/// The commands `resync` sends in order: halt, `preset`, a status query
+/// that must be answered before going on, and the resume when `streaming`
+fn resync_commands(preset: &str, streaming: bool) -> Vec<&str> {
+  let mut commands = vec!["h", preset, "s"];
+  if streaming {
+    commands.push("d");
   }
-  Ok(data[2..].to_vec())
+  commands
+}
+
+/// Reassembles a control response, which the Muse sends as a JSON object
+/// split across notifications of a length byte followed by the characters
+#[derive(Default)]
+struct ControlResponse {
+  buffer: String,
 }
 
-fn parse_ppg_data(data: &[u8]) -> Result<Vec<f32>> {
-  // PPG data: slice from index 2, then decode as 24-bit unsigned integers
-  if data.len() < 2 {
-    return Err("PPG data too short".into());
+impl ControlResponse {
+  /// Adds a fragment, returning the whole response once its closing brace arrives
+  fn push(&mut self, fragment: &[u8]) -> Option<String> {
+    let (&len, text) = fragment.split_first()?;
+    let text = &text[..(len as usize).min(text.len())];
+    self.buffer.push_str(&String::from_utf8_lossy(text));
+    self
+      .buffer
+      .contains('}')
+      .then(|| std::mem::take(&mut self.buffer))
   }
-  let channel_values = &data[2..];
-  decode_unsigned_24_bit_data(channel_values)
+}
+
+/// Middle value of `values`, the upper one of the middle two for an even
+/// count. None when empty
+fn median(mut values: Vec<f64>) -> Option<f64> {
+  values.sort_by(f64::total_cmp);
+  values.get(values.len() / 2).copied()
+}
+
+/// Reads a numeric field from a flat JSON object like the Muse status
+/// response (`{"hn":"Muse-1234",...,"bp":82,...}`)
+fn json_number(json: &str, key: &str) -> Option<f64> {
+  let start = json.find(&format!("\"{}\":", key))? + key.len() + 3;
+  let value = json[start..].trim_start();
+  let end = value
+    .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-')))
+    .unwrap_or(value.len());
+  value[..end].parse().ok()
+}
+
+/// Reads a string field from a flat JSON object like the Muse version
+/// response (`{"ap":"headset",...,"hw":"4.4",...}`)
+fn json_string(json: &str, key: &str) -> Option<String> {
+  let start = json.find(&format!("\"{}\":", key))? + key.len() + 3;
+  let value = json[start..].trim_start().strip_prefix('"')?;
+  let end = value.find('"')?;
+  Some(value[..end].to_string())
+}
+
+/// Waits for the next notification from the control characteristic
+async fn next_control_fragment<S>(notifications: &mut S) -> Result<Vec<u8>>
+where
+  S: Stream<Item = ValueNotification> + Unpin,
+{
+  timeout(CONTROL_RESPONSE_TIMEOUT, async {
+    while let Some(notification) = notifications.next().await {
+      if notification.uuid == CONTROL_UUID {
+        return Ok(notification.value);
+      }
+    }
+    Err("Notification stream ended".into())
+  })
+  .await
+  .map_err(|_| "Timed out waiting for a control response")?
+}
+
+/// Whether a whole control response arrives within `wait`. Reads all of
+/// it so it isn't left for the next command
+async fn control_answered<S>(notifications: &mut S, wait: Duration) -> bool
+where
+  S: Stream<Item = ValueNotification> + Unpin,
+{
+  let answered = timeout(wait, async {
+    let mut response = ControlResponse::default();
+    while let Some(notification) = notifications.next().await {
+      if notification.uuid == CONTROL_UUID && response.push(&notification.value).is_some() {
+        return true;
+      }
+    }
+    false
+  })
+  .await;
+  answered.unwrap_or(false)
+}
+
+/// The sample receiver was dropped, so nothing can be forwarded anymore
+struct SinkClosed;
+
+/// Reassembles the per-channel notifications into samples and forwards them
+struct NotificationHandler {
+  tx: mpsc::Sender<TimestampedData>,
+  options: BleOptions,
+  eeg_aux: Arc<AtomicBool>,
+  events: StreamEvents,
+  chunks: ChannelChunks,
+  eeg_packets: [u32; EEG_CHANNEL_COUNT],
+  eeg_sequences: [SequenceTracker; EEG_CHANNEL_COUNT],
+  ppg_sequences: [SequenceTracker; PPG_CHANNEL_COUNT],
+  eeg_loss: LossMonitor,
+  eeg_index: u64,
+  ppg_index: u64,
+  accelerometer_index: u64,
+  gyro_index: u64,
+}
+
+impl NotificationHandler {
+  fn new(
+    tx: mpsc::Sender<TimestampedData>,
+    options: BleOptions,
+    eeg_aux: Arc<AtomicBool>,
+    events: StreamEvents,
+  ) -> Self {
+    Self {
+      tx,
+      options,
+      eeg_aux,
+      events,
+      chunks: ChannelChunks::new(),
+      eeg_packets: [0; EEG_CHANNEL_COUNT],
+      eeg_sequences: Default::default(),
+      ppg_sequences: Default::default(),
+      eeg_loss: LossMonitor::new(LOSS_MONITOR_WINDOW),
+      eeg_index: 0,
+      ppg_index: 0,
+      accelerometer_index: 0,
+      gyro_index: 0,
+    }
+  }
+
+  /// Drops partial chunks and sequence history, e.g. after the link was re-established
+  fn reset(&mut self) {
+    self.chunks = ChannelChunks::new();
+    self.eeg_sequences = Default::default();
+    self.ppg_sequences = Default::default();
+    self.eeg_loss = LossMonitor::new(LOSS_MONITOR_WINDOW);
+  }
+
+  /// EEG channels that haven't delivered a single packet, which points at
+  /// the preset or hardware rather than electrode contact
+  fn silent_eeg_channels(&self) -> Vec<usize> {
+    (0..EEG_CHANNEL_COUNT)
+      .filter(|&channel_idx| self.eeg_packets[channel_idx] == 0)
+      .collect()
+  }
+
+  /// Whether `data` repeats the sequence number of the previous packet on
+  /// its channel, as happens when BLE delivers a retransmission twice
+  fn is_duplicate(&self, char_uuid: Uuid, data: &[u8]) -> bool {
+    let tracker = if let Some(channel_idx) = EEG_UUIDS.iter().position(|&uuid| uuid == char_uuid) {
+      &self.eeg_sequences[channel_idx]
+    } else if let Some(channel_idx) = PPG_UUIDS.iter().position(|&uuid| uuid == char_uuid) {
+      &self.ppg_sequences[channel_idx]
+    } else {
+      return false;
+    };
+    packet_sequence(data).is_some_and(|sequence| tracker.is_repeat(sequence))
+  }
+
+  /// Forwards any samples `data` completes, returning the EEG packet loss
+  /// (in percent) whenever a monitoring window completes
+  fn handle(
+    &mut self,
+    char_uuid: Uuid,
+    data: &[u8],
+    receipt_time: f64,
+  ) -> std::result::Result<Option<f64>, SinkClosed> {
+    let mut loss_pct = None;
+    if let Some(channel_idx) = EEG_UUIDS.iter().position(|&uuid| uuid == char_uuid) {
+      self.eeg_packets[channel_idx] += 1;
+      let mut missed = 0;
+      if let Some(sequence) = packet_sequence(data) {
+        missed = self.eeg_sequences[channel_idx].observe(sequence);
+        loss_pct = self.eeg_loss.record(missed);
+      }
+
+      // Handle EEG data - parse as raw bytes for chunking
+      let Ok(channel_values) = parse_eeg_data(data, self.options.eeg_header_bytes) else {
+        (self.events)(StreamEvent::MalformedPacket {
+          uuid: char_uuid,
+          len: data.len(),
+        });
+        return Ok(loss_pct);
+      };
+
+      // Store chunk data for this channel
+      if channel_values.len() >= EEG_CHUNK_SIZE {
+        self.chunks.eeg_chunks[channel_idx].copy_from_slice(&channel_values[..EEG_CHUNK_SIZE]);
+      }
+
+      // Check if this is the last channel (AUX = index 4, or TP10 = index 3
+      // on presets without AUX, where AUX stays 0)
+      let last_channel = if self.eeg_aux.load(Ordering::Relaxed) {
+        4
+      } else {
+        3
+      };
+      if channel_idx == last_channel {
+        // Each packet lost on the closing channel is a chunk that never got pushed
+        self.eeg_index += missed as u64 * EEG_CHUNK_SIZE as u64;
+        // Push all samples for this chunk
+        for sample_idx in 0..EEG_CHUNK_SIZE {
+          let sample: [f32; 5] = [
+            self.chunks.eeg_chunks[0][sample_idx] as f32,
+            self.chunks.eeg_chunks[1][sample_idx] as f32,
+            self.chunks.eeg_chunks[2][sample_idx] as f32,
+            self.chunks.eeg_chunks[3][sample_idx] as f32,
+            self.chunks.eeg_chunks[4][sample_idx] as f32,
+          ];
+          self.send(TimestampedData {
+            data: DataType::Eeg(sample),
+            timestamp: chunk_sample_time(receipt_time, sample_idx, EEG_CHUNK_SIZE, EEG_SAMPLE_RATE),
+            last_in_chunk: sample_idx == EEG_CHUNK_SIZE - 1,
+            index: self.eeg_index,
+          })?;
+          self.eeg_index += 1;
+        }
+        self.chunks.reset_eeg();
+      }
+    } else if let Some(channel_idx) = PPG_UUIDS.iter().position(|&uuid| uuid == char_uuid) {
+      let mut missed = 0;
+      if let Some(sequence) = packet_sequence(data) {
+        missed = self.ppg_sequences[channel_idx].observe(sequence);
+      }
+
+      // Handle PPG data - decode 24-bit values
+      let Ok(decoded_values) =
+        parse_ppg_data(data, self.options.ppg_header_bytes, self.options.ppg_signed)
+      else {
+        (self.events)(StreamEvent::MalformedPacket {
+          uuid: char_uuid,
+          len: data.len(),
+        });
+        return Ok(loss_pct);
+      };
+
+      // Store chunk data for this channel
+      if decoded_values.len() >= PPG_CHUNK_SIZE {
+        self.chunks.ppg_chunks[channel_idx].copy_from_slice(&decoded_values[..PPG_CHUNK_SIZE]);
+      }
+
+      // Check if this is the last channel (RED = index 2)
+      if channel_idx == 2 {
+        self.ppg_index += missed as u64 * PPG_CHUNK_SIZE as u64;
+        // Push all samples for this chunk
+        for sample_idx in 0..PPG_CHUNK_SIZE {
+          let sample: [f32; 3] = [
+            self.chunks.ppg_chunks[0][sample_idx],
+            self.chunks.ppg_chunks[1][sample_idx],
+            self.chunks.ppg_chunks[2][sample_idx],
+          ];
+          self.send(TimestampedData {
+            data: DataType::Ppg(sample),
+            timestamp: chunk_sample_time(receipt_time, sample_idx, PPG_CHUNK_SIZE, PPG_SAMPLE_RATE),
+            last_in_chunk: sample_idx == PPG_CHUNK_SIZE - 1,
+            index: self.ppg_index,
+          })?;
+          self.ppg_index += 1;
+        }
+        self.chunks.reset_ppg();
+      }
+    } else if char_uuid == TELEMETRY_UUID {
+      match parse_telemetry(data) {
+        Some(telemetry) => (self.events)(StreamEvent::Telemetry(telemetry)),
+        None => (self.events)(StreamEvent::MalformedPacket {
+          uuid: char_uuid,
+          len: data.len(),
+        }),
+      }
+    } else if IMU_UUIDS.contains(&char_uuid) {
+      let accelerometer = char_uuid == ACCELEROMETER_UUID;
+      let scale = if accelerometer {
+        ACCELEROMETER_SCALE
+      } else {
+        GYRO_SCALE
+      };
+      let Ok(samples) = parse_imu_data(data, scale) else {
+        (self.events)(StreamEvent::MalformedPacket {
+          uuid: char_uuid,
+          len: data.len(),
+        });
+        return Ok(loss_pct);
+      };
+
+      // Unlike EEG and PPG, one packet carries every axis of several samples
+      let count = samples.len();
+      for (sample_idx, sample) in samples.into_iter().enumerate() {
+        let (data, index) = if accelerometer {
+          (
+            DataType::Accelerometer(sample),
+            &mut self.accelerometer_index,
+          )
+        } else {
+          (DataType::Gyro(sample), &mut self.gyro_index)
+        };
+        let sample = TimestampedData {
+          data,
+          timestamp: chunk_sample_time(receipt_time, sample_idx, count, IMU_SAMPLE_RATE),
+          last_in_chunk: sample_idx == count - 1,
+          index: *index,
+        };
+        *index += 1;
+        self.send(sample)?;
+      }
+    }
+    Ok(loss_pct)
+  }
+
+  fn send(&self, sample: TimestampedData) -> std::result::Result<(), SinkClosed> {
+    self.tx.send(sample).map_err(|_| SinkClosed)
+  }
+}
+
+/// What the notification task does when its notification stream ends
+#[derive(Debug, PartialEq)]
+enum StreamEnd {
+  /// Streaming was stopped, so the end was expected
+  Finished,
+  /// The platform dropped the link under a running stream
+  Reconnect,
+  /// It ended again within `RELINK_COOLDOWN` of the last reconnect
+  GiveUp,
+}
+
+fn stream_end(streaming: bool, last_relink: Option<Instant>) -> StreamEnd {
+  if !streaming {
+    StreamEnd::Finished
+  } else if last_relink.is_some_and(|at| at.elapsed() < RELINK_COOLDOWN) {
+    StreamEnd::GiveUp
+  } else {
+    StreamEnd::Reconnect
+  }
+}
+
+/// Stops forwarding after the notification stream couldn't be
+/// re-acquired, reporting `reason` as `StreamEvent::RelinkFailed`
+/// Aborts the notification task and drops the connector's sender: once the
+/// task's own clone goes with it, the LSL thread sees the channel close and
+/// finishes
+fn end_notification_task(
+  task: &mut Option<JoinHandle<()>>,
+  data_tx: &mut Option<mpsc::Sender<TimestampedData>>,
+) {
+  if let Some(task) = task.take() {
+    task.abort();
+  }
+  *data_tx = None;
+}
+
+fn fail_relink(streaming: &StreamingFlag, events: &StreamEvents, reason: String) {
+  streaming.set(false);
+  events(StreamEvent::RelinkFailed(reason));
+}
+
+/// Writes a control command from the notification task, which has no
+/// access to the connector's characteristic map
+async fn write_command<P: Peripheral>(device: &P, cmd: &[u8], status: &LinkStatus) -> Result<()> {
+  let control_char =
+    find_characteristic(device, CONTROL_UUID).ok_or("Control characteristic not found")?;
+  device
+    .write(
+      &control_char,
+      &encode_command(cmd),
+      btleplug::api::WriteType::WithoutResponse,
+    )
+    .await?;
+  status.command_succeeded();
+  Ok(())
+}
+
+/// Best-effort halt from the notification task
+async fn halt_device<P: Peripheral>(device: &P, status: &LinkStatus) {
+  if let Err(e) = write_command(device, b"h", status).await {
+    eprintln!("Failed to halt device: {}", e);
+  }
+}
+
+/// Drops and re-establishes the BLE link, then resumes streaming on
+/// `preset`. The sample channel is untouched, so the LSL outlets stay open
+async fn relink<P: Peripheral>(
+  device: &P,
+  preset: &str,
+  command_gap: Duration,
+  status: &LinkStatus,
+  imu: bool,
+) -> Result<()> {
+  let _ = device.disconnect().await; // Already dropped links are fine
+  device.connect().await?;
+  device.discover_services().await?;
+
+  for char in device.characteristics() {
+    if is_streamed(char.uuid, imu) {
+      device.subscribe(&char).await?;
+    }
+  }
+  send_start_sequence(device, preset, command_gap, status).await
+}
+
+/// The start sequence, from the notification task
+async fn send_start_sequence<P: Peripheral>(
+  device: &P,
+  preset: &str,
+  command_gap: Duration,
+  status: &LinkStatus,
+) -> Result<()> {
+  for (i, command) in ["h", preset, "s", "d"].into_iter().enumerate() {
+    if i > 0 {
+      tokio::time::sleep(command_gap).await;
+    }
+    write_command(device, command.as_bytes(), status).await?;
+  }
+  Ok(())
+}
+
+/// `relink`, then a fresh notification stream for the new link
+async fn resume_link<P: Peripheral>(
+  device: &P,
+  preset: &str,
+  command_gap: Duration,
+  status: &LinkStatus,
+  imu: bool,
+) -> Result<NotificationStream> {
+  relink(device, preset, command_gap, status, imu).await?;
+  Ok(device.notifications().await?)
+}
+
+/// Looks a characteristic up among everything found during discovery
+fn find_characteristic<P: Peripheral>(device: &P, uuid: Uuid) -> Option<Characteristic> {
+  device
+    .characteristics()
+    .into_iter()
+    .find(|char| char.uuid == uuid)
+}
+
+/// Back-dates a chunk's samples at the nominal rate from the receipt time of
+/// the packet that completed it, so EEG and PPG are anchored to the same
+/// receipt clock. Accuracy is bounded by the BLE connection interval jitter
+/// (typically within ~15 ms) rather than by when each stream was pushed
+fn chunk_sample_time(receipt_time: f64, sample_idx: usize, chunk_size: usize, rate: f64) -> f64 {
+  receipt_time - (chunk_size - 1 - sample_idx) as f64 / rate
+}
+
+/// Frames a command as `encode_command` would write it, for comparing
+/// against other implementations. Commands are printable ASCII without
+/// spaces, short enough to go out in one write at the default MTU
+pub fn checked_encode_command(cmd: &str) -> Result<Vec<u8>> {
+  if cmd.is_empty() {
+    return Err("Command is empty".into());
+  }
+  if let Some(c) = cmd.chars().find(|c| !c.is_ascii_graphic()) {
+    return Err(
+      format!(
+        "Command {:?} contains {:?}, only printable ASCII is sent",
+        cmd, c
+      )
+      .into(),
+    );
+  }
+  if cmd.len() > MAX_COMMAND_BYTES {
+    return Err(
+      format!(
+        "Command {:?} is longer than {} bytes",
+        cmd, MAX_COMMAND_BYTES
+      )
+      .into(),
+    );
+  }
+  Ok(encode_command(cmd.as_bytes()))
+}
+
+/// Frames a control command like the TypeScript implementation: X{cmd}\n
+fn encode_command(cmd: &[u8]) -> Vec<u8> {
+  let mut buffer = Vec::with_capacity(cmd.len() + 2);
+  buffer.push(b'X');
+  buffer.extend_from_slice(cmd);
+  buffer.push(b'\n');
+
+  // Set first byte to length - 1 (like TypeScript encoded[0] = encoded.length - 1)
+  buffer[0] = (buffer.len() - 1) as u8;
+  buffer
+}
+
+/// Whether notifications from `uuid` are decoded (or, for the control
+/// characteristic, read as command responses)
+fn is_handled(uuid: Uuid) -> bool {
+  EEG_UUIDS.contains(&uuid)
+    || PPG_UUIDS.contains(&uuid)
+    || IMU_UUIDS.contains(&uuid)
+    || uuid == TELEMETRY_UUID
+    || uuid == CONTROL_UUID
+}
+
+/// Whether a device named `device_name` with `characteristics` has the
+/// sleep presets: only the Muse S does, and they rely on its PPG sensor
+fn has_sleep_presets(device_name: &str, characteristics: &BTreeSet<Characteristic>) -> bool {
+  device_name.starts_with("MuseS")
+    && PPG_UUIDS
+      .iter()
+      .all(|uuid| characteristics.iter().any(|char| char.uuid == *uuid))
+}
+
+/// Whether `uuid` is subscribed to while streaming: samples (the IMU only
+/// with `imu`) and telemetry
+fn is_streamed(uuid: Uuid, imu: bool) -> bool {
+  EEG_UUIDS.contains(&uuid)
+    || PPG_UUIDS.contains(&uuid)
+    || (imu && IMU_UUIDS.contains(&uuid))
+    || uuid == TELEMETRY_UUID
+}
+
+/// Names (as in `KNOWN_UUIDS`) of the Muse service and `REQUIRED_UUIDS`
+/// characteristics that a discovered GATT table lacks
+fn missing_characteristics(services: &BTreeSet<Service>) -> Vec<&'static str> {
+  let muse_service = services
+    .iter()
+    .find(|service| service.uuid == MUSE_SERVICE_UUID);
+  KNOWN_UUIDS
+    .iter()
+    .filter(|(_, uuid)| match muse_service {
+      Some(service) => {
+        REQUIRED_UUIDS.contains(uuid)
+          && !service
+            .characteristics
+            .iter()
+            .any(|char| char.uuid == *uuid)
+      }
+      None => *uuid == MUSE_SERVICE_UUID || REQUIRED_UUIDS.contains(uuid),
+    })
+    .map(|(name, _)| *name)
+    .collect()
+}
+
+/// The 16-bit sequence counter at the start of each data packet
+fn packet_sequence(data: &[u8]) -> Option<u16> {
+  Some(u16::from_be_bytes([*data.first()?, *data.get(1)?]))
+}
+
+/// Picks where the samples start, falling back to the default header size
+/// when the configured one would leave no payload
+fn payload_offset(data: &[u8], header_bytes: usize) -> Option<usize> {
+  if header_bytes < data.len() {
+    Some(header_bytes)
+  } else if DEFAULT_HEADER_BYTES < data.len() {
+    Some(DEFAULT_HEADER_BYTES)
+  } else {
+    None
+  }
+}
+
+fn parse_eeg_data(data: &[u8], header_bytes: usize) -> Result<Vec<u8>> {
+  // EEG data: skip the header like TypeScript (Array.from(data).slice(2))
+  let offset = payload_offset(data, header_bytes).ok_or("EEG data too short")?;
+  Ok(data[offset..].to_vec())
+}
+
+fn parse_ppg_data(data: &[u8], header_bytes: usize, signed: bool) -> Result<Vec<f32>> {
+  // PPG data: skip the header, then decode as 24-bit integers
+  let offset = payload_offset(data, header_bytes).ok_or("PPG data too short")?;
+  let channel_values = &data[offset..];
+  // A remainder means the header offset is wrong or the packet is corrupt,
+  // so none of the samples can be trusted
+  if !channel_values.len().is_multiple_of(3) {
+    return Err(
+      format!(
+        "PPG payload of {} bytes isn't whole 24-bit samples",
+        channel_values.len()
+      )
+      .into(),
+    );
+  }
+  if signed {
+    decode_signed_24_bit_data(channel_values)
+  } else {
+    decode_unsigned_24_bit_data(channel_values)
+  }
+}
+
+/// Decodes a telemetry packet: after the sequence counter come the battery
+/// (1/512 %), the fuel gauge (2.2 mV), an ADC reading and the temperature,
+/// all big-endian u16
+fn parse_telemetry(data: &[u8]) -> Option<Telemetry> {
+  let field = |i: usize| -> Option<u16> {
+    let offset = DEFAULT_HEADER_BYTES + i * 2;
+    Some(u16::from_be_bytes([
+      *data.get(offset)?,
+      *data.get(offset + 1)?,
+    ]))
+  };
+  Some(Telemetry {
+    battery_percent: field(0)? as f64 / 512.0,
+    fuel_gauge_millivolts: field(1)? as f64 * 2.2,
+    adc_voltage: field(2)?.into(),
+    temperature: field(3)?.into(),
+  })
+}
+
+/// Decodes an IMU packet's samples, X, Y and Z each, scaled to their unit
+fn parse_imu_data(data: &[u8], scale: f32) -> Result<Vec<[f32; 3]>> {
+  let payload = data
+    .get(DEFAULT_HEADER_BYTES..)
+    .ok_or("IMU data too short")?;
+  if payload.len() != IMU_SAMPLES_PER_PACKET * 6 {
+    return Err(
+      format!(
+        "IMU payload of {} bytes isn't {} samples of three 16-bit axes",
+        payload.len(),
+        IMU_SAMPLES_PER_PACKET
+      )
+      .into(),
+    );
+  }
+  Ok(
+    payload
+      .chunks_exact(6)
+      .map(|sample| {
+        let axis = |i: usize| i16::from_be_bytes([sample[i * 2], sample[i * 2 + 1]]) as f32 * scale;
+        [axis(0), axis(1), axis(2)]
+      })
+      .collect(),
+  )
+}
+
+fn decode_signed_24_bit_data(samples: &[u8]) -> Result<Vec<f32>> {
+  // Move bit 23 into the i32 sign bit, then shift back to sign-extend
+  // (0x800000 -> -8388608, 0xFFFFFF -> -1)
+  Ok(
+    read_24_bit_words(samples)
+      .map(|val| ((val << 8) as i32 >> 8) as f32)
+      .collect(),
+  )
+}
+
+/// The 24-bit word a decoded PPG value came from, with either decoding:
+/// unsigned values are the word itself, and signed ones wrap back to it
+/// (-1 -> 0xFFFFFF). Exact, since 24-bit integers fit an f32 mantissa
+pub fn raw_24_bit_word(value: f32) -> u32 {
+  (value as i32 as u32) & 0xFF_FFFF
 }
 
 fn decode_unsigned_24_bit_data(samples: &[u8]) -> Result<Vec<f32>> {
-  let mut decoded_samples = Vec::new();
+  Ok(read_24_bit_words(samples).map(|val| val as f32).collect())
+}
+
+fn read_24_bit_words(samples: &[u8]) -> impl Iterator<Item = u32> + '_ {
   let num_bytes_per_sample = 3;
 
-  for chunk in samples.chunks(num_bytes_per_sample) {
-    if chunk.len() == num_bytes_per_sample {
-      let most_significant_byte = (chunk[0] as u32) << 16;
-      let middle_byte = (chunk[1] as u32) << 8;
-      let least_significant_byte = chunk[2] as u32;
+  samples.chunks_exact(num_bytes_per_sample).map(|chunk| {
+    let most_significant_byte = (chunk[0] as u32) << 16;
+    let middle_byte = (chunk[1] as u32) << 8;
+    let least_significant_byte = chunk[2] as u32;
+
+    most_significant_byte | middle_byte | least_significant_byte
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// A PPG packet: the sequence counter, then `payload`
+  fn ppg_packet(payload: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0x00, 0x01];
+    packet.extend_from_slice(payload);
+    packet
+  }
+
+  #[test]
+  fn ppg_exact_length_decodes_every_sample() {
+    let payload: Vec<u8> = (1..=18).collect();
+    let values = parse_ppg_data(&ppg_packet(&payload), DEFAULT_HEADER_BYTES, false).unwrap();
+    assert_eq!(values.len(), PPG_CHUNK_SIZE);
+    assert_eq!(values[0], 0x010203 as f32);
+    assert_eq!(values[5], 0x101112 as f32);
+  }
+
+  #[test]
+  fn ppg_one_byte_short_is_malformed() {
+    let payload: Vec<u8> = (1..=17).collect();
+    let err = parse_ppg_data(&ppg_packet(&payload), DEFAULT_HEADER_BYTES, false).unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "PPG payload of 17 bytes isn't whole 24-bit samples"
+    );
+  }
+
+  #[test]
+  fn ppg_trailing_byte_is_malformed() {
+    let payload: Vec<u8> = (1..=19).collect();
+    for signed in [false, true] {
+      let err = parse_ppg_data(&ppg_packet(&payload), DEFAULT_HEADER_BYTES, signed).unwrap_err();
+      assert_eq!(
+        err.to_string(),
+        "PPG payload of 19 bytes isn't whole 24-bit samples"
+      );
+    }
+  }
+
+  #[test]
+  fn ppg_signed_sign_extends_bit_23() {
+    let words = [0x7F, 0xFF, 0xFF, 0x80, 0x00, 0x00, 0xFF, 0xFF, 0xFF];
+    assert_eq!(
+      decode_signed_24_bit_data(&words).unwrap(),
+      vec![8_388_607.0, -8_388_608.0, -1.0]
+    );
+    assert_eq!(
+      decode_unsigned_24_bit_data(&words).unwrap(),
+      vec![8_388_607.0, 8_388_608.0, 16_777_215.0]
+    );
+  }
+
+  #[test]
+  fn raw_24_bit_word_round_trips_both_decodings() {
+    for word in [0u32, 0x7F_FFFF, 0x80_0000, 0xFF_FFFF] {
+      let bytes = [(word >> 16) as u8, (word >> 8) as u8, word as u8];
+      let signed = decode_signed_24_bit_data(&bytes).unwrap()[0];
+      let unsigned = decode_unsigned_24_bit_data(&bytes).unwrap()[0];
+      assert_eq!(raw_24_bit_word(signed), word, "signed {:#x}", word);
+      assert_eq!(raw_24_bit_word(unsigned), word, "unsigned {:#x}", word);
+    }
+  }
+
+  fn handler() -> (NotificationHandler, mpsc::Receiver<TimestampedData>) {
+    let (tx, rx) = mpsc::channel();
+    let handler = NotificationHandler::new(
+      tx,
+      BleOptions::default(),
+      Arc::new(AtomicBool::new(false)),
+      Arc::new(|_| {}),
+    );
+    (handler, rx)
+  }
+
+  /// An EEG packet whose 12 samples all read `value`
+  fn eeg_packet(sequence: u16, value: u8) -> Vec<u8> {
+    let mut packet = sequence.to_be_bytes().to_vec();
+    packet.extend_from_slice(&[value; 18]);
+    packet
+  }
+
+  /// Delivers one notification the way the notification task does
+  fn deliver(handler: &mut NotificationHandler, uuid: Uuid, data: &[u8]) {
+    if !handler.is_duplicate(uuid, data) {
+      assert!(handler.handle(uuid, data, 1.0).is_ok());
+    }
+  }
+
+  /// Delivers an EEG packet with `sequence` on TP9 through TP10
+  fn deliver_eeg_chunk(handler: &mut NotificationHandler, sequence: u16) {
+    for &uuid in &EEG_UUIDS[..4] {
+      deliver(handler, uuid, &eeg_packet(sequence, 100));
+    }
+  }
+
+  #[test]
+  fn repeated_sequence_emits_one_chunk() {
+    let (mut handler, rx) = handler();
+    deliver_eeg_chunk(&mut handler, 7);
+    deliver_eeg_chunk(&mut handler, 7);
+    assert_eq!(rx.try_iter().count(), EEG_CHUNK_SIZE);
+
+    // The counter wrapping over is a new packet, not a repeat
+    deliver_eeg_chunk(&mut handler, 0xFFFF);
+    deliver_eeg_chunk(&mut handler, 0x0000);
+    deliver_eeg_chunk(&mut handler, 0x0000);
+    assert_eq!(rx.try_iter().count(), 2 * EEG_CHUNK_SIZE);
+  }
+
+  #[test]
+  fn sample_index_counts_dropped_chunks() {
+    let (mut handler, rx) = handler();
+    deliver_eeg_chunk(&mut handler, 1);
+    // Packet 2 never arrives
+    deliver_eeg_chunk(&mut handler, 3);
+    let eeg: Vec<u64> = rx.try_iter().map(|sample| sample.index).collect();
+    let expected: Vec<u64> = (0..12).chain(24..36).collect();
+    assert_eq!(eeg, expected);
+
+    for sequence in [10u16, 11, 13] {
+      for &uuid in &PPG_UUIDS {
+        let mut packet = sequence.to_be_bytes().to_vec();
+        packet.extend_from_slice(&[0; 18]);
+        deliver(&mut handler, uuid, &packet);
+      }
+    }
+    let ppg: Vec<u64> = rx.try_iter().map(|sample| sample.index).collect();
+    let expected: Vec<u64> = (0..12).chain(18..24).collect();
+    assert_eq!(ppg, expected);
+  }
+
+  /// An IMU packet carrying `samples`, each X, Y and Z
+  fn imu_packet(samples: &[[i16; 3]]) -> Vec<u8> {
+    let mut packet = vec![0x00, 0x01];
+    for axis in samples.iter().flatten() {
+      packet.extend_from_slice(&axis.to_be_bytes());
+    }
+    packet
+  }
+
+  #[test]
+  fn imu_packet_carries_three_samples() {
+    let packet = imu_packet(&[[1, 2, 3], [4, 5, 6], [-7, -8, -9]]);
+    let samples = parse_imu_data(&packet, 1.0).unwrap();
+    assert_eq!(
+      samples,
+      vec![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [-7.0, -8.0, -9.0]]
+    );
+  }
+
+  #[test]
+  fn imu_packet_of_the_wrong_length_is_malformed() {
+    let mut packet = imu_packet(&[[0; 3]; 3]);
+    packet.pop();
+    assert_eq!(
+      parse_imu_data(&packet, 1.0).unwrap_err().to_string(),
+      "IMU payload of 17 bytes isn't 3 samples of three 16-bit axes"
+    );
+    assert!(parse_imu_data(&imu_packet(&[[0; 3]; 4]), 1.0).is_err());
+    assert!(parse_imu_data(&[0x00], 1.0).is_err());
+  }
+
+  #[test]
+  fn imu_full_scale_readings() {
+    let packet = imu_packet(&[[i16::MAX, -i16::MAX, 0x4000]; 3]);
+    let accelerometer = parse_imu_data(&packet, ACCELEROMETER_SCALE).unwrap();
+    assert_eq!(
+      accelerometer[0],
+      [
+        32767.0 * ACCELEROMETER_SCALE,
+        -32767.0 * ACCELEROMETER_SCALE,
+        16384.0 * ACCELEROMETER_SCALE
+      ]
+    );
+    assert!((accelerometer[0][0] - 2.0).abs() < 1e-3);
+    assert!((accelerometer[0][2] - 1.0).abs() < 1e-6);
+
+    let gyro = parse_imu_data(&packet, GYRO_SCALE).unwrap();
+    assert_eq!(gyro[2][1], -32767.0 * GYRO_SCALE);
+    assert!((gyro[2][0] - 245.0).abs() < 0.01);
+  }
+
+  /// A GATT table with the Muse service holding `characteristics`
+  fn gatt_table(characteristics: &[Uuid]) -> BTreeSet<Service> {
+    let characteristics = characteristics
+      .iter()
+      .map(|&uuid| Characteristic {
+        uuid,
+        service_uuid: MUSE_SERVICE_UUID,
+        properties: CharPropFlags::NOTIFY,
+        descriptors: BTreeSet::new(),
+      })
+      .collect();
+    BTreeSet::from([Service {
+      uuid: MUSE_SERVICE_UUID,
+      primary: true,
+      characteristics,
+    }])
+  }
+
+  #[test]
+  fn full_gatt_table_is_missing_nothing() {
+    let mut uuids = REQUIRED_UUIDS.to_vec();
+    uuids.extend(PPG_UUIDS);
+    assert!(missing_characteristics(&gatt_table(&uuids)).is_empty());
+  }
+
+  #[test]
+  fn missing_characteristics_are_named() {
+    let table = gatt_table(&[CONTROL_UUID, EEG_TP9_UUID, EEG_TP10_UUID]);
+    assert_eq!(missing_characteristics(&table), vec!["eeg_af7", "eeg_af8"]);
+  }
+
+  #[test]
+  fn gatt_table_without_the_muse_service() {
+    let mut table = gatt_table(&REQUIRED_UUIDS);
+    let mut other = table.pop_first().unwrap();
+    other.uuid = uuid!("0000180f-0000-1000-8000-00805f9b34fb");
+    table.insert(other);
+    assert_eq!(
+      missing_characteristics(&table),
+      vec![
+        "service", "control", "eeg_tp9", "eeg_af7", "eeg_af8", "eeg_tp10"
+      ]
+    );
+    assert_eq!(missing_characteristics(&BTreeSet::new()).len(), 6);
+  }
+
+  #[test]
+  fn eeg_chunk_closes_on_aux_only_when_present() {
+    let (mut handler, rx) = handler();
+    deliver_eeg_chunk(&mut handler, 1);
+    let chunk: Vec<TimestampedData> = rx.try_iter().collect();
+    assert_eq!(chunk.len(), EEG_CHUNK_SIZE);
+    // Without AUX its slot stays 0, and the outlet truncates it away
+    assert!(chunk.iter().all(|sample| matches!(
+      sample.data,
+      DataType::Eeg([100.0, 100.0, 100.0, 100.0, 0.0])
+    )));
+
+    handler.eeg_aux.store(true, Ordering::Relaxed);
+    deliver_eeg_chunk(&mut handler, 2);
+    assert_eq!(rx.try_iter().count(), 0);
+    deliver(&mut handler, EEG_AUX_UUID, &eeg_packet(2, 7));
+    let chunk: Vec<TimestampedData> = rx.try_iter().collect();
+    assert_eq!(chunk.len(), EEG_CHUNK_SIZE);
+    assert!(
+      chunk
+        .iter()
+        .all(|sample| matches!(sample.data, DataType::Eeg([.., 7.0])))
+    );
+  }
 
-      let val = most_significant_byte | middle_byte | least_significant_byte;
-      decoded_samples.push(val as f32);
+  #[test]
+  fn channel_that_never_fires_is_silent() {
+    let (mut handler, _rx) = handler();
+    assert_eq!(handler.silent_eeg_channels(), vec![0, 1, 2, 3, 4]);
+    for sequence in 1..4 {
+      for uuid in [EEG_TP9_UUID, EEG_AF7_UUID, EEG_TP10_UUID, EEG_AUX_UUID] {
+        deliver(&mut handler, uuid, &eeg_packet(sequence, 100));
+      }
     }
+    assert_eq!(handler.silent_eeg_channels(), vec![2]);
   }
 
-  Ok(decoded_samples)
+  #[test]
+  fn stream_ending_while_streaming_reconnects_once() {
+    assert_eq!(stream_end(false, None), StreamEnd::Finished);
+    assert_eq!(stream_end(true, None), StreamEnd::Reconnect);
+    assert_eq!(stream_end(true, Some(Instant::now())), StreamEnd::GiveUp);
+    let long_ago = Instant::now().checked_sub(RELINK_COOLDOWN * 2);
+    if let Some(long_ago) = long_ago {
+      assert_eq!(stream_end(true, Some(long_ago)), StreamEnd::Reconnect);
+    }
+  }
+
+  #[test]
+  fn failed_notification_acquisition_stops_the_stream() {
+    let streaming = StreamingFlag::default();
+    streaming.set(true);
+    let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let events: StreamEvents = {
+      let reported = reported.clone();
+      Arc::new(move |event| reported.lock().unwrap().push(event))
+    };
+
+    fail_relink(
+      &streaming,
+      &events,
+      "Failed to reconnect: Device not connected".to_string(),
+    );
+    assert!(!streaming.get());
+    let reported = reported.lock().unwrap();
+    assert!(matches!(
+      reported.as_slice(),
+      [StreamEvent::RelinkFailed(reason)] if reason == "Failed to reconnect: Device not connected"
+    ));
+  }
+
+  #[test]
+  fn resync_reapplies_the_intended_preset() {
+    // The device drifted onto another preset mid-stream; resync forces
+    // the intended one, waits for a status answer, then resumes
+    assert_eq!(resync_commands("p50", true), vec!["h", "p50", "s", "d"]);
+    assert_eq!(resync_commands("p21", false), vec!["h", "p21", "s"]);
+  }
+
+  #[test]
+  fn status_answer_is_reassembled_from_fragments() {
+    let mut response = ControlResponse::default();
+    assert_eq!(response.push(b"\x0a{\"hn\":\"Mus"), None);
+    assert_eq!(response.push(b"\x0ae-1234\",\"b"), None);
+    let json = response.push(b"\x06p\":82}xxxx").unwrap();
+    assert_eq!(json, "{\"hn\":\"Muse-1234\",\"bp\":82}");
+    assert_eq!(json_number(&json, "bp"), Some(82.0));
+    assert_eq!(json_string(&json, "hn").as_deref(), Some("Muse-1234"));
+  }
+
+  #[test]
+  fn handler_reports_a_sink_that_exited_early() {
+    let (mut handler, rx) = handler();
+    drop(rx);
+    // Nothing is sent until a chunk completes
+    for &uuid in &EEG_UUIDS[..3] {
+      assert!(handler.handle(uuid, &eeg_packet(1, 100), 1.0).is_ok());
+    }
+    assert!(
+      handler
+        .handle(EEG_TP10_UUID, &eeg_packet(1, 100), 1.0)
+        .is_err()
+    );
+  }
+
+  #[test]
+  fn only_presets_with_the_same_sensors_share_a_layout() {
+    let layout = |preset| preset_layout(preset).unwrap();
+    assert_eq!(layout("p50"), layout("p1034"));
+    assert_eq!(layout("p50"), layout("p1035"));
+    // PPG or AUX turning on or off changes the layout
+    assert_ne!(layout("p50"), layout("p20"));
+    assert_ne!(layout("p50"), layout("p51"));
+    assert_ne!(layout("p21"), layout("p51"));
+    assert_ne!(layout("p20"), layout("p21"));
+    assert_eq!(preset_layout("p99"), None);
+  }
+
+  #[test]
+  fn scan_all_drops_the_service_filter() {
+    assert_eq!(muse_scan_filter(false).services, vec![MUSE_SERVICE_UUID]);
+    assert!(muse_scan_filter(true).services.is_empty());
+  }
+
+  #[tokio::test]
+  async fn ending_the_task_drops_every_sender() {
+    // As after a failed start: the task holds its own sender and never
+    // gets to run to completion by itself
+    let (tx, rx) = mpsc::channel::<TimestampedData>();
+    let task_tx = tx.clone();
+    let mut task = Some(tokio::spawn(async move {
+      let _task_tx = task_tx;
+      std::future::pending::<()>().await;
+    }));
+    let mut data_tx = Some(tx);
+
+    end_notification_task(&mut task, &mut data_tx);
+    assert!(task.is_none() && data_tx.is_none());
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(matches!(
+      rx.try_recv(),
+      Err(mpsc::TryRecvError::Disconnected)
+    ));
+  }
+
+  #[test]
+  fn latency_is_the_median_over_runs() {
+    // One slow run (e.g. a retransmit) doesn't move the result
+    assert_eq!(median(vec![41.0, 38.5, 250.0, 40.0, 39.0]), Some(40.0));
+    assert_eq!(median(vec![12.0, 10.0]), Some(12.0));
+    assert_eq!(median(vec![]), None);
+  }
+
+  #[tokio::test]
+  async fn ping_answers_false_when_the_device_stays_silent() {
+    let wait = Duration::from_millis(20);
+    let notification = |uuid, value: &[u8]| ValueNotification {
+      uuid,
+      value: value.to_vec(),
+    };
+
+    // Sample notifications don't count as an answer
+    let mut silent = futures::stream::iter([notification(EEG_TP9_UUID, &[0; 20])])
+      .chain(futures::stream::pending());
+    assert!(!control_answered(&mut silent, wait).await);
+    // Nor does half a response
+    let mut cut_off = futures::stream::iter([notification(CONTROL_UUID, b"\x05{\"hn\"")])
+      .chain(futures::stream::pending());
+    assert!(!control_answered(&mut cut_off, wait).await);
+
+    let mut answering = futures::stream::iter([
+      notification(CONTROL_UUID, b"\x05{\"hn\""),
+      notification(CONTROL_UUID, b"\x04:1}"),
+    ])
+    .chain(futures::stream::pending());
+    assert!(control_answered(&mut answering, wait).await);
+  }
+
+  #[test]
+  fn only_a_muse_s_with_ppg_has_the_sleep_presets() {
+    let characteristics = |uuids: &[Uuid]| gatt_table(uuids).pop_first().unwrap().characteristics;
+    let mut with_ppg = REQUIRED_UUIDS.to_vec();
+    with_ppg.extend(PPG_UUIDS);
+
+    assert!(has_sleep_presets("MuseS-1234", &characteristics(&with_ppg)));
+    assert!(!has_sleep_presets(
+      "MuseS-1234",
+      &characteristics(&REQUIRED_UUIDS)
+    ));
+    // A Muse 2 has PPG but no sleep presets
+    assert!(!has_sleep_presets("Muse-1234", &characteristics(&with_ppg)));
+  }
+
+  #[test]
+  fn soft_restart_renews_only_the_streamed_subscriptions() {
+    let mut uuids = vec![CONTROL_UUID, TELEMETRY_UUID];
+    uuids.extend(EEG_UUIDS);
+    uuids.extend(PPG_UUIDS);
+    uuids.extend(IMU_UUIDS);
+    let renewed = |imu| uuids.iter().filter(|&&uuid| is_streamed(uuid, imu)).count();
+    // EEG, PPG and telemetry; the control channel keeps its subscription
+    assert_eq!(renewed(false), EEG_UUIDS.len() + PPG_UUIDS.len() + 1);
+    assert_eq!(renewed(true), renewed(false) + IMU_UUIDS.len());
+    assert!(!is_streamed(CONTROL_UUID, true));
+  }
 }
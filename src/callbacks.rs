@@ -0,0 +1,386 @@
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+use napi_derive::napi;
+
+use crate::stats::RateWarning;
+
+pub type JsCallback<T> = ThreadsafeFunction<T, ErrorStrategy::Fatal>;
+
+/// A single named JS callback that can be fired from any thread.
+///
+/// Firing and removal take the same lock, so once `clear` returns nothing
+/// new is queued, and aborting the function drops calls already queued.
+pub struct CallbackSlot<T: 'static> {
+  name: &'static str,
+  callback: Mutex<Option<JsCallback<T>>>,
+}
+
+impl<T: 'static> CallbackSlot<T> {
+  pub fn new(name: &'static str) -> Self {
+    Self {
+      name,
+      callback: Mutex::new(None),
+    }
+  }
+
+  pub fn set(&self, callback: JsCallback<T>) {
+    if let Some(previous) = self.callback.lock().unwrap().replace(callback) {
+      let _ = previous.abort();
+    }
+  }
+
+  pub fn clear(&self) {
+    if let Some(previous) = self.callback.lock().unwrap().take() {
+      let _ = previous.abort();
+    }
+  }
+
+  /// The slot's name if a callback is registered
+  pub fn registered_name(&self) -> Option<&'static str> {
+    self.callback.lock().unwrap().as_ref().map(|_| self.name)
+  }
+
+  pub fn is_set(&self) -> bool {
+    self.callback.lock().unwrap().is_some()
+  }
+
+  pub fn call(&self, value: T) {
+    if let Some(callback) = self.callback.lock().unwrap().as_ref() {
+      callback.call(value, ThreadsafeFunctionCallMode::NonBlocking);
+    }
+  }
+}
+
+/// One sample from any sensor, as delivered to `onSample`
+#[napi(object)]
+#[derive(Debug, Clone)]
+pub struct Sample {
+  /// `"eeg"` (5 channels, 4 without AUX), `"ppg"` (3 channels, 2 with
+  /// `ppgDropAmbient`), or with `imu` on `"accel"` (x/y/z in g) and
+  /// `"gyro"` (x/y/z in deg/s)
+  pub kind: String,
+  pub timestamp: f64,
+  pub channels: Vec<f64>,
+}
+
+/// EEG samples aggregated for `onEegChunk`, oldest first
+#[napi(object)]
+#[derive(Debug, Clone, Default)]
+pub struct EegChunk {
+  pub timestamps: Vec<f64>,
+  pub samples: Vec<Vec<f64>>,
+}
+
+/// A notification from a characteristic the crate doesn't decode
+#[napi(object)]
+#[derive(Debug, Clone)]
+pub struct UnhandledNotification {
+  pub uuid: String,
+  pub data: Vec<u8>,
+}
+
+/// The verdict on one EEG epoch, as delivered to `onEpochQuality`
+#[napi(object)]
+#[derive(Debug, Clone)]
+pub struct EpochQuality {
+  /// When the epoch's first sample was taken
+  pub timestamp: f64,
+  pub clean: bool,
+  /// Labels of the channels that broke a threshold, empty when clean
+  pub artifact_channels: Vec<String>,
+}
+
+/// An outlet found on the network with the source_id this device's EEG
+/// outlet was about to use, as delivered to `onSourceIdConflict`
+#[napi(object)]
+#[derive(Debug, Clone)]
+pub struct SourceIdConflict {
+  pub source_id: String,
+  /// Host the other outlet runs on
+  pub hostname: String,
+  /// The source_id this device's EEG outlet uses instead, with
+  /// `disambiguateSourceIds`; undefined when it keeps the conflicting one
+  pub renamed_to: Option<String>,
+}
+
+/// Which sample a full `SampleQueue` gives up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+  DropOldest,
+  DropNewest,
+}
+
+#[derive(Default)]
+struct QueueState {
+  samples: VecDeque<Sample>,
+  ended: bool,
+  dropped: u64,
+}
+
+/// Samples waiting for a `sampleStream` reader, bounded by `capacity`
+pub struct SampleQueue {
+  kind: Option<String>,
+  capacity: usize,
+  overflow: Overflow,
+  state: Mutex<QueueState>,
+  ready: Notify,
+}
+
+impl SampleQueue {
+  /// Only samples of `kind` are queued, or every kind if `None`
+  pub fn new(kind: Option<String>, capacity: usize, overflow: Overflow) -> Self {
+    Self {
+      kind,
+      capacity,
+      overflow,
+      state: Mutex::new(QueueState::default()),
+      ready: Notify::new(),
+    }
+  }
+
+  /// Returns false once the queue has ended, so it can be let go
+  fn push(&self, sample: &Sample) -> bool {
+    let mut state = self.state.lock().unwrap();
+    if state.ended {
+      return false;
+    }
+    if self.kind.as_ref().is_some_and(|kind| *kind != sample.kind) {
+      return true;
+    }
+    if state.samples.len() >= self.capacity {
+      state.dropped += 1;
+      match self.overflow {
+        Overflow::DropNewest => return true,
+        Overflow::DropOldest => {
+          state.samples.pop_front();
+        }
+      }
+    }
+    state.samples.push_back(sample.clone());
+    drop(state);
+    self.ready.notify_one();
+    true
+  }
+
+  /// Drops what's queued if `discard`, otherwise lets the reader drain it
+  pub fn end(&self, discard: bool) {
+    let mut state = self.state.lock().unwrap();
+    state.ended = true;
+    if discard {
+      state.samples.clear();
+    }
+    drop(state);
+    self.ready.notify_one();
+  }
+
+  /// Samples discarded because the reader fell `capacity` behind
+  pub fn dropped(&self) -> u64 {
+    self.state.lock().unwrap().dropped
+  }
+
+  /// The next sample, or `None` once the queue has ended and is drained
+  pub async fn next(&self) -> Option<Sample> {
+    loop {
+      // Registered before checking, so a push in between isn't missed
+      let ready = self.ready.notified();
+      {
+        let mut state = self.state.lock().unwrap();
+        if let Some(sample) = state.samples.pop_front() {
+          return Some(sample);
+        }
+        if state.ended {
+          return None;
+        }
+      }
+      ready.await;
+    }
+  }
+}
+
+/// The open `sampleStream`s, fed alongside `onSample`
+#[derive(Default)]
+pub struct SampleStreams {
+  queues: Mutex<Vec<Arc<SampleQueue>>>,
+}
+
+impl SampleStreams {
+  pub fn add(&self, queue: Arc<SampleQueue>) {
+    self.queues.lock().unwrap().push(queue);
+  }
+
+  /// True when no stream is open
+  pub fn is_empty(&self) -> bool {
+    let mut queues = self.queues.lock().unwrap();
+    queues.retain(|queue| !queue.state.lock().unwrap().ended);
+    queues.is_empty()
+  }
+
+  pub fn push(&self, sample: &Sample) {
+    self
+      .queues
+      .lock()
+      .unwrap()
+      .retain(|queue| queue.push(sample));
+  }
+
+  /// Ends every open stream, after the samples already queued
+  pub fn end_all(&self) {
+    for queue in self.queues.lock().unwrap().drain(..) {
+      queue.end(false);
+    }
+  }
+}
+
+/// One step of a `SampleStream`, shaped like the async iterator protocol's
+/// results: `value` is undefined once `done`
+#[napi(object)]
+pub struct SampleIteration {
+  pub done: bool,
+  pub value: Option<Sample>,
+}
+
+/// The samples of one `sampleStream`, in order. It's an async iterable, so
+/// read it with `for await`, or wrap it in `Readable.from()` for an
+/// object-mode Node stream
+#[napi]
+pub struct SampleStream {
+  queue: Arc<SampleQueue>,
+}
+
+impl SampleStream {
+  pub fn new(queue: Arc<SampleQueue>) -> Self {
+    Self { queue }
+  }
+}
+
+#[napi]
+impl SampleStream {
+  /// Resolves with the next sample, waiting for one if none is queued, or
+  /// with `done` once the stream has ended and been drained
+  #[napi]
+  pub async fn next(&self) -> napi::Result<SampleIteration> {
+    let value = self.queue.next().await;
+    Ok(SampleIteration {
+      done: value.is_none(),
+      value,
+    })
+  }
+
+  /// Ends the stream and drops whatever is queued. `for await` calls this
+  /// when the loop is left early
+  #[napi(js_name = "return")]
+  pub fn close(&self) -> SampleIteration {
+    self.queue.end(true);
+    SampleIteration {
+      done: true,
+      value: None,
+    }
+  }
+
+  /// Samples discarded because the reader fell `bufferSamples` behind
+  #[napi(getter)]
+  pub fn dropped(&self) -> f64 {
+    self.queue.dropped() as f64
+  }
+}
+
+/// Every JS callback registered on a `MuseDevice`
+pub struct Callbacks {
+  pub eeg: CallbackSlot<Vec<f32>>,
+  pub ppg: CallbackSlot<Vec<f32>>,
+  pub sample: CallbackSlot<Sample>,
+  pub eeg_chunk: CallbackSlot<EegChunk>,
+  pub rate_warning: CallbackSlot<RateWarning>,
+  pub lsl_error: CallbackSlot<String>,
+  pub channel_silent: CallbackSlot<Vec<String>>,
+  pub loss_reconnect: CallbackSlot<f64>,
+  pub unhandled_notification: CallbackSlot<UnhandledNotification>,
+  pub state_change: CallbackSlot<String>,
+  pub consumer_connected: CallbackSlot<()>,
+  pub consumer_disconnected: CallbackSlot<()>,
+  pub auto_disconnect: CallbackSlot<String>,
+  pub ppg_raw: CallbackSlot<Vec<u32>>,
+  pub epoch_quality: CallbackSlot<EpochQuality>,
+  pub source_id_conflict: CallbackSlot<SourceIdConflict>,
+  pub sample_streams: SampleStreams,
+}
+
+impl Callbacks {
+  pub fn new() -> Self {
+    Self {
+      eeg: CallbackSlot::new("eeg"),
+      ppg: CallbackSlot::new("ppg"),
+      sample: CallbackSlot::new("sample"),
+      eeg_chunk: CallbackSlot::new("eeg_chunk"),
+      rate_warning: CallbackSlot::new("rate_warning"),
+      lsl_error: CallbackSlot::new("lsl_error"),
+      channel_silent: CallbackSlot::new("channel_silent"),
+      loss_reconnect: CallbackSlot::new("loss_reconnect"),
+      unhandled_notification: CallbackSlot::new("unhandled_notification"),
+      state_change: CallbackSlot::new("state_change"),
+      consumer_connected: CallbackSlot::new("consumer_connected"),
+      consumer_disconnected: CallbackSlot::new("consumer_disconnected"),
+      auto_disconnect: CallbackSlot::new("auto_disconnect"),
+      ppg_raw: CallbackSlot::new("ppg_raw"),
+      epoch_quality: CallbackSlot::new("epoch_quality"),
+      source_id_conflict: CallbackSlot::new("source_id_conflict"),
+      sample_streams: SampleStreams::default(),
+    }
+  }
+
+  /// Names of the callbacks currently registered
+  pub fn registered(&self) -> Vec<String> {
+    [
+      self.eeg.registered_name(),
+      self.ppg.registered_name(),
+      self.sample.registered_name(),
+      self.eeg_chunk.registered_name(),
+      self.rate_warning.registered_name(),
+      self.lsl_error.registered_name(),
+      self.channel_silent.registered_name(),
+      self.loss_reconnect.registered_name(),
+      self.unhandled_notification.registered_name(),
+      self.state_change.registered_name(),
+      self.consumer_connected.registered_name(),
+      self.consumer_disconnected.registered_name(),
+      self.auto_disconnect.registered_name(),
+      self.ppg_raw.registered_name(),
+      self.epoch_quality.registered_name(),
+      self.source_id_conflict.registered_name(),
+      (!self.sample_streams.is_empty()).then_some("sample_stream"),
+    ]
+    .into_iter()
+    .flatten()
+    .map(String::from)
+    .collect()
+  }
+
+  pub fn clear_all(&self) {
+    self.eeg.clear();
+    self.ppg.clear();
+    self.sample.clear();
+    self.eeg_chunk.clear();
+    self.rate_warning.clear();
+    self.lsl_error.clear();
+    self.channel_silent.clear();
+    self.loss_reconnect.clear();
+    self.unhandled_notification.clear();
+    self.state_change.clear();
+    self.consumer_connected.clear();
+    self.consumer_disconnected.clear();
+    self.auto_disconnect.clear();
+    self.ppg_raw.clear();
+    self.epoch_quality.clear();
+    self.source_id_conflict.clear();
+    self.sample_streams.end_all();
+  }
+}
+
+impl Default for Callbacks {
+  fn default() -> Self {
+    Self::new()
+  }
+}
@@ -1,9 +1,24 @@
+use napi_derive::napi;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct DeviceInfo {
     pub name: String,
     pub uuid: String,
 }
 
+/// The latest reading from the telemetry characteristic, which the Muse
+/// sends every few seconds while streaming
+#[napi(object)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Telemetry {
+    pub battery_percent: f64,
+    pub fuel_gauge_millivolts: f64,
+    /// Undocumented ADC reading, in raw counts
+    pub adc_voltage: u32,
+    /// In raw counts, the unit isn't documented
+    pub temperature: u32,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum ConnectionState {
     Disconnected,
@@ -16,28 +31,134 @@ pub enum StreamingState {
     Streaming,
 }
 
+/// Where the device is in its connect/stream lifecycle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LifecycleState {
+    Idle,
+    Scanning,
+    Connecting,
+    Connected,
+    Streaming,
+    /// Streaming, but the device is being restarted on a new preset
+    Paused,
+    /// Streaming, but the BLE link is being re-established
+    Reconnecting,
+    Disconnecting,
+    /// A connect failed or a stream stopped on its own, see `last_error`
+    Error,
+}
+
+impl LifecycleState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Idle => "idle",
+            Self::Scanning => "scanning",
+            Self::Connecting => "connecting",
+            Self::Connected => "connected",
+            Self::Streaming => "streaming",
+            Self::Paused => "paused",
+            Self::Reconnecting => "reconnecting",
+            Self::Disconnecting => "disconnecting",
+            Self::Error => "error",
+        }
+    }
+
+    pub fn can_become(self, next: Self) -> bool {
+        use LifecycleState::*;
+        match (self, next) {
+            // Failures can happen anywhere, and disconnecting always cleans up
+            (_, Error) | (_, Disconnecting) => self != next,
+            (Idle | Error, Scanning) => true,
+            (Scanning, Connecting) => true,
+            (Connecting, Connected) => true,
+            (Connected | Error, Streaming) => true,
+            (Streaming, Paused | Reconnecting) => true,
+            (Paused | Reconnecting, Streaming) => true,
+            (Streaming | Paused | Reconnecting | Error, Connected) => true,
+            (Disconnecting, Idle) => true,
+            _ => false,
+        }
+    }
+}
+
 pub struct DeviceStateManager {
+    lifecycle: LifecycleState,
     connection_state: ConnectionState,
     streaming_state: StreamingState,
+    sleep_mode_supported: bool,
+    hardware_revision: Option<String>,
+    telemetry: Option<Telemetry>,
+    last_error: Option<String>,
 }
 
 impl DeviceStateManager {
     pub fn new() -> Self {
         Self {
+            lifecycle: LifecycleState::Idle,
             connection_state: ConnectionState::Disconnected,
             streaming_state: StreamingState::Stopped,
+            sleep_mode_supported: false,
+            hardware_revision: None,
+            telemetry: None,
+            last_error: None,
+        }
+    }
+
+    // Lifecycle management
+    pub fn lifecycle(&self) -> LifecycleState {
+        self.lifecycle
+    }
+
+    /// Rejects `next` unless the lifecycle allows it from the current state
+    pub fn check_transition(&self, next: LifecycleState) -> Result<(), String> {
+        if self.lifecycle.can_become(next) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Cannot go from {} to {}",
+                self.lifecycle.as_str(),
+                next.as_str()
+            ))
+        }
+    }
+
+    /// Rejects a connect while the link is still up, e.g. in the error
+    /// state after a stream failed, where the lifecycle alone would allow it
+    pub fn check_connect(&self) -> Result<(), String> {
+        if self.is_connected() {
+            return Err("Device already connected".to_string());
         }
+        self.check_transition(LifecycleState::Scanning)
+    }
+
+    /// Rejects starting a stream without a link, e.g. in the error state
+    /// after a failed connect, before any outlet is created
+    pub fn check_stream_start(&self) -> Result<(), String> {
+        if !self.is_connected() {
+            return Err("Device not connected".to_string());
+        }
+        self.check_transition(LifecycleState::Streaming)
+    }
+
+    pub fn transition(&mut self, next: LifecycleState) -> Result<(), String> {
+        self.check_transition(next)?;
+        self.lifecycle = next;
+        Ok(())
     }
 
     // Connection state management
     pub fn set_connected(&mut self, name: String, uuid: String) {
         self.connection_state = ConnectionState::Connected(DeviceInfo { name, uuid });
+        self.last_error = None;
     }
 
     pub fn set_disconnected(&mut self) {
         // When disconnecting, also stop streaming
         self.set_streaming_stopped();
         self.connection_state = ConnectionState::Disconnected;
+        self.sleep_mode_supported = false;
+        self.hardware_revision = None;
+        self.telemetry = None;
     }
 
     pub fn is_connected(&self) -> bool {
@@ -59,6 +180,31 @@ impl DeviceStateManager {
         self.get_device_info().map(|info| info.uuid.as_str())
     }
 
+    // Capabilities of the connected device
+    pub fn set_sleep_mode_supported(&mut self, supported: bool) {
+        self.sleep_mode_supported = supported;
+    }
+
+    pub fn set_hardware_revision(&mut self, revision: Option<String>) {
+        self.hardware_revision = revision;
+    }
+
+    pub fn hardware_revision(&self) -> Option<&str> {
+        self.hardware_revision.as_deref()
+    }
+
+    pub fn set_telemetry(&mut self, telemetry: Telemetry) {
+        self.telemetry = Some(telemetry);
+    }
+
+    pub fn telemetry(&self) -> Option<Telemetry> {
+        self.telemetry
+    }
+
+    pub fn supports_sleep_mode(&self) -> bool {
+        self.is_connected() && self.sleep_mode_supported
+    }
+
     // Streaming state management
     pub fn set_streaming_started(&mut self) -> Result<(), &'static str> {
         if !self.is_connected() {
@@ -66,6 +212,7 @@ impl DeviceStateManager {
         }
 
         self.streaming_state = StreamingState::Streaming;
+        self.last_error = None;
         Ok(())
     }
 
@@ -77,21 +224,35 @@ impl DeviceStateManager {
         matches!(self.streaming_state, StreamingState::Streaming)
     }
 
+    // Failures that stopped a stream outside of a JS call
+    pub fn set_error(&mut self, error: String) {
+        self.last_error = Some(error);
+    }
+
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
     // Validation methods
+    #[allow(dead_code)]
     pub fn can_start_streaming(&self) -> bool {
         self.is_connected() && !self.is_streaming()
     }
 
+    #[allow(dead_code)]
     pub fn can_stop_streaming(&self) -> bool {
         self.is_streaming()
     }
 
     // State reporting for debugging
+    #[allow(dead_code)]
     pub fn get_state_summary(&self) -> String {
         match self.get_device_info() {
             Some(info) => format!(
-                "Connected to {} ({}), Streaming: {}", 
-                info.name, info.uuid, self.is_streaming()
+                "Connected to {} ({}), Streaming: {}",
+                info.name,
+                info.uuid,
+                self.is_streaming()
             ),
             None => format!("Disconnected, Streaming: {}", self.is_streaming()),
         }
@@ -102,4 +263,101 @@ impl Default for DeviceStateManager {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use LifecycleState::*;
+
+    const ALL: [LifecycleState; 9] = [
+        Idle,
+        Scanning,
+        Connecting,
+        Connected,
+        Streaming,
+        Paused,
+        Reconnecting,
+        Disconnecting,
+        Error,
+    ];
+
+    #[test]
+    fn lifecycle_allows_only_the_listed_transitions() {
+        let allowed = [
+            (Idle, Scanning),
+            (Error, Scanning),
+            (Scanning, Connecting),
+            (Connecting, Connected),
+            (Connected, Streaming),
+            (Error, Streaming),
+            (Streaming, Paused),
+            (Streaming, Reconnecting),
+            (Paused, Streaming),
+            (Reconnecting, Streaming),
+            (Streaming, Connected),
+            (Paused, Connected),
+            (Reconnecting, Connected),
+            (Error, Connected),
+            (Disconnecting, Idle),
+        ];
+        for from in ALL {
+            for to in ALL {
+                let expected = allowed.contains(&(from, to))
+                    || (from != to && matches!(to, Error | Disconnecting));
+                assert_eq!(
+                    from.can_become(to),
+                    expected,
+                    "{} -> {}",
+                    from.as_str(),
+                    to.as_str()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejected_transition_leaves_the_state() {
+        let mut state = DeviceStateManager::new();
+        assert_eq!(
+            state.transition(Streaming),
+            Err("Cannot go from idle to streaming".to_string())
+        );
+        assert_eq!(state.lifecycle(), Idle);
+    }
+
+    #[test]
+    fn connect_needs_the_link_down_even_from_error() {
+        let mut state = DeviceStateManager::new();
+        state.transition(Scanning).unwrap();
+        state.transition(Connecting).unwrap();
+        state.set_connected("MuseS-1234".to_string(), "uuid".to_string());
+        state.transition(Connected).unwrap();
+        // A stream failure moves to error with the headband still linked
+        state.transition(Error).unwrap();
+        assert!(Error.can_become(Scanning));
+        assert_eq!(
+            state.check_connect(),
+            Err("Device already connected".to_string())
+        );
+
+        state.set_disconnected();
+        assert_eq!(state.check_connect(), Ok(()));
+    }
+
+    #[test]
+    fn stream_start_needs_a_link_even_from_error() {
+        let mut state = DeviceStateManager::new();
+        // A failed connect ends in error without a link
+        state.transition(Scanning).unwrap();
+        state.transition(Error).unwrap();
+        assert!(Error.can_become(Streaming));
+        assert_eq!(
+            state.check_stream_start(),
+            Err("Device not connected".to_string())
+        );
+
+        state.set_connected("MuseS-1234".to_string(), "uuid".to_string());
+        assert_eq!(state.check_stream_start(), Ok(()));
+    }
+}
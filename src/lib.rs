@@ -1,107 +1,467 @@
+use btleplug::api::CharPropFlags;
 use btleplug::platform::Peripheral as PlatformPeripheral;
-use napi::{Env, JsBoolean, JsNumber, JsString, Result};
-use napi_derive::napi;
+use napi::bindgen_prelude::{Buffer, ToNapiValue, block_on};
+use napi::threadsafe_function::ThreadSafeCallContext;
+use napi::{
+  CallContext, Env, JsBoolean, JsFunction, JsNumber, JsObject, JsString, JsSymbol, Result,
+};
+use napi_derive::{js_function, napi};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, Instant};
 
 mod ble;
-mod lsl_manager;
+mod callbacks;
 mod device_state;
+mod lsl_manager;
+mod processing;
+mod stats;
+mod udp;
+
+use ble::{
+  BleConnector, BleOptions, EEG_PACKET_BYTES, EEG_RAILS, KNOWN_UUIDS, LinkStatus, PPG_PACKET_BYTES,
+  SLEEP_PRESET, StreamEvent, StreamEvents, TimestampedData,
+};
+use callbacks::{JsCallback, Overflow, Sample, SampleQueue, SampleStream, UnhandledNotification};
+use device_state::{DeviceStateManager, LifecycleState, Telemetry};
+use lsl_manager::{
+  BURST_START_MARKER, BURST_STOP_MARKER, EEG_NOMINAL_RATE, LslOptions, LslShared, LslStreamManager,
+  PPG_NOMINAL_RATE, RECOMMENDED_STREAM_TYPES, SAMPLE_INDEX_LABEL, StreamDescription, StreamTypes,
+  TimestampSource, eeg_channel_labels, lsl_available, ppg_channel_labels,
+};
+use processing::{DspOptions, EpochThresholds, SignalSummary};
+use stats::{RuntimeStats, StreamStats};
+
+// Runs of the status command behind `measureCommandLatency`
+const LATENCY_RUNS: usize = 5;
+
+// Correlations kept for `syncPulses`
+const SYNC_PULSE_HISTORY: usize = 100;
+
+// Long enough for advertisements from every headband in range
+const DEFAULT_LIST_SCAN: Duration = Duration::from_secs(3);
+
+// Battery drifts slowly, so there's no point polling it faster
+const BATTERY_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// How long the exit hook may hold up process exit halting the device
+const EXIT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
 
-use ble::{BleConnector, DataType};
-use lsl_manager::LslStreamManager;
-use device_state::DeviceStateManager;
+// How long `stopStreaming` waits for the LSL thread to destroy the outlets
+const LSL_TEARDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+const LSL_TEARDOWN_POLL: Duration = Duration::from_millis(10);
+
+// How often the `idleDisconnectMs` watcher looks at the device
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// `burst` defaults: 10 s of data every minute
+const DEFAULT_BURST_ON_MS: u32 = 10_000;
+const DEFAULT_BURST_EVERY_MS: u32 = 60_000;
+
+// `epochQuality` defaults, in the decoder's raw counts (0-255): resting EEG
+// stays well inside them, while blinks, jaw clenches and electrode pops swing
+// across most of the span
+const DEFAULT_EPOCH_MS: u32 = 1000;
+const DEFAULT_EPOCH_MAX_RANGE: f64 = 150.0;
+const DEFAULT_EPOCH_MAX_STEP: f64 = 50.0;
+
+// `orientation` gain (rad/s): Madgwick's suggested value, a few seconds to
+// settle on the tilt while riding out head movement
+const DEFAULT_ORIENTATION_GAIN: f64 = 0.1;
+
+// `sampleStream` buffer: 4 s of EEG, or 16 s of PPG
+const DEFAULT_STREAM_BUFFER_SAMPLES: u32 = 1024;
+
+type SharedConnector = Arc<tokio::sync::Mutex<Option<BleConnector<PlatformPeripheral>>>>;
 
 // Removed shared runtime - using blocking operations instead
 
 #[napi]
 pub struct MuseDevice {
-  connector: Arc<tokio::sync::Mutex<Option<BleConnector<PlatformPeripheral>>>>,
+  connector: SharedConnector,
   target_uuid: Option<String>,
   #[allow(dead_code)]
   rssi_interval_ms: Option<u32>,
   #[allow(dead_code)]
   xdf_record_path: Option<String>,
+  ble_options: BleOptions,
+  lsl_options: LslOptions,
+  lsl_shared: Arc<LslShared>,
+  lsl_threads: Mutex<Vec<std::thread::JoinHandle<()>>>,
+  link_status: Arc<LinkStatus>,
   state: Arc<Mutex<DeviceStateManager>>,
+  idle_disconnect: Option<Duration>,
+  auto_connect_on_stream: bool,
+  disambiguate_source_ids: bool,
+  // Restarted by connect, stopStreaming, ping and while streaming
+  last_activity: Arc<Mutex<Instant>>,
+  idle_watch: Mutex<Option<tokio::task::JoinHandle<()>>>,
+  burst: Option<Burst>,
+  burst_cycle: Mutex<Option<tokio::task::JoinHandle<()>>>,
+  burst_resting: Arc<AtomicBool>,
+  sync_pulses: Mutex<VecDeque<SyncPulse>>,
 }
 
 #[napi]
 impl MuseDevice {
+  /// The device is also halted and disconnected when the Node environment
+  /// is torn down (see `shutdown`)
   #[napi(constructor)]
-  pub fn new(options: DeviceAdapterOptions) -> Self {
-    let target_uuid = options.ble_uuid.and_then(|js_str| {
-      js_str
-        .into_utf8()
-        .ok()
-        .and_then(|utf8| utf8.as_str().ok().map(|s| s.to_string()))
+  pub fn new(mut env: Env, options: DeviceAdapterOptions) -> Result<Self> {
+    let target_uuid = js_string(options.ble_uuid);
+    let rssi_interval_ms = js_u32(options.rssi_interval_ms);
+    let xdf_record_path = js_string(options.xdf_record_path);
+    let defaults = BleOptions::default();
+    let ble_options = BleOptions {
+      eeg_header_bytes: js_u32(options.eeg_header_bytes)
+        .map_or(defaults.eeg_header_bytes, |bytes| bytes as usize),
+      ppg_header_bytes: js_u32(options.ppg_header_bytes)
+        .map_or(defaults.ppg_header_bytes, |bytes| bytes as usize),
+      keepalive_interval_ms: js_u32(options.keepalive_interval_ms)
+        .filter(|&interval_ms| interval_ms > 0)
+        .map(u64::from),
+      ppg_signed: js_bool(options.ppg_signed).unwrap_or(false),
+      reset_on_disconnect: js_bool(options.reset_on_disconnect).unwrap_or(false),
+      silent_channel_timeout: js_u32(options.silent_channel_timeout_ms)
+        .map_or(defaults.silent_channel_timeout, |ms| {
+          Duration::from_millis(ms.into())
+        }),
+      reconnect_on_loss_pct: js_f64(options.reconnect_on_loss_pct).filter(|&pct| pct > 0.0),
+      adapter: js_string(options.adapter),
+      min_rssi: js_f64(options.min_rssi).map(|dbm| dbm.round() as i16),
+      service_ready_timeout: js_u32(options.service_ready_ms)
+        .map_or(defaults.service_ready_timeout, |ms| {
+          Duration::from_millis(ms.into())
+        }),
+      scan_all: js_bool(options.scan_all).unwrap_or(defaults.scan_all),
+      control_command_gap: js_u32(options.control_command_gap_ms)
+        .map_or(defaults.control_command_gap, |ms| {
+          Duration::from_millis(ms.into())
+        }),
+      scan_poll_interval: js_u32(options.scan_poll_interval_ms)
+        .filter(|&ms| ms > 0)
+        .map_or(defaults.scan_poll_interval, |ms| {
+          Duration::from_millis(ms.into())
+        }),
+      imu: js_bool(options.imu).unwrap_or(defaults.imu),
+    };
+    let lsl_defaults = LslOptions::default();
+    let dsp = dsp_options(ProcessingOptions {
+      ppg_smoothing_window: js_u32(options.ppg_smoothing_window),
+      ppg_subtract_ambient: js_bool(options.ppg_subtract_ambient),
+      ppg_drop_ambient: js_bool(options.ppg_drop_ambient),
+      clamp_counts: js_f64(options.clamp_counts),
+      clamp_to_nan: js_bool(options.clamp_to_nan),
     });
-    let rssi_interval_ms = options
-      .rssi_interval_ms
-      .and_then(|js_num| js_num.get_uint32().ok());
-    let xdf_record_path = options.xdf_record_path.and_then(|js_str| {
-      js_str
-        .into_utf8()
-        .ok()
-        .and_then(|utf8| utf8.as_str().ok().map(|s| s.to_string()))
+    let mut lsl_options = LslOptions {
+      dsp,
+      js_dsp: options.js_processing.map(dsp_options),
+      outlet_retries: js_u32(options.lsl_outlet_retries).unwrap_or(lsl_defaults.outlet_retries),
+      rate_warning_pct: js_f64(options.rate_warning_pct).unwrap_or(lsl_defaults.rate_warning_pct),
+      include_aux: js_bool(options.include_aux).unwrap_or(lsl_defaults.include_aux),
+      include_sample_index: js_bool(options.include_sample_index)
+        .unwrap_or(lsl_defaults.include_sample_index),
+      chunked_push: js_bool(options.chunked_push).unwrap_or(lsl_defaults.chunked_push),
+      bracket_markers: js_bool(options.bracket_markers).unwrap_or(lsl_defaults.bracket_markers),
+      jitter_buffer_secs: js_u32(options.lsl_jitter_buffer_ms)
+        .map_or(lsl_defaults.jitter_buffer_secs, |ms| ms as f64 / 1000.0),
+      js_chunk_samples: js_u32(options.js_chunk_samples)
+        .map_or(lsl_defaults.js_chunk_samples, |samples| samples as usize),
+      timestamp_source: timestamp_source(js_string(options.timestamp_source))?,
+      dejitter_timestamps: js_bool(options.dejitter_timestamps)
+        .unwrap_or(lsl_defaults.dejitter_timestamps),
+      udp_target: js_string(options.udp_target),
+      measure_latency: js_bool(options.measure_latency).unwrap_or(lsl_defaults.measure_latency),
+      preview_outlet: js_bool(options.preview_outlet).unwrap_or(lsl_defaults.preview_outlet),
+      source_id_suffix: String::new(),
+      eeg_int16: eeg_int16(js_string(options.channel_format))?,
+      epoch_quality: options.epoch_quality.map(epoch_thresholds).transpose()?,
+      stream_types: options.stream_types.map(stream_types).unwrap_or_default(),
+      bursts: options.burst.is_some(),
+      imu: ble_options.imu,
+      orientation_gain: options
+        .orientation
+        .map(|orientation| orientation_gain(orientation, ble_options.imu))
+        .transpose()?,
+      source_id_check: match js_u32(options.source_id_check_ms) {
+        Some(0) => None,
+        Some(ms) => Some(Duration::from_millis(ms.into())),
+        None => lsl_defaults.source_id_check,
+      },
+      source_id_disambiguator: None,
+    };
+    if lsl_options.eeg_int16 && lsl_options.include_sample_index {
+      return Err(napi::Error::from_reason(
+        "channelFormat \"int16\" can't carry includeSampleIndex",
+      ));
+    }
+    let eeg_exclude = eeg_channel_indices(
+      &options.filter_exclude_channels.unwrap_or_default(),
+      lsl_options.include_aux,
+    )?;
+    let ppg_decimation = ppg_decimation(js_f64(options.ppg_output_rate))?;
+    if let Some(js_dsp) = lsl_options.js_dsp.as_mut() {
+      js_dsp.eeg_exclude = eeg_exclude.clone();
+      js_dsp.ppg_decimation = ppg_decimation;
+    }
+    lsl_options.dsp.eeg_exclude = eeg_exclude;
+    lsl_options.dsp.ppg_decimation = ppg_decimation;
+
+    let burst = options
+      .burst
+      .map(|burst| self::burst(burst, ble_options.silent_channel_timeout))
+      .transpose()?;
+
+    let connector: SharedConnector = Arc::new(tokio::sync::Mutex::new(None));
+    // Registered after napi's own runtime hook, so it runs before the runtime
+    // goes away. The hook can't be removed without an env, so it only holds a
+    // weak reference: a device dropped before exit leaves nothing behind
+    let _ = env.add_env_cleanup_hook(Arc::downgrade(&connector), |connector| {
+      let Some(connector) = connector.upgrade() else {
+        return;
+      };
+      // Unwinding out of a cleanup hook would abort the process
+      let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _ = block_on(tokio::time::timeout(
+          EXIT_SHUTDOWN_TIMEOUT,
+          shutdown_connector(&connector),
+        ));
+      }));
     });
 
-    Self {
-      connector: Arc::new(tokio::sync::Mutex::new(None)),
+    Ok(Self {
+      connector,
       target_uuid,
       rssi_interval_ms,
       xdf_record_path,
+      ble_options,
+      lsl_options,
+      lsl_shared: Arc::new(LslShared::default()),
+      lsl_threads: Mutex::new(Vec::new()),
+      link_status: Arc::new(LinkStatus::default()),
       state: Arc::new(Mutex::new(DeviceStateManager::new())),
-    }
+      idle_disconnect: js_u32(options.idle_disconnect_ms)
+        .filter(|&ms| ms > 0)
+        .map(|ms| Duration::from_millis(ms.into())),
+      auto_connect_on_stream: js_bool(options.auto_connect_on_stream).unwrap_or(false),
+      disambiguate_source_ids: js_bool(options.disambiguate_source_ids).unwrap_or(false),
+      last_activity: Arc::new(Mutex::new(Instant::now())),
+      idle_watch: Mutex::new(None),
+      burst,
+      burst_cycle: Mutex::new(None),
+      burst_resting: Arc::new(AtomicBool::new(false)),
+      sync_pulses: Mutex::new(VecDeque::new()),
+    })
   }
 
+  /// Preflight check of the BLE adapter and liblsl, no device required
   #[napi]
-  pub async fn connect(&self) -> napi::Result<()> {
-    let mut connector_guard = self.connector.lock().await;
+  pub async fn check_environment() -> napi::Result<EnvironmentReport> {
+    // A missing Bluetooth stack is reported, not thrown
+    let adapters = ble::list_adapters().await.unwrap_or_default();
 
-    if connector_guard.is_none() {
-      let connector = BleConnector::new()
-        .await
-        .map_err(|e| napi::Error::from_reason(format!("Failed to create BLE connector: {}", e)))?;
-      *connector_guard = Some(connector);
+    Ok(EnvironmentReport {
+      ble_adapter_present: !adapters.is_empty(),
+      ble_adapter_name: adapters.first().cloned(),
+      lsl_available: lsl_available(),
+      adapter_count: adapters.len() as u32,
+    })
+  }
+
+  /// Scans on every BLE adapter at once for `scanMs` (default 3000) and lists
+  /// the Muses each one sees; a headband in range of several adapters is
+  /// listed once per adapter. To run several headbands from one process,
+  /// create one `MuseDevice` per headband with its `adapter` and `bleUuid`
+  /// set: each device scans and connects only through its own adapter, so
+  /// they don't contend for one radio. `scanAll` picks Muses by name alone,
+  /// as the `scanAll` option does for `connect`
+  #[napi]
+  pub async fn list_devices(
+    scan_ms: Option<u32>,
+    scan_all: Option<bool>,
+  ) -> napi::Result<Vec<DiscoveredDevice>> {
+    let scan = scan_ms.map_or(DEFAULT_LIST_SCAN, |ms| Duration::from_millis(ms.into()));
+    let muses = ble::scan_all_adapters(scan, scan_all.unwrap_or(false))
+      .await
+      .map_err(|e| napi::Error::from_reason(format!("Failed to scan for devices: {}", e)))?;
+
+    Ok(
+      muses
+        .into_iter()
+        .map(|muse| DiscoveredDevice {
+          name: muse.name,
+          uuid: muse.uuid,
+          adapter: muse.adapter,
+          rssi: muse.rssi.map(i32::from),
+          handle: muse.handle,
+        })
+        .collect(),
+    )
+  }
+
+  /// The Muse service and characteristic UUIDs the crate uses, keyed by
+  /// name (e.g. `control`, `eeg_tp9`, `ppg_red`)
+  #[napi]
+  pub fn known_uuids() -> HashMap<String, String> {
+    KNOWN_UUIDS
+      .iter()
+      .map(|(name, uuid)| (name.to_string(), uuid.to_string()))
+      .collect()
+  }
+
+  /// The bytes a control command is written as (`X{cmd}\n`, with the first
+  /// byte replaced by the frame's length - 1), without sending anything
+  /// @throws if the command is empty, not printable ASCII or over 18 bytes
+  #[napi]
+  pub fn encode_command(cmd: String) -> Result<Buffer> {
+    ble::checked_encode_command(&cmd)
+      .map(Buffer::from)
+      .map_err(|e| napi::Error::from_reason(e.to_string()))
+  }
+
+  /// With a `handle` from `listDevices`, connects straight to that
+  /// headband without scanning again, ignoring `bleUuid` and `minRssi`.
+  /// Handles last 60 s; an expired or unknown handle throws, as does a
+  /// headband that has since gone out of range
+  /// @throws if already connected, or when no Muse is found or the
+  /// connection fails (the lifecycle then moves to "error")
+  #[napi]
+  pub async fn connect(&self, handle: Option<String>) -> napi::Result<()> {
+    let mut connector_guard = self.connector.lock().await;
+    if connector_guard
+      .as_ref()
+      .is_some_and(|connector| connector.is_connected())
+    {
+      return Err(napi::Error::from_reason("Device already connected"));
     }
+    self
+      .state
+      .lock()
+      .unwrap()
+      .check_connect()
+      .map_err(napi::Error::from_reason)?;
+    transition(&self.state, &self.lsl_shared, LifecycleState::Scanning)?;
 
-    if let Some(connector) = connector_guard.as_mut() {
-      let (device_name, device_uuid) =
-        connector
-          .connect(self.target_uuid.clone())
+    let connected: napi::Result<_> = async {
+      // Resolved first so a bad handle fails without touching the adapter
+      let listed = handle
+        .as_deref()
+        .map(ble::discovered_peripheral)
+        .transpose()
+        .map_err(|e| napi::Error::from_reason(format!("Failed to find Muse device: {}", e)))?;
+      if connector_guard.is_none() {
+        let connector = BleConnector::new(self.ble_options.clone(), self.link_status.clone())
           .await
           .map_err(|e| {
-            napi::Error::from_reason(format!("Failed to connect to Muse device: {}", e))
+            napi::Error::from_reason(format!("Failed to create BLE connector: {}", e))
           })?;
+        *connector_guard = Some(connector);
+      }
+      let connector = connector_guard.as_mut().unwrap();
+
+      let device = match listed {
+        Some(device) => device,
+        None => connector
+          .find_device(self.target_uuid.clone())
+          .await
+          .map_err(|e| napi::Error::from_reason(format!("Failed to find Muse device: {}", e)))?,
+      };
+      transition(&self.state, &self.lsl_shared, LifecycleState::Connecting)?;
 
-      // Update device state
-      self.state.lock().unwrap().set_connected(device_name, device_uuid);
+      let (device_name, device_uuid) = connector.connect(device).await.map_err(|e| {
+        let hint = if handle.is_some() {
+          " (it may no longer be in range, list devices again)"
+        } else {
+          ""
+        };
+        napi::Error::from_reason(format!("Failed to connect to Muse device{}: {}", hint, e))
+      })?;
+      let supports_sleep_mode = connector.supports_sleep_mode(&device_name);
+      Ok((device_name, device_uuid, supports_sleep_mode))
     }
+    .await;
 
-    Ok(())
+    match connected {
+      Ok((device_name, device_uuid, supports_sleep_mode)) => {
+        {
+          let mut state = self.state.lock().unwrap();
+          state.set_connected(device_name, device_uuid);
+          state.set_sleep_mode_supported(supports_sleep_mode);
+        }
+        self.start_idle_watch();
+        transition(&self.state, &self.lsl_shared, LifecycleState::Connected)
+      }
+      Err(e) => {
+        enter_error(&self.state, &self.lsl_shared, e.reason.clone());
+        Err(e)
+      }
+    }
   }
 
+  /// With `autoConnectOnStream`, connects first when not connected
+  /// @throws if not connected, or with "Auto-connect failed: ..." when the
+  /// implicit connect fails (the lifecycle then moves to "error")
   #[napi]
   pub async fn start_streaming(&self) -> napi::Result<()> {
+    let connected = self.state.lock().unwrap().is_connected();
+    if self.auto_connect_on_stream && !connected {
+      self
+        .connect(None)
+        .await
+        .map_err(|e| napi::Error::from_reason(format!("Auto-connect failed: {}", e.reason)))?;
+    }
     let mut connector_guard = self.connector.lock().await;
 
     if let Some(connector) = connector_guard.as_mut() {
+      self
+        .state
+        .lock()
+        .unwrap()
+        .check_stream_start()
+        .map_err(napi::Error::from_reason)?;
+
       // Create channel for data streaming
-      let (data_tx, data_rx) = mpsc::channel::<DataType>();
+      let (data_tx, data_rx) = mpsc::channel::<TimestampedData>();
+      let (ready_tx, ready_rx) = tokio::sync::oneshot::channel();
+
+      // Use blocking LSL operations without async runtime to reduce thread creation
+      let mut lsl_options = self.lsl_options.clone();
+      if self.disambiguate_source_ids {
+        lsl_options.source_id_disambiguator = source_id_disambiguator(&self.state.lock().unwrap());
+      }
+      let lsl_shared = self.lsl_shared.clone();
+      let lsl_thread = std::thread::spawn(move || {
+        LslStreamManager::process_data_stream_simple(data_rx, lsl_options, lsl_shared, ready_tx);
+      });
+      {
+        let mut lsl_threads = self.lsl_threads.lock().unwrap();
+        lsl_threads.retain(|thread| !thread.is_finished());
+        lsl_threads.push(lsl_thread);
+      }
+
+      // Wait for the outlets so a failure is reported before the device starts streaming
+      ready_rx
+        .await
+        .map_err(|_| napi::Error::from_reason("LSL thread exited unexpectedly"))?
+        .map_err(|e| napi::Error::from_reason(format!("Failed to create LSL outlets: {}", e)))?;
 
       // Start BLE streaming with the sender
+      let events = stream_events(self.state.clone(), self.lsl_shared.clone());
       connector
-        .start_streaming(data_tx)
+        .start_streaming(data_tx, events)
         .await
         .map_err(|e| napi::Error::from_reason(format!("Failed to start streaming: {}", e)))?;
 
-      // Use blocking LSL operations without async runtime to reduce thread creation
-      let _streaming_handle = std::thread::spawn(move || {
-        LslStreamManager::process_data_stream_simple(data_rx);
-      });
-
       // Update streaming state
-      self.state.lock().unwrap().set_streaming_started()
-        .map_err(|e| napi::Error::from_reason(e))?;
+      self
+        .state
+        .lock()
+        .unwrap()
+        .set_streaming_started()
+        .map_err(napi::Error::from_reason)?;
+      transition(&self.state, &self.lsl_shared, LifecycleState::Streaming)?;
+      self.start_burst_cycle();
     } else {
       return Err(napi::Error::from_reason("Device not connected"));
     }
@@ -112,6 +472,7 @@ impl MuseDevice {
   #[napi]
   pub async fn stop_streaming(&self) -> napi::Result<()> {
     let mut connector_guard = self.connector.lock().await;
+    self.stop_burst_cycle();
 
     if let Some(connector) = connector_guard.as_mut() {
       connector
@@ -120,86 +481,2435 @@ impl MuseDevice {
         .map_err(|e| napi::Error::from_reason(format!("Failed to stop streaming: {}", e)))?;
     }
 
-    // Note: Background streaming task will stop when data_rx channel closes
-    // due to BLE connector cleanup above
-    
-    // Brief pause to allow LSL cleanup to complete
-    std::thread::sleep(std::time::Duration::from_millis(100));
+    // The LSL thread ends once the BLE side above has dropped its sender
+    let teardown = await_lsl_teardown(&self.lsl_threads).await;
 
     // Update streaming state
-    self.state.lock().unwrap().set_streaming_stopped();
+    let back_to_connected = {
+      let mut state = self.state.lock().unwrap();
+      state.set_streaming_stopped();
+      state.is_connected() && state.lifecycle() != LifecycleState::Connected
+    };
+    if back_to_connected {
+      transition(&self.state, &self.lsl_shared, LifecycleState::Connected)?;
+    }
+    *self.last_activity.lock().unwrap() = Instant::now();
 
-    Ok(())
+    teardown
   }
 
   #[napi]
   pub async fn restart_streaming(&self) -> napi::Result<()> {
-    // Stop and restart without full disconnect to avoid thread churn
+    // Stop and restart without full disconnect to avoid thread churn. The
+    // old outlets are gone once stop_streaming returns
     self.stop_streaming().await?;
-    std::thread::sleep(std::time::Duration::from_millis(200)); // Brief pause for cleanup
     self.start_streaming().await?;
     Ok(())
   }
 
+  /// Switches a Muse S to its sleep preset. When already streaming the
+  /// device is restarted on the new preset and the outlets stay open
+  /// @throws if not connected or the device has no sleep preset
   #[napi]
-  pub async fn disconnect(&self) -> napi::Result<()> {
+  pub async fn enable_sleep_mode(&self) -> napi::Result<()> {
     let mut connector_guard = self.connector.lock().await;
 
-    if let Some(connector) = connector_guard.as_mut() {
-      connector
-        .disconnect()
-        .await
-        .map_err(|e| napi::Error::from_reason(format!("Failed to disconnect: {}", e)))?;
+    let connector = connector_guard
+      .as_mut()
+      .filter(|connector| connector.is_connected())
+      .ok_or_else(|| napi::Error::from_reason("Device not connected"))?;
+
+    if !self.state.lock().unwrap().supports_sleep_mode() {
+      return Err(napi::Error::from_reason(
+        "Sleep mode is not supported by this device (requires a Muse S)",
+      ));
+    }
+
+    // A live stream is restarted on the new preset
+    let streaming = self.state.lock().unwrap().is_streaming();
+    if streaming {
+      transition(&self.state, &self.lsl_shared, LifecycleState::Paused)?;
+    }
+    let result = connector
+      .set_preset(SLEEP_PRESET)
+      .await
+      .map_err(|e| napi::Error::from_reason(format!("Failed to enable sleep mode: {}", e)));
+    if streaming {
+      transition(&self.state, &self.lsl_shared, LifecycleState::Streaming)?;
     }
+    result
+  }
+
+  /// Median round-trip time in milliseconds of the status command (`s`),
+  /// measured to the first fragment of each response over 5 runs
+  /// @throws if not connected or the device doesn't answer within 2 s
+  #[napi]
+  pub async fn measure_command_latency(&self) -> napi::Result<f64> {
+    let connector_guard = self.connector.lock().await;
+    let connector = connector_guard
+      .as_ref()
+      .filter(|connector| connector.is_connected())
+      .ok_or_else(|| napi::Error::from_reason("Device not connected"))?;
+
+    connector
+      .measure_command_latency(LATENCY_RUNS)
+      .await
+      .map_err(|e| napi::Error::from_reason(format!("Failed to measure latency: {}", e)))
+  }
+
+  /// Checks the link by sending a status command, resolving whether the
+  /// device answered within 2 s. Safe to call while streaming. Also
+  /// restarts the `idleDisconnectMs` countdown
+  /// @throws if not connected or the command can't be written
+  #[napi]
+  pub async fn ping(&self) -> napi::Result<bool> {
+    let connector_guard = self.connector.lock().await;
+    let connector = connector_guard
+      .as_ref()
+      .filter(|connector| connector.is_connected())
+      .ok_or_else(|| napi::Error::from_reason("Device not connected"))?;
+    *self.last_activity.lock().unwrap() = Instant::now();
+
+    connector
+      .ping()
+      .await
+      .map_err(|e| napi::Error::from_reason(format!("Failed to ping device: {}", e)))
+  }
+
+  /// Brings a device that stopped following its commands (e.g. streaming
+  /// the wrong preset after a lost write) back to a known configuration
+  /// without disconnecting: halts it, re-applies the current preset,
+  /// checks that it answers a status query, and restarts streaming if it
+  /// was streaming. The start commands are also resent automatically,
+  /// counted in `StreamStats.resyncs`, when no EEG at all arrives within
+  /// `silentChannelTimeoutMs` of starting a stream
+  /// @throws if not connected or the device doesn't answer
+  #[napi]
+  pub async fn resync(&self) -> napi::Result<()> {
+    let connector_guard = self.connector.lock().await;
+    let connector = connector_guard
+      .as_ref()
+      .filter(|connector| connector.is_connected())
+      .ok_or_else(|| napi::Error::from_reason("Device not connected"))?;
+
+    connector
+      .resync()
+      .await
+      .map_err(|e| napi::Error::from_reason(format!("Failed to resync device: {}", e)))
+  }
 
-    // Update device state
-    self.state.lock().unwrap().set_disconnected();
+  /// Recovers a stream whose device side is stuck while keeping the LSL
+  /// outlets (and their uids) open, so consumers stay attached and only
+  /// see a short gap in the data: the EEG and PPG subscriptions are
+  /// renewed and the start commands resent. Unlike `resync` it doesn't wait
+  /// for the device to answer, and unlike `restartStreaming` the outlets
+  /// aren't recreated
+  /// @throws if not streaming or a command can't be written
+  #[napi]
+  pub async fn soft_restart(&self) -> napi::Result<()> {
+    let connector_guard = self.connector.lock().await;
+    let connector = connector_guard
+      .as_ref()
+      .filter(|connector| connector.is_connected())
+      .ok_or_else(|| napi::Error::from_reason("Device not connected"))?;
 
+    connector
+      .soft_restart()
+      .await
+      .map_err(|e| napi::Error::from_reason(format!("Failed to restart device: {}", e)))?;
+    *self.last_activity.lock().unwrap() = Instant::now();
     Ok(())
   }
 
-  /// @throws if its not connected
-  #[napi(getter)]
-  pub fn ble_name(&self, env: Env) -> Result<JsString> {
-    let state = self.state.try_lock()
-      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?;
-    match state.get_device_name() {
-      Some(name) => env.create_string(name),
-      None => Err(napi::Error::from_reason("Device not connected")),
+  /// Asks the device for its hardware revision (e.g. "4.4"), which is
+  /// distinct from the firmware version and tells apart headbands that
+  /// behave differently. Resolves null if the firmware doesn't report one.
+  /// The result is kept for `hardwareRevision` until disconnect
+  /// @throws if not connected or no response arrives
+  #[napi]
+  pub async fn read_hardware_revision(&self) -> napi::Result<Option<String>> {
+    let connector_guard = self.connector.lock().await;
+    let connector = connector_guard
+      .as_ref()
+      .filter(|connector| connector.is_connected())
+      .ok_or_else(|| napi::Error::from_reason("Device not connected"))?;
+
+    let revision = connector
+      .hardware_revision()
+      .await
+      .map_err(|e| napi::Error::from_reason(format!("Failed to read hardware revision: {}", e)))?;
+    self
+      .state
+      .lock()
+      .unwrap()
+      .set_hardware_revision(revision.clone());
+    Ok(revision)
+  }
+
+  /// Polls the battery every 5 s until it reaches `minPercent`, resolving
+  /// false if `timeoutMs` passes first. Useful for rigs that charge between
+  /// subjects
+  /// @throws if not connected or the battery can't be read
+  #[napi]
+  pub async fn wait_for_battery(&self, min_percent: u32, timeout_ms: u32) -> napi::Result<bool> {
+    let deadline = tokio::time::Instant::now() + Duration::from_millis(timeout_ms.into());
+
+    loop {
+      // Only hold the connector while reading so other calls can run between polls
+      let percent = {
+        let connector_guard = self.connector.lock().await;
+        let connector = connector_guard
+          .as_ref()
+          .filter(|connector| connector.is_connected())
+          .ok_or_else(|| napi::Error::from_reason("Device not connected"))?;
+        connector
+          .battery_percent()
+          .await
+          .map_err(|e| napi::Error::from_reason(format!("Failed to read battery: {}", e)))?
+      };
+      if percent >= min_percent as f64 {
+        return Ok(true);
+      }
+
+      match next_battery_poll(tokio::time::Instant::now(), deadline) {
+        Some(at) => tokio::time::sleep_until(at).await,
+        None => return Ok(false),
+      }
     }
   }
 
-  /// @throws if its not connected
+  /// Headband health check: streams for `durationMs`, then stops and
+  /// reports per-channel DC offset, RMS noise and saturation of the raw EEG,
+  /// with the measured rate and packet loss. The outlets are only open for
+  /// the run. Loss is measured over 5 s windows, so shorter runs report 0
+  /// @throws if not connected, already streaming, or streaming fails to start
+  #[napi]
+  pub async fn run_diagnostics(&self, duration_ms: u32) -> napi::Result<DiagnosticsReport> {
+    if self.state.lock().unwrap().is_streaming() {
+      return Err(napi::Error::from_reason(
+        "Cannot run diagnostics while streaming",
+      ));
+    }
+
+    let labels = eeg_channel_labels(self.lsl_options.include_aux);
+    *self.lsl_shared.diagnostics.lock().unwrap() =
+      Some(SignalSummary::new(labels.len(), EEG_RAILS));
+    if let Err(e) = self.start_streaming().await {
+      *self.lsl_shared.diagnostics.lock().unwrap() = None;
+      return Err(e);
+    }
+    let started = Instant::now();
+    tokio::time::sleep(Duration::from_millis(duration_ms.into())).await;
+    let elapsed = started.elapsed().as_secs_f64();
+
+    let stopped = self.stop_streaming().await;
+    let summary = self.lsl_shared.diagnostics.lock().unwrap().take();
+    stopped?;
+    let summary = summary.unwrap_or_else(|| SignalSummary::new(labels.len(), EEG_RAILS));
+
+    Ok(DiagnosticsReport {
+      duration_ms: elapsed * 1000.0,
+      eeg_samples: summary.samples(),
+      measured_eeg_rate: summary.samples() as f64 / elapsed,
+      packet_loss_pct: self.lsl_shared.stats.lock().unwrap().packet_loss_pct,
+      channels: labels
+        .iter()
+        .zip(&summary.channels)
+        .map(|(label, channel)| ChannelDiagnostics {
+          label: label.to_string(),
+          dc_offset: channel.mean(),
+          rms_noise: channel.rms_noise(),
+          saturated_samples: channel.saturated,
+        })
+        .collect(),
+    })
+  }
+
+  /// Per-channel signal quality over the last complete second of raw EEG,
+  /// with the metrics behind each classification so tooling can apply its
+  /// own thresholds. A channel is "saturated" when over 5% of its samples
+  /// hit the decoder's rails, "flat" when its RMS noise is under 0.5, and
+  /// "ok" otherwise. Null during the first second of a stream
+  /// @throws if not streaming
+  #[napi]
+  pub fn get_signal_quality(&self) -> Result<Option<SignalQuality>> {
+    let streaming = self
+      .state
+      .try_lock()
+      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?
+      .is_streaming();
+    if !streaming {
+      return Err(napi::Error::from_reason("Not streaming"));
+    }
+
+    let labels = eeg_channel_labels(self.lsl_options.include_aux);
+    let window = self.lsl_shared.signal_quality.lock().unwrap();
+    Ok(window.as_ref().map(|window| {
+      SignalQuality {
+        window_samples: window.samples(),
+        channels: labels
+          .iter()
+          .zip(&window.channels)
+          .map(|(label, channel)| ChannelQuality {
+            label: label.to_string(),
+            quality: channel.quality().to_string(),
+            dc_offset: channel.mean(),
+            variance: channel.rms_noise().powi(2),
+            rms_noise: channel.rms_noise(),
+            saturated_samples: channel.saturated,
+          })
+          .collect(),
+      }
+    }))
+  }
+
+  /// Correlates a reading of an external clock (e.g. a lab-wide time-sync
+  /// source, or a GPIO pulse relayed through JS) with LSL's `local_clock()`,
+  /// for aligning devices after the fact. Call it as close to the external
+  /// event as possible: the pairing is only as accurate as the delay between
+  /// the event and this call, typically well under a millisecond but more
+  /// when the event loop is busy, so fit a line through many pulses rather
+  /// than trusting any single one. The last 100 pulses are kept in
+  /// `syncPulses`, and while streaming with `bracketMarkers` each is also
+  /// pushed to the markers outlet as `sync_pulse <externalTime>`, stamped
+  /// with its LSL time
+  #[napi]
+  pub fn push_sync_pulse(&self, external_time: f64) -> SyncPulse {
+    let pulse = SyncPulse {
+      external_time,
+      local_time: self.lsl_shared.sync_pulse(external_time),
+    };
+    let mut pulses = self.sync_pulses.lock().unwrap();
+    if pulses.len() == SYNC_PULSE_HISTORY {
+      pulses.pop_front();
+    }
+    pulses.push_back(pulse.clone());
+    pulse
+  }
+
+  /// The most recent `pushSyncPulse` correlations, oldest first
   #[napi(getter)]
-  pub fn ble_uuid(&self, env: Env) -> Result<JsString> {
-    let state = self.state.try_lock()
-      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?;
-    match state.get_device_uuid() {
-      Some(uuid) => env.create_string(uuid),
-      None => Err(napi::Error::from_reason("Device not connected")),
+  pub fn sync_pulses(&self) -> Vec<SyncPulse> {
+    self.sync_pulses.lock().unwrap().iter().cloned().collect()
+  }
+
+  /// Receipt-to-push latency over the last 4096 chunks of the current (or
+  /// last) stream, with `measureLatency` on. The median is the typical
+  /// processing cost; p99 and max show stalls, e.g. from the event loop or
+  /// heavy filtering, and a p99 near the jitter buffer delay is expected
+  /// when it's on. Null when `measureLatency` is off or nothing has been
+  /// pushed yet
+  #[napi]
+  pub fn get_latency_stats(&self) -> Option<LatencyStats> {
+    let latency = self.lsl_shared.latency.lock().unwrap();
+    let log = latency.as_ref()?;
+    let [min_ms, median_ms, p99_ms, max_ms] = log.percentiles()?;
+    Some(LatencyStats {
+      samples: log.len() as u32,
+      min_ms,
+      median_ms,
+      p99_ms,
+      max_ms,
+    })
+  }
+
+  /// Switches the device preset (`p20`, `p21`, `p50`, `p51`, `p1034`,
+  /// `p1035`). While streaming the device is restarted on the new preset and
+  /// the outlets stay open, so EEG continues across the switch.
+  ///
+  /// Only presets streaming the same sensors switch live (p50, p1034 and
+  /// p1035 among themselves). Turning PPG (p50/p51 vs p20/p21) or AUX on or
+  /// off changes what the subscriptions and outlets carry, so it needs
+  /// `stopStreaming` first; the outlets' channel layout itself only changes
+  /// with `includeAux`
+  /// @throws if not connected, the preset is unknown, or it streams other
+  /// sensors than the current one while streaming
+  #[napi]
+  pub async fn set_preset(&self, preset: String) -> napi::Result<()> {
+    let mut connector_guard = self.connector.lock().await;
+
+    let connector = connector_guard
+      .as_mut()
+      .filter(|connector| connector.is_connected())
+      .ok_or_else(|| napi::Error::from_reason("Device not connected"))?;
+
+    let streaming = self.state.lock().unwrap().is_streaming();
+    if streaming {
+      transition(&self.state, &self.lsl_shared, LifecycleState::Paused)?;
     }
+    let result = connector
+      .set_preset(&preset)
+      .await
+      .map_err(|e| napi::Error::from_reason(format!("Failed to set preset: {}", e)));
+    if streaming {
+      transition(&self.state, &self.lsl_shared, LifecycleState::Streaming)?;
+    }
+    result
+  }
+
+  /// Lists the discovered GATT services and characteristics with their flags
+  /// @throws if its not connected
+  #[napi]
+  pub async fn dump_gatt(&self) -> napi::Result<Vec<GattService>> {
+    let connector_guard = self.connector.lock().await;
+
+    let services = connector_guard
+      .as_ref()
+      .and_then(|connector| connector.services())
+      .ok_or_else(|| napi::Error::from_reason("Device not connected"))?;
+
+    Ok(
+      services
+        .into_iter()
+        .map(|service| GattService {
+          uuid: service.uuid.to_string(),
+          primary: service.primary,
+          characteristics: service
+            .characteristics
+            .into_iter()
+            .map(|char| GattCharacteristic {
+              uuid: char.uuid.to_string(),
+              read: char.properties.contains(CharPropFlags::READ),
+              write: char.properties.contains(CharPropFlags::WRITE),
+              write_without_response: char
+                .properties
+                .contains(CharPropFlags::WRITE_WITHOUT_RESPONSE),
+              notify: char.properties.contains(CharPropFlags::NOTIFY),
+              indicate: char.properties.contains(CharPropFlags::INDICATE),
+            })
+            .collect(),
+        })
+        .collect(),
+    )
+  }
+
+  /// Reads a characteristic's current value with a GATT read, for one-shot
+  /// values (device info, telemetry) that aren't worth subscribing to.
+  /// `uuid` is any characteristic from `dumpGatt`, e.g. from `knownUuids`
+  /// @throws if `uuid` isn't a UUID, not connected, or the characteristic
+  /// is missing or not readable
+  #[napi]
+  pub async fn read_characteristic(&self, uuid: String) -> napi::Result<Buffer> {
+    let uuid = uuid::Uuid::parse_str(&uuid)
+      .map_err(|e| napi::Error::from_reason(format!("Invalid UUID \"{}\": {}", uuid, e)))?;
+    let connector_guard = self.connector.lock().await;
+    let connector = connector_guard
+      .as_ref()
+      .filter(|connector| connector.is_connected())
+      .ok_or_else(|| napi::Error::from_reason("Device not connected"))?;
+
+    let value = connector
+      .read_characteristic(uuid)
+      .await
+      .map_err(|e| napi::Error::from_reason(format!("Failed to read characteristic: {}", e)))?;
+    Ok(value.into())
+  }
+
+  /// Called with each EEG sample (TP9, AF7, AF8, TP10, AUX) as it's pushed
+  /// to LSL. AUX is left out when `includeAux` is false, and `includeSampleIndex`
+  /// appends the sample index
+  #[napi(ts_args_type = "callback: (sample: number[]) => void")]
+  pub fn on_eeg(&self, callback: JsFunction) -> Result<()> {
+    self.lsl_shared.callbacks.eeg.set(js_callback(callback)?);
+    Ok(())
+  }
+
+  /// Called with each PPG sample (AMBIENT, INFRARED, RED) as it's pushed to LSL
+  #[napi(ts_args_type = "callback: (sample: number[]) => void")]
+  pub fn on_ppg(&self, callback: JsFunction) -> Result<()> {
+    self.lsl_shared.callbacks.ppg.set(js_callback(callback)?);
+    Ok(())
+  }
+
+  /// Called with `jsChunkSamples` EEG samples at a time. Whatever is left
+  /// when streaming stops is delivered as a final, shorter chunk
+  #[napi(ts_args_type = "callback: (chunk: EegChunk) => void")]
+  pub fn on_eeg_chunk(&self, callback: JsFunction) -> Result<()> {
+    self
+      .lsl_shared
+      .callbacks
+      .eeg_chunk
+      .set(js_callback(callback)?);
+    Ok(())
   }
 
+  #[napi]
+  pub fn off_eeg_chunk(&self) {
+    self.lsl_shared.callbacks.eeg_chunk.clear();
+  }
+
+  /// Called with every sample from every sensor, tagged with its `kind`.
+  /// The call rate is the sum of the sensor rates (256 Hz EEG + 64 Hz PPG,
+  /// plus 52 Hz each of accel and gyro with `imu` on), and samples of
+  /// different kinds interleave in chunk-sized bursts, so filter on `kind`
+  #[napi(ts_args_type = "callback: (sample: Sample) => void")]
+  pub fn on_sample(&self, callback: JsFunction) -> Result<()> {
+    self.lsl_shared.callbacks.sample.set(js_callback(callback)?);
+    Ok(())
+  }
+
+  #[napi]
+  pub fn off_sample(&self) {
+    self.lsl_shared.callbacks.sample.clear();
+  }
+
+  /// The newest sample of `kind` ("eeg", "ppg", or with `imu` on "accel"
+  /// and "gyro") that `onSample` got in the current or last stream, for UIs
+  /// that draw on their own clock rather than per sample. Null until one
+  /// arrives in a stream
+  /// @throws if `kind` isn't one of those
+  #[napi]
+  pub fn get_latest_sample(&self, kind: String) -> Result<Option<Sample>> {
+    let latest = match kind.as_str() {
+      "eeg" => &self.lsl_shared.latest_eeg,
+      "ppg" => &self.lsl_shared.latest_ppg,
+      "accel" => &self.lsl_shared.latest_accelerometer,
+      "gyro" => &self.lsl_shared.latest_gyro,
+      _ => {
+        return Err(napi::Error::from_reason(format!(
+          "kind must be \"eeg\", \"ppg\", \"accel\" or \"gyro\", got {}",
+          kind
+        )));
+      }
+    };
+    Ok(latest.lock().unwrap().clone())
+  }
+
+  /// The newest head orientation from `orientation`, as a unit quaternion
+  /// `[w, x, y, z]`, or null until the first gyroscope sample of a stream
   #[napi(getter)]
-  pub fn is_streaming(&self, env: Env) -> Result<JsBoolean> {
-    let state = self.state.try_lock()
-      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?;
-    env.get_boolean(state.is_streaming())
+  pub fn orientation(&self) -> Option<Vec<f64>> {
+    self.lsl_shared.latest_orientation.lock().unwrap().clone()
+  }
+
+  /// The samples `onSample` gets (of one `kind` if set), pulled instead of
+  /// pushed: `for await (const sample of device.sampleStream())`, or
+  /// `Readable.from(device.sampleStream())` for an object-mode Readable.
+  /// Samples wait in a buffer of `bufferSamples` until read; when a slow
+  /// reader lets it fill up, `overflow` decides whether the oldest queued
+  /// sample or the incoming one is dropped (counted in `dropped`), so the
+  /// device is never held up. The stream ends once the current stream (or
+  /// the next, if not streaming) stops, after what's buffered is read
+  /// @throws if an option is invalid
+  #[napi(ts_return_type = "SampleStream")]
+  pub fn sample_stream(&self, env: Env, options: Option<SampleStreamOptions>) -> Result<JsObject> {
+    let queue = Arc::new(sample_queue(options.unwrap_or_default())?);
+    self.lsl_shared.callbacks.sample_streams.add(queue.clone());
+    let stream = SampleStream::new(queue).into_instance(env)?.as_object(env);
+    make_async_iterable(env, stream)
+  }
+
+  /// Called when the EEG rate measured over a 5 s window deviates from the
+  /// nominal 256 Hz by more than `rateWarningPct`
+  #[napi(ts_args_type = "callback: (warning: RateWarning) => void")]
+  pub fn on_rate_warning(&self, callback: JsFunction) -> Result<()> {
+    self
+      .lsl_shared
+      .callbacks
+      .rate_warning
+      .set(js_callback(callback)?);
+    Ok(())
+  }
+
+  /// Called once per stream with the labels of the EEG channels that sent no
+  /// packets at all within `silentChannelTimeoutMs`. That points at the
+  /// preset or hardware, whereas bad contact still delivers (noisy) data
+  #[napi(ts_args_type = "callback: (channels: string[]) => void")]
+  pub fn on_channel_silent(&self, callback: JsFunction) -> Result<()> {
+    self
+      .lsl_shared
+      .callbacks
+      .channel_silent
+      .set(js_callback(callback)?);
+    Ok(())
+  }
+
+  #[napi]
+  pub fn off_channel_silent(&self) {
+    self.lsl_shared.callbacks.channel_silent.clear();
+  }
+
+  /// Called with the measured packet loss (in percent) just before a
+  /// `reconnectOnLossPct` relink. If the relink fails the stream stops and
+  /// `lastError` says why
+  #[napi(ts_args_type = "callback: (lossPct: number) => void")]
+  pub fn on_loss_reconnect(&self, callback: JsFunction) -> Result<()> {
+    self
+      .lsl_shared
+      .callbacks
+      .loss_reconnect
+      .set(js_callback(callback)?);
+    Ok(())
+  }
+
+  #[napi]
+  pub fn off_loss_reconnect(&self) {
+    self.lsl_shared.callbacks.loss_reconnect.clear();
+  }
+
+  /// Called with each notification from a characteristic the crate doesn't
+  /// decode. They're otherwise only counted in
+  /// `StreamStats.unhandledNotifications`
+  #[napi(ts_args_type = "callback: (notification: UnhandledNotification) => void")]
+  pub fn on_unhandled_notification(&self, callback: JsFunction) -> Result<()> {
+    self
+      .lsl_shared
+      .callbacks
+      .unhandled_notification
+      .set(js_callback(callback)?);
+    Ok(())
+  }
+
+  #[napi]
+  pub fn off_unhandled_notification(&self) {
+    self.lsl_shared.callbacks.unhandled_notification.clear();
+  }
+
+  /// Called with the new lifecycle state (see `lifecycle`) on every transition
+  #[napi(ts_args_type = "callback: (state: string) => void")]
+  pub fn on_state_change(&self, callback: JsFunction) -> Result<()> {
+    self
+      .lsl_shared
+      .callbacks
+      .state_change
+      .set(js_callback(callback)?);
+    Ok(())
+  }
+
+  #[napi]
+  pub fn off_state_change(&self) {
+    self.lsl_shared.callbacks.state_change.clear();
+  }
+
+  /// Called when the EEG or PPG outlet gets its first LSL consumer (e.g. a
+  /// recorder opens an inlet). Changes lasting under a second are ignored
+  #[napi(ts_args_type = "callback: () => void")]
+  pub fn on_consumer_connected(&self, callback: JsFunction) -> Result<()> {
+    self
+      .lsl_shared
+      .callbacks
+      .consumer_connected
+      .set(js_callback(callback)?);
+    Ok(())
+  }
+
+  #[napi]
+  pub fn off_consumer_connected(&self) {
+    self.lsl_shared.callbacks.consumer_connected.clear();
+  }
+
+  /// Called when the last LSL consumer goes away, including when streaming
+  /// stops while one is connected
+  #[napi(ts_args_type = "callback: () => void")]
+  pub fn on_consumer_disconnected(&self, callback: JsFunction) -> Result<()> {
+    self
+      .lsl_shared
+      .callbacks
+      .consumer_disconnected
+      .set(js_callback(callback)?);
+    Ok(())
+  }
+
+  #[napi]
+  pub fn off_consumer_disconnected(&self) {
+    self.lsl_shared.callbacks.consumer_disconnected.clear();
+  }
+
+  /// Called with a message when the LSL side stops taking samples mid-stream.
+  /// The device is halted and `isStreaming` turns false before this fires
+  #[napi(ts_args_type = "callback: (error: string) => void")]
+  pub fn on_lsl_error(&self, callback: JsFunction) -> Result<()> {
+    self
+      .lsl_shared
+      .callbacks
+      .lsl_error
+      .set(js_callback(callback)?);
+    Ok(())
+  }
+
+  #[napi]
+  pub fn off_lsl_error(&self) {
+    self.lsl_shared.callbacks.lsl_error.clear();
+  }
+
+  #[napi]
+  pub fn off_rate_warning(&self) {
+    self.lsl_shared.callbacks.rate_warning.clear();
+  }
+
+  #[napi]
+  pub fn off_eeg(&self) {
+    self.lsl_shared.callbacks.eeg.clear();
+  }
+
+  #[napi]
+  pub fn off_ppg(&self) {
+    self.lsl_shared.callbacks.ppg.clear();
+  }
+
+  /// Called with each PPG sample's raw 24-bit words (AMBIENT, INFRARED,
+  /// RED) as received, for doing your own decoding. Each is the big-endian
+  /// 24-bit value from the packet as an unsigned integer (0 to 0xFFFFFF),
+  /// taken before `ppgSigned` sign extension and before any filtering or
+  /// `ppgOutputRate` decimation, so it fires at 64 Hz
+  #[napi(ts_args_type = "callback: (sample: number[]) => void")]
+  pub fn on_ppg_raw(&self, callback: JsFunction) -> Result<()> {
+    self
+      .lsl_shared
+      .callbacks
+      .ppg_raw
+      .set(js_callback(callback)?);
+    Ok(())
+  }
+
+  #[napi]
+  pub fn off_ppg_raw(&self) {
+    self.lsl_shared.callbacks.ppg_raw.clear();
+  }
+
+  /// Called when `startStreaming` finds another outlet with this device's
+  /// EEG source_id, see `sourceIdCheckMs`
+  #[napi(ts_args_type = "callback: (conflict: SourceIdConflict) => void")]
+  pub fn on_source_id_conflict(&self, callback: JsFunction) -> Result<()> {
+    self
+      .lsl_shared
+      .callbacks
+      .source_id_conflict
+      .set(js_callback(callback)?);
+    Ok(())
   }
 
+  #[napi]
+  pub fn off_source_id_conflict(&self) {
+    self.lsl_shared.callbacks.source_id_conflict.clear();
+  }
+
+  /// Called with the verdict on each EEG epoch, see `epochQuality`
+  #[napi(ts_args_type = "callback: (epoch: EpochQuality) => void")]
+  pub fn on_epoch_quality(&self, callback: JsFunction) -> Result<()> {
+    self
+      .lsl_shared
+      .callbacks
+      .epoch_quality
+      .set(js_callback(callback)?);
+    Ok(())
+  }
+
+  #[napi]
+  pub fn off_epoch_quality(&self) {
+    self.lsl_shared.callbacks.epoch_quality.clear();
+  }
+
+  /// Removes every registered callback. Calls already queued are dropped,
+  /// so none of them fire after this returns
+  #[napi]
+  pub fn clear_callbacks(&self) {
+    self.lsl_shared.callbacks.clear_all();
+  }
+
+  /// Stops one sink from receiving samples without affecting the BLE stream
+  /// or the other sinks: "lsl" (the outlets stay open but get nothing), "js"
+  /// (the sample callbacks) or "udp". Lasts until `resumeSink`, across streams
+  /// @throws if the sink name is unknown
+  #[napi]
+  pub fn pause_sink(&self, name: String) -> Result<()> {
+    sink_pause(&self.lsl_shared, &name)?.store(true, Ordering::Relaxed);
+    Ok(())
+  }
+
+  /// @throws if the sink name is unknown
+  #[napi]
+  pub fn resume_sink(&self, name: String) -> Result<()> {
+    sink_pause(&self.lsl_shared, &name)?.store(false, Ordering::Relaxed);
+    Ok(())
+  }
+
+  /// Names of the sinks paused by `pauseSink`
   #[napi(getter)]
-  pub fn is_connected(&self, env: Env) -> Result<JsBoolean> {
-    let state = self.state.try_lock()
-      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?;
-    env.get_boolean(state.is_connected())
+  pub fn paused_sinks(&self) -> Vec<String> {
+    self.lsl_shared.paused_sinks.paused()
   }
 
-}
+  /// Names of the callbacks currently registered, e.g. `["eeg", "ppg"]`
+  #[napi]
+  pub fn registered_callbacks(&self) -> Vec<String> {
+    self.lsl_shared.callbacks.registered()
+  }
 
-#[napi(object)]
-pub struct DeviceAdapterOptions {
-  pub ble_uuid: Option<JsString>,
-  pub rssi_interval_ms: Option<JsNumber>,
-  /// If present, this will record the XDF to this path
-  pub xdf_record_path: Option<JsString>,
+  /// Halts and disconnects the device and removes every callback, so nothing
+  /// is left streaming or calling into JS. Safe to call more than once and
+  /// when not connected. Runs automatically on normal exit and
+  /// `process.exit()`, but not when the process is killed
+  #[napi]
+  pub async fn shutdown(&self) -> napi::Result<()> {
+    self.lsl_shared.callbacks.clear_all();
+    self.stop_burst_cycle();
+    shutdown_connector(&self.connector)
+      .await
+      .map_err(|e| napi::Error::from_reason(format!("Failed to shut down: {}", e)))?;
+
+    let lifecycle = {
+      let mut state = self.state.lock().unwrap();
+      state.set_disconnected();
+      state.lifecycle()
+    };
+    if lifecycle != LifecycleState::Idle {
+      transition(&self.state, &self.lsl_shared, LifecycleState::Disconnecting)?;
+      transition(&self.state, &self.lsl_shared, LifecycleState::Idle)?;
+    }
+    Ok(())
+  }
+
+  /// Also ends a live stream without a `stopStreaming` call first, and
+  /// like `stopStreaming` resolves only once its outlets are closed (with
+  /// `bracketMarkers`, after the `stream_stop` marker has gone out)
+  #[napi]
+  pub async fn disconnect(&self) -> napi::Result<()> {
+    let mut connector_guard = self.connector.lock().await;
+    // Holding the connector, so the watcher can't be midway through its own disconnect
+    if let Some(watch) = self.idle_watch.lock().unwrap().take() {
+      watch.abort();
+    }
+    self.stop_burst_cycle();
+    disconnect_device(connector_guard.as_mut(), &self.state, &self.lsl_shared).await?;
+    await_lsl_teardown(&self.lsl_threads).await
+  }
+
+  /// Called with the reason when the device is disconnected without a
+  /// `disconnect` call. Currently only "idle", see `idleDisconnectMs`. The
+  /// lifecycle has already gone back to "idle" when this fires
+  #[napi(ts_args_type = "callback: (reason: string) => void")]
+  pub fn on_auto_disconnect(&self, callback: JsFunction) -> Result<()> {
+    self
+      .lsl_shared
+      .callbacks
+      .auto_disconnect
+      .set(js_callback(callback)?);
+    Ok(())
+  }
+
+  #[napi]
+  pub fn off_auto_disconnect(&self) {
+    self.lsl_shared.callbacks.auto_disconnect.clear();
+  }
+
+  /// Packet sizes for diagnosing truncated notifications. btleplug doesn't
+  /// expose the negotiated MTU on any platform, so `mtu` is null and the
+  /// sizes are the ones the Muse sends, which fit the 23-byte default MTU
+  /// @throws if its not connected
+  #[napi(getter)]
+  pub fn packet_sizes(&self) -> Result<PacketSizes> {
+    let state = self
+      .state
+      .try_lock()
+      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?;
+    if !state.is_connected() {
+      return Err(napi::Error::from_reason("Device not connected"));
+    }
+    Ok(PacketSizes {
+      mtu: None,
+      eeg_bytes: EEG_PACKET_BYTES,
+      ppg_bytes: PPG_PACKET_BYTES,
+    })
+  }
+
+  /// @throws if its not connected
+  #[napi(getter)]
+  pub fn ble_name(&self, env: Env) -> Result<JsString> {
+    let state = self
+      .state
+      .try_lock()
+      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?;
+    match state.get_device_name() {
+      Some(name) => env.create_string(name),
+      None => Err(napi::Error::from_reason("Device not connected")),
+    }
+  }
+
+  /// @throws if its not connected
+  #[napi(getter)]
+  pub fn ble_uuid(&self, env: Env) -> Result<JsString> {
+    let state = self
+      .state
+      .try_lock()
+      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?;
+    match state.get_device_uuid() {
+      Some(uuid) => env.create_string(uuid),
+      None => Err(napi::Error::from_reason("Device not connected")),
+    }
+  }
+
+  #[napi(getter)]
+  pub fn is_streaming(&self, env: Env) -> Result<JsBoolean> {
+    let state = self
+      .state
+      .try_lock()
+      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?;
+    env.get_boolean(state.is_streaming())
+  }
+
+  /// "sampling" or "resting" while streaming with `burst`, otherwise "off"
+  #[napi(getter)]
+  pub fn burst_state(&self) -> Result<String> {
+    let state = self
+      .state
+      .try_lock()
+      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?;
+    let burst_state = match (self.burst, state.is_streaming()) {
+      (Some(_), true) if self.burst_resting.load(Ordering::Relaxed) => "resting",
+      (Some(_), true) => "sampling",
+      _ => "off",
+    };
+    Ok(burst_state.to_string())
+  }
+
+  /// True while `connect` is scanning for the headband
+  #[napi(getter)]
+  pub fn is_scanning(&self) -> bool {
+    self.link_status.scanning.load(Ordering::Relaxed)
+  }
+
+  /// Milliseconds since a control command was last written successfully on
+  /// this connection, or null if none has been. Compare with
+  /// `lastSampleAgeMs`: a device that takes commands but sends no samples has
+  /// a stalled data characteristic rather than a dead link
+  #[napi(getter)]
+  pub fn last_command_age_ms(&self) -> Option<f64> {
+    age_ms(*self.link_status.last_command.lock().unwrap())
+  }
+
+  /// Milliseconds since a sample was last pushed to LSL in this stream, or
+  /// null if none has been
+  #[napi(getter)]
+  pub fn last_sample_age_ms(&self) -> Option<f64> {
+    age_ms(*self.lsl_shared.last_sample_at.lock().unwrap())
+  }
+
+  #[napi(getter)]
+  pub fn is_connected(&self, env: Env) -> Result<JsBoolean> {
+    let state = self
+      .state
+      .try_lock()
+      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?;
+    env.get_boolean(state.is_connected())
+  }
+
+  /// Counters for the current (or last) streaming session
+  #[napi]
+  pub fn get_stats(&self) -> StreamStats {
+    self.lsl_shared.stats.lock().unwrap().clone()
+  }
+
+  /// Counts of the threads and tasks behind streaming, for debugging leaks
+  /// across start/stop cycles. Approximate: tasks may be finishing as it's read
+  #[napi]
+  pub async fn runtime_stats(&self) -> RuntimeStats {
+    let lsl_threads = self
+      .lsl_threads
+      .lock()
+      .unwrap()
+      .iter()
+      .filter(|thread| !thread.is_finished())
+      .count() as u32;
+    let (notification_task_running, keepalive_running) = self
+      .connector
+      .lock()
+      .await
+      .as_ref()
+      .map_or((false, false), |connector| connector.task_status());
+
+    RuntimeStats {
+      active_streaming_tasks: lsl_threads + notification_task_running as u32,
+      lsl_threads,
+      notification_task_running,
+      keepalive_running,
+    }
+  }
+
+  /// Latest EEG timestamp minus latest PPG timestamp in milliseconds, or null
+  /// until both streams have samples. Both streams are stamped from the same
+  /// BLE receipt clock, so this stays within one PPG chunk (~94 ms) as chunks
+  /// complete; alignment of the timestamps themselves is bounded by the BLE
+  /// connection interval jitter (typically within ~15 ms)
+  #[napi(getter)]
+  pub fn eeg_ppg_offset_ms(&self) -> Option<f64> {
+    self.lsl_shared.stats.lock().unwrap().eeg_ppg_offset_ms
+  }
+
+  /// The filters and channel selection applied to the samples, for display
+  /// or for storing alongside a recording. Fixed when the device is created
+  #[napi]
+  pub fn get_processing_config(&self) -> ProcessingConfig {
+    ProcessingConfig {
+      lsl: processing_options(&self.lsl_options.dsp),
+      js: self.lsl_options.js_dsp.as_ref().map(processing_options),
+      include_aux: self.lsl_options.include_aux,
+      include_sample_index: self.lsl_options.include_sample_index,
+      filter_exclude_channels: self
+        .lsl_options
+        .dsp
+        .eeg_exclude
+        .iter()
+        .map(|&channel| eeg_channel_labels(true)[channel].to_string())
+        .collect(),
+      ppg_output_rate: PPG_NOMINAL_RATE / self.lsl_options.dsp.ppg_decimation as f64,
+    }
+  }
+
+  /// Every option as resolved by the constructor, with the defaults filled
+  /// in, alongside the processing config. Flags are always present; only
+  /// optional values that weren't set and have no default (e.g.
+  /// `keepaliveIntervalMs`, `idleDisconnectMs`, `udpTarget`) are left out.
+  /// Options are fixed when the device is created, so this is what every
+  /// connection and stream of this device uses
+  #[napi]
+  pub fn get_effective_options(&self, env: Env) -> Result<EffectiveOptions> {
+    let string = |value: &str| env.create_string(value);
+    let number = |value: f64| env.create_double(value);
+    let flag = |value: bool| env.get_boolean(value);
+    let millis = |duration: Duration| number(duration.as_millis() as f64);
+    let ble = &self.ble_options;
+    let lsl = &self.lsl_options;
+    let processing = self.get_processing_config();
+
+    let options = DeviceAdapterOptions {
+      ble_uuid: self.target_uuid.as_deref().map(string).transpose()?,
+      rssi_interval_ms: self
+        .rssi_interval_ms
+        .map(|ms| number(ms.into()))
+        .transpose()?,
+      xdf_record_path: self.xdf_record_path.as_deref().map(string).transpose()?,
+      ppg_smoothing_window: Some(number(lsl.dsp.ppg_smoothing_window as f64)?),
+      ppg_subtract_ambient: Some(flag(lsl.dsp.ppg_subtract_ambient)?),
+      ppg_drop_ambient: Some(flag(lsl.dsp.ppg_drop_ambient)?),
+      eeg_header_bytes: Some(number(ble.eeg_header_bytes as f64)?),
+      ppg_header_bytes: Some(number(ble.ppg_header_bytes as f64)?),
+      keepalive_interval_ms: ble
+        .keepalive_interval_ms
+        .map(|ms| number(ms as f64))
+        .transpose()?,
+      lsl_outlet_retries: Some(number(lsl.outlet_retries.into())?),
+      rate_warning_pct: Some(number(lsl.rate_warning_pct)?),
+      ppg_signed: Some(flag(ble.ppg_signed)?),
+      reset_on_disconnect: Some(flag(ble.reset_on_disconnect)?),
+      include_aux: Some(flag(lsl.include_aux)?),
+      include_sample_index: Some(flag(lsl.include_sample_index)?),
+      chunked_push: Some(flag(lsl.chunked_push)?),
+      silent_channel_timeout_ms: Some(millis(ble.silent_channel_timeout)?),
+      clamp_counts: lsl
+        .dsp
+        .eeg_clamp
+        .map(|limit| number(limit.into()))
+        .transpose()?,
+      clamp_to_nan: Some(flag(lsl.dsp.eeg_clamp_nan)?),
+      reconnect_on_loss_pct: ble.reconnect_on_loss_pct.map(number).transpose()?,
+      bracket_markers: Some(flag(lsl.bracket_markers)?),
+      lsl_jitter_buffer_ms: Some(number(lsl.jitter_buffer_secs * 1000.0)?),
+      js_chunk_samples: Some(number(lsl.js_chunk_samples as f64)?),
+      timestamp_source: Some(string(lsl.timestamp_source.as_str())?),
+      js_processing: lsl.js_dsp.as_ref().map(processing_options),
+      dejitter_timestamps: Some(flag(lsl.dejitter_timestamps)?),
+      udp_target: lsl.udp_target.as_deref().map(string).transpose()?,
+      adapter: ble.adapter.as_deref().map(string).transpose()?,
+      min_rssi: ble.min_rssi.map(|dbm| number(dbm.into())).transpose()?,
+      service_ready_ms: Some(millis(ble.service_ready_timeout)?),
+      scan_all: Some(flag(ble.scan_all)?),
+      filter_exclude_channels: Some(processing.filter_exclude_channels.clone()),
+      idle_disconnect_ms: self.idle_disconnect.map(millis).transpose()?,
+      ppg_output_rate: Some(number(processing.ppg_output_rate)?),
+      control_command_gap_ms: Some(millis(ble.control_command_gap)?),
+      scan_poll_interval_ms: Some(millis(ble.scan_poll_interval)?),
+      measure_latency: Some(flag(lsl.measure_latency)?),
+      preview_outlet: Some(flag(lsl.preview_outlet)?),
+      channel_format: Some(string(if lsl.eeg_int16 { "int16" } else { "float32" })?),
+      auto_connect_on_stream: Some(flag(self.auto_connect_on_stream)?),
+      burst: self.burst.map(|burst| BurstOptions {
+        on_ms: Some(burst.on.as_millis() as u32),
+        every_ms: Some(burst.every.as_millis() as u32),
+      }),
+      epoch_quality: lsl.epoch_quality.map(epoch_quality_options),
+      stream_types: Some(StreamTypeOptions {
+        eeg: Some(lsl.eeg_description().stream_type),
+        ppg: Some(lsl.ppg_description().stream_type),
+        markers: lsl
+          .markers_description()
+          .map(|description| description.stream_type),
+        preview: lsl
+          .preview_description()
+          .map(|description| description.stream_type),
+        epoch_quality: lsl
+          .epoch_quality_description()
+          .map(|description| description.stream_type),
+        accelerometer: lsl
+          .accelerometer_description()
+          .map(|description| description.stream_type),
+        gyroscope: lsl
+          .gyroscope_description()
+          .map(|description| description.stream_type),
+        orientation: lsl
+          .orientation_description()
+          .map(|description| description.stream_type),
+      }),
+      imu: Some(flag(ble.imu)?),
+      orientation: lsl
+        .orientation_gain
+        .map(|gain| OrientationOptions { gain: Some(gain) }),
+      source_id_check_ms: Some(millis(lsl.source_id_check.unwrap_or_default())?),
+      disambiguate_source_ids: Some(flag(self.disambiguate_source_ids)?),
+    };
+    Ok(EffectiveOptions {
+      options,
+      processing,
+    })
+  }
+
+  /// Metadata of the live EEG, PPG and (with `bracketMarkers`,
+  /// `previewOutlet` and `epochQuality`) markers, preview and epoch quality
+  /// outlets, the same values their StreamInfo was built from. Empty when
+  /// not streaming
+  #[napi]
+  pub fn get_stream_info(&self) -> Result<Vec<StreamDescription>> {
+    let uids = self
+      .lsl_shared
+      .outlet_uids
+      .try_lock()
+      .map_err(|_| napi::Error::from_reason("Failed to acquire outlet lock"))?;
+    if uids.is_none() {
+      return Ok(Vec::new());
+    }
+    // The source_id check may have extended the configured suffix
+    let mut options = self.lsl_options.clone();
+    options.source_id_suffix = self.lsl_shared.source_id_suffix.lock().unwrap().clone();
+    Ok(options.stream_descriptions())
+  }
+
+  /// EEG channels reported silent in the current (or last) stream
+  #[napi(getter)]
+  pub fn silent_channels(&self) -> Vec<String> {
+    self
+      .lsl_shared
+      .stats
+      .lock()
+      .unwrap()
+      .silent_eeg_channels
+      .clone()
+  }
+
+  /// The uids of the live EEG/PPG outlets, or null when not streaming.
+  /// liblsl assigns a new uid to every outlet instance, so integration tests
+  /// should read them here rather than expecting a fixed value
+  #[napi(getter)]
+  pub fn outlet_uids(&self) -> Result<Option<OutletUids>> {
+    let uids = self
+      .lsl_shared
+      .outlet_uids
+      .try_lock()
+      .map_err(|_| napi::Error::from_reason("Failed to acquire outlet lock"))?;
+    Ok(uids.clone().map(|(eeg, ppg)| OutletUids { eeg, ppg }))
+  }
+
+  /// Whether LSL outlets currently exist. Turns false only once they've
+  /// been destroyed, which `stopStreaming` waits for
+  #[napi(getter)]
+  pub fn lsl_active(&self) -> Result<bool> {
+    let uids = self
+      .lsl_shared
+      .outlet_uids
+      .try_lock()
+      .map_err(|_| napi::Error::from_reason("Failed to acquire outlet lock"))?;
+    Ok(uids.is_some())
+  }
+
+  /// Labels of the EEG channels, in the order they're pushed
+  #[napi(getter)]
+  pub fn eeg_channels(&self) -> Vec<String> {
+    channel_names(
+      eeg_channel_labels(self.lsl_options.include_aux),
+      self.lsl_options.include_sample_index,
+    )
+  }
+
+  /// Labels of the PPG channels, in the order they're pushed to LSL
+  #[napi(getter)]
+  pub fn ppg_channels(&self) -> Vec<String> {
+    let dsp = &self.lsl_options.dsp;
+    channel_names(
+      ppg_channel_labels(dsp.ppg_subtract_ambient, dsp.ppg_drop_ambient),
+      self.lsl_options.include_sample_index,
+    )
+  }
+
+  #[napi(getter)]
+  pub fn supports_sleep_mode(&self, env: Env) -> Result<JsBoolean> {
+    let state = self
+      .state
+      .try_lock()
+      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?;
+    env.get_boolean(state.supports_sleep_mode())
+  }
+
+  /// Battery charge in percent from the last telemetry packet. The Muse
+  /// only sends telemetry while streaming (every few seconds); use
+  /// `waitForBattery` to query it otherwise
+  /// @throws "Telemetry not available" until a packet has arrived since
+  /// connecting
+  #[napi(getter)]
+  pub fn battery_level(&self, env: Env) -> Result<JsNumber> {
+    let state = self
+      .state
+      .try_lock()
+      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?;
+    match state.telemetry() {
+      Some(telemetry) => env.create_double(telemetry.battery_percent),
+      None => Err(napi::Error::from_reason("Telemetry not available")),
+    }
+  }
+
+  /// The last telemetry packet since connecting, or null before the first
+  #[napi(getter)]
+  pub fn telemetry(&self) -> Result<Option<Telemetry>> {
+    let state = self
+      .state
+      .try_lock()
+      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?;
+    Ok(state.telemetry())
+  }
+
+  /// Whether the headband is on its charger. Not supported: the telemetry
+  /// packet has no charging flag and the battery charge is too jittery to
+  /// tell, so this is always null. Use `telemetry.batteryPercent` instead
+  #[napi(getter)]
+  pub fn is_charging(&self) -> Option<bool> {
+    None
+  }
+
+  /// The hardware revision from the last `readHardwareRevision`, or null
+  /// if it hasn't been read since connecting
+  #[napi(getter)]
+  pub fn hardware_revision(&self) -> Result<Option<String>> {
+    let state = self
+      .state
+      .try_lock()
+      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?;
+    Ok(state.hardware_revision().map(String::from))
+  }
+
+  /// Why the last stream stopped on its own or the last connect failed,
+  /// cleared by `connect` and `startStreaming`
+  #[napi(getter)]
+  pub fn last_error(&self) -> Result<Option<String>> {
+    let state = self
+      .state
+      .try_lock()
+      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?;
+    Ok(state.last_error().map(String::from))
+  }
+
+  /// Where the device is in its lifecycle, with the error that put it in
+  /// the "error" state
+  #[napi(getter)]
+  pub fn lifecycle(&self) -> Result<Lifecycle> {
+    let state = self
+      .state
+      .try_lock()
+      .map_err(|_| napi::Error::from_reason("Failed to acquire state lock"))?;
+    Ok(Lifecycle {
+      state: state.lifecycle().as_str().to_string(),
+      last_error: state.last_error().map(String::from),
+    })
+  }
+}
+
+/// Runs several headbands from one object, addressing them by the index
+/// `addDevice` returned. Each device gets its own connection (give each its
+/// own `adapter` to spread them over several radios), and its outlets'
+/// source_ids end in `-<index>` so LSL consumers can tell them apart. Each
+/// device streams through its own LSL thread; there is no runtime shared
+/// between them to coordinate
+#[napi]
+#[derive(Default)]
+pub struct MuseManager {
+  devices: Mutex<Vec<Arc<MuseDevice>>>,
+}
+
+#[napi]
+impl MuseManager {
+  #[napi(constructor)]
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Same as `MuseDevice.listDevices`, to pick handles or `bleUuid`s from
+  #[napi]
+  pub async fn discover(
+    scan_ms: Option<u32>,
+    scan_all: Option<bool>,
+  ) -> napi::Result<Vec<DiscoveredDevice>> {
+    MuseDevice::list_devices(scan_ms, scan_all).await
+  }
+
+  /// Creates a device from `options` and returns its index
+  #[napi]
+  pub fn add_device(&self, env: Env, options: DeviceAdapterOptions) -> Result<u32> {
+    let mut device = MuseDevice::new(env, options)?;
+    let mut devices = self.devices.lock().unwrap();
+    let index = devices.len() as u32;
+    device.lsl_options.source_id_suffix = format!("-{}", index);
+    devices.push(Arc::new(device));
+    Ok(index)
+  }
+
+  /// Every device added, in index order
+  #[napi]
+  pub fn devices(&self) -> Vec<ManagedDevice> {
+    let devices = self.devices.lock().unwrap();
+    devices
+      .iter()
+      .enumerate()
+      .map(|(index, device)| {
+        let state = device.state.lock().unwrap();
+        ManagedDevice {
+          index: index as u32,
+          name: state.get_device_name().map(String::from),
+          uuid: state.get_device_uuid().map(String::from),
+          lifecycle: state.lifecycle().as_str().to_string(),
+          source_id_suffix: device.lsl_options.source_id_suffix.clone(),
+        }
+      })
+      .collect()
+  }
+
+  /// See `MuseDevice.connect`
+  #[napi]
+  pub async fn connect(&self, index: u32, handle: Option<String>) -> napi::Result<()> {
+    self.device(index)?.connect(handle).await
+  }
+
+  /// Connects every device that isn't connected yet, one at a time since
+  /// devices sharing an adapter would otherwise scan over each other
+  /// @throws on the first device that fails, naming its index
+  #[napi]
+  pub async fn connect_all(&self) -> napi::Result<()> {
+    let devices = self.devices.lock().unwrap().clone();
+    for (index, device) in devices.iter().enumerate() {
+      if device.state.lock().unwrap().is_connected() {
+        continue;
+      }
+      device
+        .connect(None)
+        .await
+        .map_err(|e| napi::Error::from_reason(format!("Device {}: {}", index, e.reason)))?;
+    }
+    Ok(())
+  }
+
+  #[napi]
+  pub async fn start_streaming(&self, index: u32) -> napi::Result<()> {
+    self.device(index)?.start_streaming().await
+  }
+
+  #[napi]
+  pub async fn stop_streaming(&self, index: u32) -> napi::Result<()> {
+    self.device(index)?.stop_streaming().await
+  }
+
+  #[napi]
+  pub async fn disconnect(&self, index: u32) -> napi::Result<()> {
+    self.device(index)?.disconnect().await
+  }
+
+  #[napi]
+  pub fn get_stats(&self, index: u32) -> Result<StreamStats> {
+    Ok(self.device(index)?.get_stats())
+  }
+
+  /// Shuts every device down (see `MuseDevice.shutdown`), carrying on past
+  /// failures so one stuck headband doesn't keep the others streaming.
+  /// Devices are also shut down when the Node environment is torn down
+  /// @throws with the first failure once all have been tried
+  #[napi]
+  pub async fn shutdown(&self) -> napi::Result<()> {
+    let devices = self.devices.lock().unwrap().clone();
+    let mut first_error = None;
+    for (index, device) in devices.iter().enumerate() {
+      if let Err(e) = device.shutdown().await {
+        let error = napi::Error::from_reason(format!("Device {}: {}", index, e.reason));
+        first_error.get_or_insert(error);
+      }
+    }
+    first_error.map_or(Ok(()), Err)
+  }
+}
+
+impl MuseManager {
+  fn device(&self, index: u32) -> Result<Arc<MuseDevice>> {
+    let devices = self.devices.lock().unwrap();
+    devices
+      .get(index as usize)
+      .cloned()
+      .ok_or_else(|| napi::Error::from_reason(format!("No device at index {}", index)))
+  }
+}
+
+#[napi(object)]
+pub struct DeviceAdapterOptions {
+  pub ble_uuid: Option<JsString>,
+  pub rssi_interval_ms: Option<JsNumber>,
+  /// If present, this will record the XDF to this path
+  pub xdf_record_path: Option<JsString>,
+  /// Moving-average window (in samples) applied to each PPG channel before
+  /// pushing to LSL. The outlet still advertises 64 Hz. Defaults to no smoothing
+  pub ppg_smoothing_window: Option<JsNumber>,
+  /// Subtract the ambient reading from the infrared and red PPG channels to
+  /// reduce ambient-light interference, pushing IR-ambient and RED-ambient.
+  /// Defaults to false (raw values)
+  pub ppg_subtract_ambient: Option<JsBoolean>,
+  /// With `ppgSubtractAmbient`, leave the ambient channel out so the PPG
+  /// outlet and `onPpg` carry only the two corrected channels. Defaults to false
+  pub ppg_drop_ambient: Option<JsBoolean>,
+  /// Bytes to skip at the start of each EEG packet. Defaults to 2 (the
+  /// sequence counter); falls back to 2 if larger than the packet
+  pub eeg_header_bytes: Option<JsNumber>,
+  /// Bytes to skip at the start of each PPG packet. Defaults to 2 (the
+  /// sequence counter); falls back to 2 if larger than the packet
+  pub ppg_header_bytes: Option<JsNumber>,
+  /// If present, sends the Muse keep-alive command (`k`) at this interval
+  /// while connected so idle links aren't dropped by the platform
+  pub keepalive_interval_ms: Option<JsNumber>,
+  /// Extra attempts at creating the LSL outlets (500 ms apart) before
+  /// `startStreaming` fails. Defaults to 2
+  pub lsl_outlet_retries: Option<JsNumber>,
+  /// Deviation (in percent) of the measured EEG rate from 256 Hz that fires
+  /// `onRateWarning`. Defaults to 10
+  pub rate_warning_pct: Option<JsNumber>,
+  /// Sign-extend the 24-bit PPG values (so 0xFFFFFF is -1) instead of
+  /// treating them as unsigned. Defaults to false
+  pub ppg_signed: Option<JsBoolean>,
+  /// Restore the Muse's factory preset on `disconnect` by sending halt (`h`)
+  /// then `p21`, so the next user doesn't inherit a custom preset. Write
+  /// failures are ignored and the link is still closed. Defaults to false
+  pub reset_on_disconnect: Option<JsBoolean>,
+  /// Keep the AUX electrode as the 5th EEG channel. When false the EEG
+  /// outlet and `onEeg` carry only TP9, AF7, AF8, TP10. Defaults to true
+  pub include_aux: Option<JsBoolean>,
+  /// Append a `SampleIndex` channel to the EEG and PPG outputs (LSL, UDP
+  /// and JS) counting samples from the start of the stream. Samples lost
+  /// in transit still use up their indices, so a jump of more than 1 is a
+  /// gap. Wraps at 2^24 to stay exact as float32. Defaults to false
+  pub include_sample_index: Option<JsBoolean>,
+  /// Push each BLE chunk (12 EEG / 6 PPG samples) to LSL at once, stamped
+  /// with its last sample's time so liblsl deduces the rest at the nominal
+  /// rate. False pushes each sample with its own timestamp. PPG decimated
+  /// by `ppgOutputRate` is always pushed per sample. Defaults to true
+  pub chunked_push: Option<JsBoolean>,
+  /// How long after `startStreaming` an EEG channel may go without a single
+  /// packet before `onChannelSilent` reports it. Defaults to 5000
+  pub silent_channel_timeout_ms: Option<JsNumber>,
+  /// Largest absolute EEG value pushed, in the decoder's raw counts (0-255
+  /// before filtering); beyond it values are clamped to the limit and
+  /// counted in `StreamStats.clampedEegValues`. Applies to the values as
+  /// pushed to the EEG outlet. Defaults to no clamping
+  pub clamp_counts: Option<JsNumber>,
+  /// Replace out-of-range EEG values with NaN instead of the limit
+  pub clamp_to_nan: Option<JsBoolean>,
+  /// EEG packet loss (in percent, measured over 5 s windows) above which the
+  /// BLE link is dropped and re-established while the outlets stay open.
+  /// `onLossReconnect` fires first; relinks are at least 60 s apart.
+  /// Defaults to never reconnecting
+  pub reconnect_on_loss_pct: Option<JsNumber>,
+  /// Open a "Markers" outlet with the streams and push `stream_start` when
+  /// streaming starts and `stream_stop` just before the outlets close, so
+  /// recordings have unambiguous session boundaries. Defaults to false
+  pub bracket_markers: Option<JsBoolean>,
+  /// Holds each sample this long after its timestamp before pushing to LSL,
+  /// so BLE bursts go out at their nominal spacing and stalls shorter than
+  /// this don't show up as gaps. Adds exactly this much latency, which
+  /// doesn't grow if a stall persists. Defaults to 0 (push immediately)
+  pub lsl_jitter_buffer_ms: Option<JsNumber>,
+  /// EEG samples aggregated per `onEegChunk` call. The default 12 (one BLE
+  /// packet, ~47 ms) has the lowest latency; larger values such as 256 (1 s)
+  /// cost fewer JS calls but delay each sample by up to that long
+  pub js_chunk_samples: Option<JsNumber>,
+  /// Clock for the timestamps passed to `onSample` and `onEegChunk`: "lsl"
+  /// (`local_clock()`, the default), "unix" (seconds since the epoch) or
+  /// "monotonic" (seconds since the stream started). The LSL outlets always
+  /// use LSL time
+  pub timestamp_source: Option<JsString>,
+  /// Give the JS callbacks (`onEeg`, `onPpg`, `onSample`, `onEegChunk`) their
+  /// own filter chain, run on a copy of each raw sample, instead of the
+  /// output of the LSL chain set by the options above. Settings left out
+  /// are off. Costs one extra 3-5 float copy per sample and a second set of
+  /// filter state (e.g. the smoothing window). Defaults to sharing the LSL chain
+  pub js_processing: Option<ProcessingOptions>,
+  /// Replace the receipt-based sample timestamps, which bunch up with BLE
+  /// bursts, with ones fitted to the regular sampling clock (a regression of
+  /// timestamp on sample index over roughly the last 30 s). Used for LSL,
+  /// the JS callbacks and UDP; the fitted EEG rate is reported as
+  /// `StreamStats.dejitteredEegRate`. Defaults to false
+  pub dejitter_timestamps: Option<JsBoolean>,
+  /// `host:port` to also send each sample to as one UDP datagram, for
+  /// low-latency consumers on a LAN that can tolerate loss. The layout, all
+  /// little-endian: a kind byte (0 = EEG, 1 = PPG), the LSL timestamp as an
+  /// f64, then the channels as f32s, as pushed to LSL (so the count is
+  /// `(length - 9) / 4`). The socket is closed when streaming stops
+  pub udp_target: Option<JsString>,
+  /// Info string of the BLE adapter to scan and connect through, as listed
+  /// by `listDevices` and `checkEnvironment`. Defaults to the first adapter
+  /// that isn't powered off and can start a scan
+  pub adapter: Option<JsString>,
+  /// Weakest signal (in dBm, e.g. -70) a Muse may have for `connect` to
+  /// pick it. If only weaker ones are found, `connect` fails with an error
+  /// suggesting moving closer. Skipped, with a logged note, where the
+  /// platform doesn't report RSSI. Defaults to accepting any signal
+  pub min_rssi: Option<JsNumber>,
+  /// How long `connect` waits, re-discovering services, for the Muse
+  /// characteristics to appear. Some platforms briefly report an empty or
+  /// incomplete service list (or fail discovery) right after connecting; each
+  /// retry is logged on stderr. If the characteristics never show up,
+  /// `connect` fails rather than leaving a device that can't stream.
+  /// Defaults to 3000
+  pub service_ready_ms: Option<JsNumber>,
+  /// Scan for every BLE device and pick Muses by name alone, instead of
+  /// filtering on the Muse service UUID. Needed for units whose
+  /// advertisements leave the service out (it only shows up after
+  /// connecting), which the filtered scan can't see on macOS/iOS
+  /// (CoreBluetooth filters on advertised services) and on some BlueZ
+  /// setups. Slower and noisier in busy environments. Defaults to false
+  pub scan_all: Option<JsBoolean>,
+  /// EEG channels, by label (e.g. `["EEG_AUX"]`), that skip the filter
+  /// chains (currently the `clampCounts` clamp) and are passed on raw,
+  /// e.g. when the AUX port carries a custom sensor. Each label must be one
+  /// of `eegChannels`, or the constructor throws. Defaults to none
+  pub filter_exclude_channels: Option<Vec<String>>,
+  /// Disconnect automatically once the device has been connected but not
+  /// streaming for this long, e.g. to free a shared headband and save its
+  /// battery. Streaming, `stopStreaming` and `ping` restart the countdown.
+  /// Fires `onAutoDisconnect` with "idle". Defaults to off
+  pub idle_disconnect_ms: Option<JsNumber>,
+  /// PPG rate (in Hz) to stream at, for bandwidth-constrained setups. The
+  /// 64 Hz PPG is low-pass filtered below the new Nyquist frequency and
+  /// decimated, and the PPG outlet advertises the reduced rate. Must divide
+  /// 64 evenly (32, 16, ...), or the constructor throws. `ppgSmoothingWindow`
+  /// then counts samples at the reduced rate. Defaults to 64
+  pub ppg_output_rate: Option<JsNumber>,
+  /// Pause (in ms) between the commands `startStreaming` sends to start
+  /// the device. The commands are written without response, and sent
+  /// back-to-back some platforms' BLE controllers silently drop one, leaving
+  /// a connected device that never streams. Raise it if that still happens,
+  /// 0 sends them back-to-back. Defaults to 20
+  pub control_command_gap_ms: Option<JsNumber>,
+  /// How often (in ms) `connect` checks the scan results for a Muse. Slower
+  /// polling costs less CPU but can add up to this much to connecting.
+  /// There's no active/passive choice: btleplug always scans actively on
+  /// Windows and leaves it to BlueZ and CoreBluetooth, which scan actively
+  /// by default. Defaults to 100
+  pub scan_poll_interval_ms: Option<JsNumber>,
+  /// Measure how long each BLE chunk's last sample takes from receipt to
+  /// just before its LSL push, for `getLatencyStats`. This is the crate's
+  /// own processing delay (decoding, filters, queueing to the LSL thread),
+  /// not the BLE link's or the consumer's, and it includes all of
+  /// `lslJitterBufferMs`. Costs a clock read and a lock per chunk.
+  /// Defaults to false
+  pub measure_latency: Option<JsBoolean>,
+  /// Also open a 32 Hz EEG outlet ("Muse S Gen 2 EEG Preview", type
+  /// "EEGPreview") for live plots, so a UI can subscribe to it cheaply
+  /// while a recorder takes the full-rate "EEG" outlet. It carries the raw
+  /// EEG, low-pass filtered and decimated, before `clampCounts` and
+  /// without the sample index. The filter smooths and slightly delays the
+  /// signal: it's for visualization only, not analysis. Defaults to false
+  pub preview_outlet: Option<JsBoolean>,
+  /// Sample format of the EEG outlet: "float32" (default) or "int16", which
+  /// halves its bandwidth. int16 samples are 1/32 of a raw EEG count,
+  /// spanning ±1024; larger values saturate. Each channel's `scale` in the
+  /// StreamInfo gives the raw counts per int16 count. The PPG outlet stays
+  /// float32. Can't be combined with `includeSampleIndex`
+  pub channel_format: Option<JsString>,
+  /// Let `startStreaming` connect first (as `connect()` would, with
+  /// `bleUuid` and the other options) when the device isn't connected,
+  /// instead of throwing "Device not connected". Defaults to false
+  pub auto_connect_on_stream: Option<JsBoolean>,
+  /// Judge the raw EEG in fixed-length epochs, aligned to the first sample
+  /// of the stream, and flag each one clean or not on a "Muse S Gen 2
+  /// Epoch Quality" outlet (1 = clean, 0 = artifact, stamped with the
+  /// epoch's first sample) and through `onEpochQuality`. An epoch has an
+  /// artifact when any of TP9, AF7, AF8 and TP10 exceeds the range or step
+  /// threshold; AUX is ignored. Unlike `getSignalQuality` this is meant for
+  /// rejecting analysis windows. Off by default
+  pub epoch_quality: Option<EpochQualityOptions>,
+  /// Content types to give the outlets instead of their defaults, which
+  /// recorders and viewers use to categorize streams. A type outside the
+  /// XDF conventions ("EEG", "PPG", "Markers", "Accelerometer", ...) is
+  /// used anyway, with a warning on stderr
+  pub stream_types: Option<StreamTypeOptions>,
+  /// Stream in bursts for low duty-cycle logging: `onMs` of data every
+  /// `everyMs`, with the device halted in between. The BLE link stays up
+  /// while resting, so this saves the sensors' and notifications' power
+  /// but not the connection's. The outlets stay open across rests, with
+  /// `burst_start`/`burst_stop` markers (see `bracketMarkers`) around each
+  /// burst; rate warnings are off. Anything computed over time (signal
+  /// quality, epochs, dejittered timestamps) sees the gaps, so it isn't
+  /// continuous. `onMs` must be at least `silentChannelTimeoutMs`. Off by
+  /// default
+  pub burst: Option<BurstOptions>,
+  /// Subscribe to the accelerometer and gyroscope and push them to their
+  /// own outlets ("Accelerometer" in g and "Gyroscope" in deg/s, X, Y, Z
+  /// at 52 Hz), e.g. to regress head movement out of the EEG offline. The
+  /// samples go to LSL only, not to the JS callbacks. Defaults to false
+  pub imu: Option<JsBoolean>,
+  /// Fuse the accelerometer and gyroscope into head orientation, pushed as
+  /// a unit quaternion (W, X, Y, Z) per gyroscope sample to a "Muse S Gen 2
+  /// Orientation" outlet and kept for the `orientation` getter. It's an
+  /// estimate, not ground truth: it starts level and takes a few seconds
+  /// (longer with a lower `gain`) to settle on the head's tilt, and with no
+  /// magnetometer the heading is relative to the start and drifts. Needs
+  /// `imu`. Off by default
+  pub orientation: Option<OrientationOptions>,
+  /// How long `startStreaming` looks on the network for an outlet already
+  /// using this device's EEG source_id (e.g. a second headband run without
+  /// distinct ones), which LSL consumers would mix up with this one. A
+  /// conflict is logged and reported to `onSourceIdConflict`. When there's
+  /// none the whole wait is spent, delaying the start, so it's opt-in: 500
+  /// is usually enough on a LAN. Defaults to 0, which skips the check
+  pub source_id_check_ms: Option<JsNumber>,
+  /// When the source_id check finds a conflict, append the headband's name
+  /// to every outlet's source_id for this stream instead of keeping the
+  /// conflicting ones, as `getStreamInfo` then shows.
+  /// Defaults to false, since consumers may be looking for the usual ids
+  pub disambiguate_source_ids: Option<JsBoolean>,
+}
+
+/// Filters for one sink, as in `DeviceAdapterOptions`
+#[napi(object)]
+pub struct ProcessingOptions {
+  pub ppg_smoothing_window: Option<u32>,
+  pub ppg_subtract_ambient: Option<bool>,
+  pub ppg_drop_ambient: Option<bool>,
+  pub clamp_counts: Option<f64>,
+  pub clamp_to_nan: Option<bool>,
+}
+
+/// Duty cycle for `burst`
+#[napi(object)]
+pub struct BurstOptions {
+  /// How long each burst streams, defaults to 10000
+  pub on_ms: Option<u32>,
+  /// Time from the start of one burst to the next, defaults to 60000
+  pub every_ms: Option<u32>,
+}
+
+/// Content type of each outlet, see `streamTypes`
+#[napi(object)]
+pub struct StreamTypeOptions {
+  /// Defaults to "EEG"
+  pub eeg: Option<String>,
+  /// Defaults to "PPG"
+  pub ppg: Option<String>,
+  /// Defaults to "Markers"
+  pub markers: Option<String>,
+  /// Defaults to "EEGPreview", so it isn't mistaken for the full-rate EEG
+  pub preview: Option<String>,
+  /// Defaults to "EpochQuality"
+  pub epoch_quality: Option<String>,
+  /// Defaults to "Accelerometer"
+  pub accelerometer: Option<String>,
+  /// Defaults to "Gyroscope"
+  pub gyroscope: Option<String>,
+  /// Defaults to "Orientation"
+  pub orientation: Option<String>,
+}
+
+/// Buffering of a `sampleStream`
+#[napi(object)]
+#[derive(Default)]
+pub struct SampleStreamOptions {
+  /// Only samples of this kind, `"eeg"`, `"ppg"`, `"accel"` or `"gyro"`; all by default
+  pub kind: Option<String>,
+  /// Samples held for a reader that falls behind, defaults to 1024
+  pub buffer_samples: Option<u32>,
+  /// What a full buffer drops: `"dropOldest"` (the default) keeps the
+  /// freshest data, `"dropNewest"` keeps a gapless start
+  pub overflow: Option<String>,
+}
+
+/// Thresholds for `epochQuality`
+#[napi(object)]
+pub struct EpochQualityOptions {
+  /// Epoch length, defaults to 1000
+  pub epoch_ms: Option<u32>,
+  /// Largest peak-to-peak range (raw counts) a clean epoch may have on a
+  /// channel, defaults to 150
+  pub max_range_counts: Option<f64>,
+  /// Largest change (raw counts) between consecutive samples a clean epoch
+  /// may have on a channel, defaults to 50
+  pub max_step_counts: Option<f64>,
+}
+
+/// Tuning for `orientation`
+#[napi(object)]
+pub struct OrientationOptions {
+  /// How hard each step is pulled toward the accelerometer's gravity, in
+  /// rad/s: higher settles faster but lets head acceleration tilt the
+  /// estimate more. Defaults to 0.1
+  pub gain: Option<f64>,
+}
+
+#[napi(object)]
+pub struct ProcessingConfig {
+  /// Filters applied before pushing to LSL
+  pub lsl: ProcessingOptions,
+  /// The JS callbacks' own chain (`jsProcessing`), or null when they get
+  /// the `lsl` chain's output
+  pub js: Option<ProcessingOptions>,
+  pub include_aux: bool,
+  pub include_sample_index: bool,
+  pub filter_exclude_channels: Vec<String>,
+  pub ppg_output_rate: f64,
+}
+
+/// A Muse found by `listDevices`
+#[napi(object)]
+pub struct DiscoveredDevice {
+  pub name: String,
+  /// Pass as `bleUuid` to connect to this headband
+  pub uuid: String,
+  /// Pass as `adapter` to connect through the adapter that saw it
+  pub adapter: String,
+  pub rssi: Option<i32>,
+  /// Pass to `connect` within 60 s to connect without scanning again
+  pub handle: String,
+}
+
+/// The result of `getEffectiveOptions`
+#[napi(object)]
+pub struct EffectiveOptions {
+  pub options: DeviceAdapterOptions,
+  pub processing: ProcessingConfig,
+}
+
+#[napi(object)]
+pub struct EnvironmentReport {
+  pub ble_adapter_present: bool,
+  /// Info string of the adapter that `connect` will use
+  pub ble_adapter_name: Option<String>,
+  pub lsl_available: bool,
+  pub adapter_count: u32,
+}
+
+#[napi(object)]
+pub struct GattService {
+  pub uuid: String,
+  pub primary: bool,
+  pub characteristics: Vec<GattCharacteristic>,
+}
+
+#[napi(object)]
+pub struct GattCharacteristic {
+  pub uuid: String,
+  pub read: bool,
+  pub write: bool,
+  pub write_without_response: bool,
+  pub notify: bool,
+  pub indicate: bool,
+}
+
+/// The device's lifecycle state: "idle", "scanning", "connecting",
+/// "connected", "streaming", "paused" (restarting on a new preset),
+/// "reconnecting" (relinking after packet loss or a dropped notification
+/// stream), "disconnecting" or "error"
+#[napi(object)]
+pub struct Lifecycle {
+  pub state: String,
+  pub last_error: Option<String>,
+}
+
+/// What `runDiagnostics` found over its run
+#[napi(object)]
+pub struct DiagnosticsReport {
+  /// How long the stream actually ran
+  pub duration_ms: f64,
+  pub eeg_samples: u32,
+  /// EEG samples received per second of the run, nominally 256
+  pub measured_eeg_rate: f64,
+  /// EEG packet loss over the last complete 5 s window
+  pub packet_loss_pct: f64,
+  pub channels: Vec<ChannelDiagnostics>,
+}
+
+/// One EEG channel in a `DiagnosticsReport`, in the decoder's raw units
+#[napi(object)]
+pub struct ChannelDiagnostics {
+  pub label: String,
+  /// Mean value over the run
+  pub dc_offset: f64,
+  /// RMS around the mean; flat near 0 or very large points at poor contact
+  pub rms_noise: f64,
+  /// Samples at the smallest or largest value the decoder produces
+  pub saturated_samples: u32,
+}
+
+/// What `getSignalQuality` found in the last second of EEG
+#[napi(object)]
+pub struct SignalQuality {
+  /// EEG samples the metrics cover, one second's worth
+  pub window_samples: u32,
+  pub channels: Vec<ChannelQuality>,
+}
+
+/// One EEG channel in a `SignalQuality`, in the decoder's raw units
+#[napi(object)]
+pub struct ChannelQuality {
+  pub label: String,
+  /// "ok", "flat" or "saturated"
+  pub quality: String,
+  pub dc_offset: f64,
+  /// Variance around the mean, i.e. `rmsNoise` squared
+  pub variance: f64,
+  pub rms_noise: f64,
+  pub saturated_samples: u32,
+}
+
+/// An external clock reading paired with LSL time by `pushSyncPulse`
+#[napi(object)]
+#[derive(Clone)]
+pub struct SyncPulse {
+  /// The time passed to `pushSyncPulse`, in the external clock's units
+  pub external_time: f64,
+  /// `local_clock()` when the pulse was recorded, in seconds
+  pub local_time: f64,
+}
+
+/// Receipt-to-push latency percentiles from `getLatencyStats`
+#[napi(object)]
+pub struct LatencyStats {
+  /// Chunks measured
+  pub samples: u32,
+  pub min_ms: f64,
+  pub median_ms: f64,
+  pub p99_ms: f64,
+  pub max_ms: f64,
+}
+
+#[napi(object)]
+pub struct PacketSizes {
+  /// Negotiated ATT MTU, null where the platform doesn't report it
+  pub mtu: Option<u32>,
+  /// Expected size of each EEG notification
+  pub eeg_bytes: u32,
+  /// Expected size of each PPG notification
+  pub ppg_bytes: u32,
+}
+
+#[napi(object)]
+pub struct OutletUids {
+  pub eeg: String,
+  pub ppg: String,
+}
+
+#[napi(object)]
+pub struct ManagedDevice {
+  pub index: u32,
+  /// Null until the device has connected
+  pub name: Option<String>,
+  pub uuid: Option<String>,
+  pub lifecycle: String,
+  /// What the device's outlets' source_ids end in
+  pub source_id_suffix: String,
+}
+
+/// Routes the notification task's reports to the device state, stats and JS callbacks
+fn stream_events(
+  state: Arc<Mutex<DeviceStateManager>>,
+  lsl_shared: Arc<LslShared>,
+) -> StreamEvents {
+  Arc::new(move |event| match event {
+    // If the LSL thread dies mid-stream the BLE side halts itself, so
+    // only the state and the JS side need to hear about it here
+    StreamEvent::SinkClosed => {
+      let error = "LSL sink stopped receiving samples".to_string();
+      stop_with_error(&state, &lsl_shared, error.clone());
+      lsl_shared.callbacks.lsl_error.call(error);
+    }
+    StreamEvent::ChannelsSilent(channels) => {
+      let labels = eeg_channel_labels(true);
+      let channels: Vec<String> = channels
+        .into_iter()
+        .map(|idx| labels[idx].to_string())
+        .collect();
+      lsl_shared.stats.lock().unwrap().silent_eeg_channels = channels.clone();
+      lsl_shared.callbacks.channel_silent.call(channels);
+    }
+    StreamEvent::PacketLoss(loss_pct) => {
+      lsl_shared.stats.lock().unwrap().packet_loss_pct = loss_pct;
+    }
+    StreamEvent::Relinking(loss_pct) => {
+      lsl_shared.stats.lock().unwrap().loss_reconnects += 1;
+      lsl_shared.callbacks.loss_reconnect.call(loss_pct);
+      let _ = transition(&state, &lsl_shared, LifecycleState::Reconnecting);
+    }
+    StreamEvent::StreamEnded => {
+      let _ = transition(&state, &lsl_shared, LifecycleState::Reconnecting);
+    }
+    StreamEvent::MalformedPacket { uuid, len } => {
+      let mut stats = lsl_shared.stats.lock().unwrap();
+      // Usually every packet is malformed at once (e.g. a wrong header
+      // size), so only the first is worth logging
+      if stats.malformed_packets == 0 {
+        eprintln!("Dropped a malformed {}-byte packet from {}", len, uuid);
+      }
+      stats.malformed_packets += 1;
+    }
+    StreamEvent::Resynced => {
+      lsl_shared.stats.lock().unwrap().resyncs += 1;
+    }
+    StreamEvent::Relinked => {
+      let _ = transition(&state, &lsl_shared, LifecycleState::Streaming);
+    }
+    StreamEvent::RelinkFailed(error) => stop_with_error(&state, &lsl_shared, error),
+    StreamEvent::DuplicatePacket => {
+      lsl_shared.stats.lock().unwrap().duplicate_packets += 1;
+    }
+    StreamEvent::Telemetry(telemetry) => state.lock().unwrap().set_telemetry(telemetry),
+    StreamEvent::Unhandled { uuid, data } => {
+      lsl_shared.stats.lock().unwrap().unhandled_notifications += 1;
+      lsl_shared
+        .callbacks
+        .unhandled_notification
+        .call(UnhandledNotification {
+          uuid: uuid.to_string(),
+          data,
+        });
+    }
+  })
+}
+
+impl MuseDevice {
+  /// Starts the `idleDisconnectMs` watcher, which disconnects once the
+  /// device has been connected without streaming or other activity for that
+  /// long. It ends with the connection
+  fn start_idle_watch(&self) {
+    let Some(idle) = self.idle_disconnect else {
+      return;
+    };
+    *self.last_activity.lock().unwrap() = Instant::now();
+    let connector = self.connector.clone();
+    let state = self.state.clone();
+    let lsl_shared = self.lsl_shared.clone();
+    let last_activity = self.last_activity.clone();
+
+    let watch = tokio::spawn(async move {
+      // Whether the device is connected, and idle long enough to disconnect
+      let check = || {
+        let state = state.lock().unwrap();
+        let mut last_activity = last_activity.lock().unwrap();
+        if state.is_streaming() {
+          *last_activity = Instant::now();
+        }
+        (state.is_connected(), last_activity.elapsed() >= idle)
+      };
+      loop {
+        tokio::time::sleep(IDLE_CHECK_INTERVAL.min(idle)).await;
+        match check() {
+          (false, _) => return,
+          (true, false) => continue,
+          (true, true) => {}
+        }
+        // Check again with the connector held, a call may have got in first
+        let mut connector_guard = connector.lock().await;
+        match check() {
+          (false, _) => return,
+          (true, false) => continue,
+          (true, true) => {}
+        }
+
+        match disconnect_device(connector_guard.as_mut(), &state, &lsl_shared).await {
+          Ok(()) => lsl_shared
+            .callbacks
+            .auto_disconnect
+            .call("idle".to_string()),
+          Err(e) => eprintln!("Idle disconnect failed: {}", e.reason),
+        }
+        return;
+      }
+    });
+    if let Some(previous) = self.idle_watch.lock().unwrap().replace(watch) {
+      previous.abort();
+    }
+  }
+
+  /// Starts alternating between bursts and rests for `burst`, from a
+  /// freshly started stream. It ends with the stream
+  fn start_burst_cycle(&self) {
+    let Some(burst) = self.burst else {
+      return;
+    };
+    self.burst_resting.store(false, Ordering::Relaxed);
+    let connector = self.connector.clone();
+    let state = self.state.clone();
+    let lsl_shared = self.lsl_shared.clone();
+    let resting = self.burst_resting.clone();
+
+    let cycle = tokio::spawn(async move {
+      loop {
+        tokio::time::sleep(burst.on).await;
+        if !burst_step(&connector, &state, &lsl_shared, &resting, true).await {
+          return;
+        }
+        tokio::time::sleep(burst.every - burst.on).await;
+        if !burst_step(&connector, &state, &lsl_shared, &resting, false).await {
+          return;
+        }
+      }
+    });
+    if let Some(previous) = self.burst_cycle.lock().unwrap().replace(cycle) {
+      previous.abort();
+    }
+  }
+
+  /// Called holding the connector, so the cycle isn't midway through a step
+  fn stop_burst_cycle(&self) {
+    if let Some(cycle) = self.burst_cycle.lock().unwrap().take() {
+      cycle.abort();
+    }
+    self.burst_resting.store(false, Ordering::Relaxed);
+  }
+}
+
+/// Disconnects the device, going through "disconnecting" back to "idle"
+async fn disconnect_device(
+  connector: Option<&mut BleConnector<PlatformPeripheral>>,
+  state: &Mutex<DeviceStateManager>,
+  lsl_shared: &LslShared,
+) -> Result<()> {
+  if let Some(connector) = connector {
+    transition(state, lsl_shared, LifecycleState::Disconnecting)?;
+    if let Err(e) = connector.disconnect().await {
+      let error = format!("Failed to disconnect: {}", e);
+      enter_error(state, lsl_shared, error.clone());
+      return Err(napi::Error::from_reason(error));
+    }
+  }
+
+  // Update device state
+  let was_disconnecting = {
+    let mut state = state.lock().unwrap();
+    state.set_disconnected();
+    state.lifecycle() == LifecycleState::Disconnecting
+  };
+  if was_disconnecting {
+    transition(state, lsl_shared, LifecycleState::Idle)?;
+  }
+
+  Ok(())
+}
+
+/// Rests (halts) or wakes the device between bursts, returning false once
+/// the stream is over. A failed command is logged and the cycle carries on
+async fn burst_step(
+  connector: &SharedConnector,
+  state: &Mutex<DeviceStateManager>,
+  lsl_shared: &LslShared,
+  resting: &AtomicBool,
+  rest: bool,
+) -> bool {
+  let connector_guard = connector.lock().await;
+  let Some(connector) = connector_guard.as_ref() else {
+    return false;
+  };
+  if !state.lock().unwrap().is_streaming() {
+    return false;
+  }
+
+  if rest {
+    lsl_shared.queue_marker(BURST_STOP_MARKER);
+    if let Err(e) = connector.rest().await {
+      eprintln!("Failed to halt the device between bursts: {}", e);
+    }
+    let _ = transition(state, lsl_shared, LifecycleState::Paused);
+  } else {
+    if let Err(e) = connector.wake().await {
+      eprintln!("Failed to restart the device for a burst: {}", e);
+    }
+    lsl_shared.queue_marker(BURST_START_MARKER);
+    let _ = transition(state, lsl_shared, LifecycleState::Streaming);
+  }
+  resting.store(rest, Ordering::Relaxed);
+  true
+}
+
+/// Halts and disconnects the device if one is connected
+async fn shutdown_connector(connector: &SharedConnector) -> std::result::Result<(), String> {
+  let mut connector_guard = connector.lock().await;
+  match connector_guard
+    .as_mut()
+    .filter(|connector| connector.is_connected())
+  {
+    Some(connector) => connector.disconnect().await.map_err(|e| e.to_string()),
+    None => Ok(()),
+  }
+}
+
+/// Waits for every LSL thread to finish, which is when its outlets have
+/// been destroyed, so a following start can't race the old outlets
+async fn await_lsl_teardown(lsl_threads: &Mutex<Vec<std::thread::JoinHandle<()>>>) -> Result<()> {
+  let deadline = Instant::now() + LSL_TEARDOWN_TIMEOUT;
+  loop {
+    let finished = {
+      let mut lsl_threads = lsl_threads.lock().unwrap();
+      lsl_threads.retain(|thread| !thread.is_finished());
+      lsl_threads.is_empty()
+    };
+    if finished {
+      return Ok(());
+    }
+    if Instant::now() >= deadline {
+      return Err(napi::Error::from_reason(format!(
+        "LSL outlets still open {} s after stopping",
+        LSL_TEARDOWN_TIMEOUT.as_secs()
+      )));
+    }
+    tokio::time::sleep(LSL_TEARDOWN_POLL).await;
+  }
+}
+
+/// Moves the lifecycle to `next` and tells `onStateChange`
+fn transition(
+  state: &Mutex<DeviceStateManager>,
+  lsl_shared: &LslShared,
+  next: LifecycleState,
+) -> Result<()> {
+  state
+    .lock()
+    .unwrap()
+    .transition(next)
+    .map_err(napi::Error::from_reason)?;
+  lsl_shared
+    .callbacks
+    .state_change
+    .call(next.as_str().to_string());
+  Ok(())
+}
+
+fn enter_error(state: &Mutex<DeviceStateManager>, lsl_shared: &LslShared, error: String) {
+  state.lock().unwrap().set_error(error);
+  // Only rejected when already in the error state
+  let _ = transition(state, lsl_shared, LifecycleState::Error);
+}
+
+fn stop_with_error(state: &Mutex<DeviceStateManager>, lsl_shared: &LslShared, error: String) {
+  state.lock().unwrap().set_streaming_stopped();
+  enter_error(state, lsl_shared, error);
+}
+
+/// When `waitForBattery` reads the battery next, or None once `deadline`
+/// has passed and it gives up
+fn next_battery_poll(
+  now: tokio::time::Instant,
+  deadline: tokio::time::Instant,
+) -> Option<tokio::time::Instant> {
+  (now < deadline).then(|| (now + BATTERY_POLL_INTERVAL).min(deadline))
+}
+
+fn processing_options(dsp: &DspOptions) -> ProcessingOptions {
+  ProcessingOptions {
+    ppg_smoothing_window: Some(dsp.ppg_smoothing_window as u32),
+    ppg_subtract_ambient: Some(dsp.ppg_subtract_ambient),
+    ppg_drop_ambient: Some(dsp.ppg_drop_ambient),
+    clamp_counts: dsp.eeg_clamp.map(f64::from),
+    clamp_to_nan: Some(dsp.eeg_clamp_nan),
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Burst {
+  on: Duration,
+  every: Duration,
+}
+
+/// `silent_channel_timeout` bounds `on`: the one-off check for a device that
+/// missed its start commands mustn't fall into a rest
+fn burst(options: BurstOptions, silent_channel_timeout: Duration) -> Result<Burst> {
+  let on = Duration::from_millis(options.on_ms.unwrap_or(DEFAULT_BURST_ON_MS).into());
+  let every = Duration::from_millis(options.every_ms.unwrap_or(DEFAULT_BURST_EVERY_MS).into());
+  if on >= every {
+    return Err(napi::Error::from_reason(
+      "burst.onMs must be shorter than burst.everyMs",
+    ));
+  }
+  if on < silent_channel_timeout {
+    return Err(napi::Error::from_reason(format!(
+      "burst.onMs must be at least silentChannelTimeoutMs ({} ms)",
+      silent_channel_timeout.as_millis()
+    )));
+  }
+  Ok(Burst { on, every })
+}
+
+/// Warns about types recorders may not recognize, but keeps them
+fn stream_types(options: StreamTypeOptions) -> StreamTypes {
+  let configured = [
+    &options.eeg,
+    &options.ppg,
+    &options.markers,
+    &options.preview,
+    &options.epoch_quality,
+    &options.accelerometer,
+    &options.gyroscope,
+    &options.orientation,
+  ];
+  for stream_type in configured.into_iter().flatten() {
+    if !RECOMMENDED_STREAM_TYPES.contains(&stream_type.as_str()) {
+      eprintln!(
+        "Stream type \"{}\" isn't a recommended LSL content type ({}), recorders may not categorize it",
+        stream_type,
+        RECOMMENDED_STREAM_TYPES.join(", ")
+      );
+    }
+  }
+  StreamTypes {
+    eeg: options.eeg,
+    ppg: options.ppg,
+    markers: options.markers,
+    preview: options.preview,
+    epoch_quality: options.epoch_quality,
+    accelerometer: options.accelerometer,
+    gyroscope: options.gyroscope,
+    orientation: options.orientation,
+  }
+}
+
+/// The headband's name (or failing that its uuid) as a source_id suffix
+fn source_id_disambiguator(state: &DeviceStateManager) -> Option<String> {
+  state
+    .get_device_name()
+    .or(state.get_device_uuid())
+    .map(|name| name.replace(|c: char| !c.is_ascii_alphanumeric(), "-"))
+}
+
+fn sample_queue(options: SampleStreamOptions) -> Result<SampleQueue> {
+  if let Some(kind) = options.kind.as_deref()
+    && !matches!(kind, "eeg" | "ppg" | "accel" | "gyro")
+  {
+    return Err(napi::Error::from_reason(format!(
+      "sampleStream kind must be \"eeg\", \"ppg\", \"accel\" or \"gyro\", got {}",
+      kind
+    )));
+  }
+  let capacity = options
+    .buffer_samples
+    .unwrap_or(DEFAULT_STREAM_BUFFER_SAMPLES);
+  if capacity == 0 {
+    return Err(napi::Error::from_reason(
+      "sampleStream bufferSamples must be at least 1",
+    ));
+  }
+  let overflow = match options.overflow.as_deref() {
+    None | Some("dropOldest") => Overflow::DropOldest,
+    Some("dropNewest") => Overflow::DropNewest,
+    Some(other) => {
+      return Err(napi::Error::from_reason(format!(
+        "sampleStream overflow must be \"dropOldest\" or \"dropNewest\", got {}",
+        other
+      )));
+    }
+  };
+  Ok(SampleQueue::new(options.kind, capacity as usize, overflow))
+}
+
+/// Gives `object` the `Symbol.asyncIterator` method `for await` and
+/// `Readable.from` look for, returning itself as the iterator
+fn make_async_iterable(env: Env, mut object: JsObject) -> Result<JsObject> {
+  let symbol: JsObject = env.get_global()?.get_named_property("Symbol")?;
+  let async_iterator: JsSymbol = symbol.get_named_property("asyncIterator")?;
+  object.set_property(
+    async_iterator,
+    env.create_function("asyncIterator", return_this)?,
+  )?;
+  Ok(object)
+}
+
+#[js_function]
+fn return_this(ctx: CallContext) -> Result<JsObject> {
+  ctx.this()
+}
+
+fn epoch_thresholds(options: EpochQualityOptions) -> Result<EpochThresholds> {
+  let epoch_ms = options.epoch_ms.unwrap_or(DEFAULT_EPOCH_MS);
+  let epoch_samples = (epoch_ms as f64 * EEG_NOMINAL_RATE / 1000.0).round() as usize;
+  if epoch_samples == 0 {
+    return Err(napi::Error::from_reason(format!(
+      "epochQuality.epochMs must cover at least one EEG sample, got {}",
+      epoch_ms
+    )));
+  }
+  Ok(EpochThresholds {
+    epoch_samples,
+    max_range: options.max_range_counts.unwrap_or(DEFAULT_EPOCH_MAX_RANGE) as f32,
+    max_step: options.max_step_counts.unwrap_or(DEFAULT_EPOCH_MAX_STEP) as f32,
+  })
+}
+
+fn epoch_quality_options(thresholds: EpochThresholds) -> EpochQualityOptions {
+  EpochQualityOptions {
+    epoch_ms: Some((thresholds.epoch_samples as f64 * 1000.0 / EEG_NOMINAL_RATE).round() as u32),
+    max_range_counts: Some(thresholds.max_range.into()),
+    max_step_counts: Some(thresholds.max_step.into()),
+  }
+}
+
+fn orientation_gain(options: OrientationOptions, imu: bool) -> Result<f64> {
+  if !imu {
+    return Err(napi::Error::from_reason(
+      "orientation needs imu, which provides its samples",
+    ));
+  }
+  let gain = options.gain.unwrap_or(DEFAULT_ORIENTATION_GAIN);
+  if !(gain > 0.0 && gain.is_finite()) {
+    return Err(napi::Error::from_reason(format!(
+      "orientation.gain must be a positive number, got {}",
+      gain
+    )));
+  }
+  Ok(gain)
+}
+
+/// The PPG decimation factor that gives `rate`, 1 when unset
+fn ppg_decimation(rate: Option<f64>) -> Result<usize> {
+  let Some(rate) = rate else {
+    return Ok(1);
+  };
+  let factor = PPG_NOMINAL_RATE / rate;
+  if !(rate > 0.0 && factor.fract() == 0.0) {
+    return Err(napi::Error::from_reason(format!(
+      "ppgOutputRate must divide 64 Hz evenly (64, 32, 16, ...), got {}",
+      rate
+    )));
+  }
+  Ok(factor as usize)
+}
+
+/// Whether `channelFormat` asks for int16 EEG
+fn eeg_int16(format: Option<String>) -> Result<bool> {
+  match format.as_deref() {
+    None | Some("float32") => Ok(false),
+    Some("int16") => Ok(true),
+    Some(other) => Err(napi::Error::from_reason(format!(
+      "channelFormat must be \"float32\" or \"int16\", got {}",
+      other
+    ))),
+  }
+}
+
+/// The clock `timestampSource` names, LSL time when unset
+fn timestamp_source(source: Option<String>) -> Result<TimestampSource> {
+  let Some(source) = source else {
+    return Ok(TimestampSource::default());
+  };
+  TimestampSource::parse(&source).ok_or_else(|| {
+    napi::Error::from_reason(format!(
+      "timestampSource must be \"lsl\", \"unix\" or \"monotonic\", got {}",
+      source
+    ))
+  })
+}
+
+/// Indices of the EEG channels named in `labels`, which must all be active
+fn eeg_channel_indices(labels: &[String], include_aux: bool) -> Result<Vec<usize>> {
+  let active = eeg_channel_labels(include_aux);
+  labels
+    .iter()
+    .map(|label| {
+      active
+        .iter()
+        .position(|active| active == label)
+        .ok_or_else(|| {
+          napi::Error::from_reason(format!(
+            "Unknown filterExcludeChannels label \"{}\", expected one of {}",
+            label,
+            active.join(", ")
+          ))
+        })
+    })
+    .collect()
+}
+
+fn sink_pause<'a>(lsl_shared: &'a LslShared, name: &str) -> Result<&'a AtomicBool> {
+  lsl_shared.paused_sinks.get(name).ok_or_else(|| {
+    napi::Error::from_reason(format!(
+      "Unknown sink \"{}\", expected \"lsl\", \"js\" or \"udp\"",
+      name
+    ))
+  })
+}
+
+fn dsp_options(options: ProcessingOptions) -> DspOptions {
+  DspOptions {
+    ppg_smoothing_window: options.ppg_smoothing_window.unwrap_or(0) as usize,
+    ppg_subtract_ambient: options.ppg_subtract_ambient.unwrap_or(false),
+    ppg_drop_ambient: options.ppg_drop_ambient.unwrap_or(false),
+    eeg_clamp: options
+      .clamp_counts
+      .filter(|&limit| limit > 0.0)
+      .map(|limit| limit as f32),
+    eeg_clamp_nan: options.clamp_to_nan.unwrap_or(false),
+    // Set device-wide from `filterExcludeChannels` and `ppgOutputRate`
+    eeg_exclude: Vec::new(),
+    ppg_decimation: 1,
+  }
+}
+
+fn age_ms(at: Option<Instant>) -> Option<f64> {
+  at.map(|at| at.elapsed().as_secs_f64() * 1000.0)
+}
+
+fn js_callback<T: ToNapiValue + 'static>(callback: JsFunction) -> Result<JsCallback<T>> {
+  callback.create_threadsafe_function(0, |ctx: ThreadSafeCallContext<T>| Ok(vec![ctx.value]))
+}
+
+/// `labels` as owned strings, with `SampleIndex` appended when it's pushed too
+fn channel_names(labels: &[&str], include_sample_index: bool) -> Vec<String> {
+  let mut names: Vec<String> = labels.iter().map(|label| label.to_string()).collect();
+  if include_sample_index {
+    names.push(SAMPLE_INDEX_LABEL.to_string());
+  }
+  names
+}
+
+fn js_string(value: Option<JsString>) -> Option<String> {
+  value.and_then(|js_str| {
+    js_str
+      .into_utf8()
+      .ok()
+      .and_then(|utf8| utf8.as_str().ok().map(|s| s.to_string()))
+  })
+}
+
+fn js_u32(value: Option<JsNumber>) -> Option<u32> {
+  value.and_then(|js_num| js_num.get_uint32().ok())
+}
+
+fn js_bool(value: Option<JsBoolean>) -> Option<bool> {
+  value.and_then(|js_bool| js_bool.get_value().ok())
+}
+
+fn js_f64(value: Option<JsNumber>) -> Option<f64> {
+  value.and_then(|js_num| js_num.get_double().ok())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn battery_wait_polls_until_the_deadline_then_gives_up() {
+    let now = tokio::time::Instant::now();
+    let deadline = now + Duration::from_secs(12);
+    assert_eq!(
+      next_battery_poll(now, deadline),
+      Some(now + BATTERY_POLL_INTERVAL)
+    );
+    // The last poll lands on the deadline rather than past it
+    let late = now + Duration::from_secs(10);
+    assert_eq!(next_battery_poll(late, deadline), Some(deadline));
+    assert_eq!(next_battery_poll(deadline, deadline), None);
+  }
 }
@@ -1,129 +1,1551 @@
-use lsl::{ChannelFormat, Pushable, StreamInfo, StreamOutlet};
-use std::sync::mpsc;
-use crate::ble::DataType;
+use crate::ble::{DataType, EEG_RAILS, IMU_SAMPLE_RATE, TimestampedData, raw_24_bit_word};
+use crate::callbacks::{Callbacks, EegChunk, EpochQuality, Sample, SourceIdConflict};
+use crate::processing::{
+    Debounced, Decimator, Dejitter, DspChain, DspOptions, EpochArtifacts, EpochThresholds,
+    JitterBuffer, LatencyLog, Orientation, RateMonitor, SignalSummary,
+};
+use crate::stats::{RateWarning, StreamStats};
+use crate::udp::{UDP_KIND_EEG, UDP_KIND_PPG, UdpSink};
+use lsl::{ChannelFormat, ExPushable, StreamInfo, StreamOutlet};
+use napi_derive::napi;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::oneshot;
+
+const OUTLET_RETRY_DELAY: Duration = Duration::from_millis(500);
+// How long the buffered loop waits for samples when nothing is queued
+const IDLE_WAIT: Duration = Duration::from_secs(1);
+
+pub const EEG_NOMINAL_RATE: f64 = 256.0;
+pub const PPG_NOMINAL_RATE: f64 = 64.0;
+const STREAM_START_MARKER: &str = "stream_start";
+const STREAM_STOP_MARKER: &str = "stream_stop";
+// Followed by the external time, e.g. "sync_pulse 1718000000.25"
+const SYNC_PULSE_MARKER: &str = "sync_pulse";
+pub const BURST_START_MARKER: &str = "burst_start";
+pub const BURST_STOP_MARKER: &str = "burst_stop";
+const EEG_CHANNEL_LABELS: [&str; 5] = ["EEG_TP9", "EEG_AF7", "EEG_AF8", "EEG_TP10", "EEG_AUX"];
+const PPG_CHANNEL_LABELS: [&str; 3] = ["PPG_AMBIENT", "PPG_INFRARED", "PPG_RED"];
+const PPG_SUBTRACTED_LABELS: [&str; 3] = [
+    "PPG_AMBIENT",
+    "PPG_INFRARED_MINUS_AMBIENT",
+    "PPG_RED_MINUS_AMBIENT",
+];
+const ACCELEROMETER_CHANNEL_LABELS: [&str; 3] = ["ACC_X", "ACC_Y", "ACC_Z"];
+const GYRO_CHANNEL_LABELS: [&str; 3] = ["GYRO_X", "GYRO_Y", "GYRO_Z"];
+const ORIENTATION_CHANNEL_LABELS: [&str; 4] = ["QUAT_W", "QUAT_X", "QUAT_Y", "QUAT_Z"];
+// Long enough to average out BLE burstiness
+const RATE_MONITOR_WINDOW: Duration = Duration::from_secs(5);
+// Long enough to average out BLE bursts, short enough to follow clock drift
+const DEJITTER_HALF_LIFE_SECS: f64 = 30.0;
+// have_consumers is cheap, but there's no need to ask per sample
+const CONSUMER_POLL_INTERVAL: Duration = Duration::from_millis(250);
+// Consumer changes shorter than this (e.g. a recorder reconnecting) aren't reported
+const CONSUMER_DEBOUNCE: Duration = Duration::from_secs(1);
+// One second of EEG per `get_signal_quality` snapshot
+const QUALITY_WINDOW_SAMPLES: u32 = EEG_NOMINAL_RATE as u32;
+// 256 Hz / 8 = 32 Hz, plenty for a live plot
+const PREVIEW_DECIMATION: usize = 8;
+// A couple of minutes of EEG and PPG chunks
+const LATENCY_LOG_CAPACITY: usize = 4096;
+/// Content types from the XDF meta-data conventions (plus the motion
+/// sensor ones) that recorders and viewers sort streams by
+pub const RECOMMENDED_STREAM_TYPES: [&str; 12] = [
+    "EEG",
+    "EMG",
+    "ECG",
+    "EOG",
+    "PPG",
+    "NIRS",
+    "Gaze",
+    "MoCap",
+    "Audio",
+    "Markers",
+    "Accelerometer",
+    "Gyroscope",
+];
+// Epochs are judged on the four head electrodes; a floating AUX input
+// would flag every one
+const EPOCH_CHANNELS: usize = 4;
+/// Raw EEG counts per int16 count. Spans ±1024, which covers the decoder's
+/// 0-255 range and filter overshoot; a power of two so the decoder's whole
+/// values round-trip exactly. Larger values saturate
+pub const EEG_INT16_SCALE: f64 = 1.0 / 32.0;
+
+/// Clock the timestamps handed to JS callbacks are expressed in
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TimestampSource {
+    /// `lsl::local_clock()`, the same as the outlets
+    #[default]
+    Lsl,
+    /// Seconds since the Unix epoch
+    Unix,
+    /// Seconds since the stream started
+    Monotonic,
+}
+
+impl TimestampSource {
+    pub fn parse(source: &str) -> Option<Self> {
+        match source {
+            "lsl" => Some(Self::Lsl),
+            "unix" => Some(Self::Unix),
+            "monotonic" => Some(Self::Monotonic),
+            _ => None,
+        }
+    }
+
+    /// The name `parse` accepts
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lsl => "lsl",
+            Self::Unix => "unix",
+            Self::Monotonic => "monotonic",
+        }
+    }
+
+    /// What to add to an LSL timestamp to express it on this clock. Taken
+    /// once per stream so the converted timestamps stay as monotonic as the
+    /// LSL ones, even if the wall clock is adjusted mid-stream
+    fn offset(self) -> f64 {
+        let unix_now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |since_epoch| since_epoch.as_secs_f64());
+        self.offset_at(lsl::local_clock(), unix_now)
+    }
+
+    /// `offset` for a stream starting at `lsl_now`, when the wall clock reads `unix_now`
+    fn offset_at(self, lsl_now: f64, unix_now: f64) -> f64 {
+        match self {
+            Self::Lsl => 0.0,
+            Self::Unix => unix_now - lsl_now,
+            Self::Monotonic => -lsl_now,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LslOptions {
+    /// Filters applied before pushing to LSL, and before the JS callbacks
+    /// unless `js_dsp` gives them their own chain
+    pub dsp: DspOptions,
+    /// Separate filters for the JS callbacks, run on a copy of each raw sample
+    pub js_dsp: Option<DspOptions>,
+    /// Extra attempts at creating the outlets before giving up
+    pub outlet_retries: u32,
+    /// Deviation (in percent) of the measured EEG rate from nominal that triggers a warning
+    pub rate_warning_pct: f64,
+    /// Keep the AUX electrode as the 5th EEG channel
+    pub include_aux: bool,
+    /// Append each sample's stream index (see `sample_index_value`) as a last channel
+    pub include_sample_index: bool,
+    /// Push whole BLE chunks stamped with their last sample's time instead of
+    /// pushing each sample with its own timestamp
+    pub chunked_push: bool,
+    /// Create a markers outlet carrying `stream_start`/`stream_stop`
+    pub bracket_markers: bool,
+    /// Delay (in seconds) samples are held for before pushing, 0 disables the jitter buffer
+    pub jitter_buffer_secs: f64,
+    /// EEG samples aggregated per `on_eeg_chunk` call
+    pub js_chunk_samples: usize,
+    /// Clock for the timestamps given to JS callbacks; the outlets always use LSL time
+    pub timestamp_source: TimestampSource,
+    /// Replace receipt-based timestamps with ones fitted to the regular sampling clock
+    pub dejitter_timestamps: bool,
+    /// `host:port` that also gets each sample as a UDP datagram
+    pub udp_target: Option<String>,
+    /// Record how long each chunk's last sample took from BLE receipt to its push
+    pub measure_latency: bool,
+    /// Create a decimated EEG outlet for UI previews next to the full-rate one
+    pub preview_outlet: bool,
+    /// Appended to every outlet's source_id, so several devices' outlets stay apart
+    pub source_id_suffix: String,
+    /// Push EEG as int16 counts of `EEG_INT16_SCALE` instead of float32
+    pub eeg_int16: bool,
+    /// Flag EEG epochs with artifacts on an outlet and `on_epoch_quality`
+    pub epoch_quality: Option<EpochThresholds>,
+    pub stream_types: StreamTypes,
+    /// The device streams in bursts, so the EEG rate isn't checked against nominal
+    pub bursts: bool,
+    /// Create accelerometer and gyroscope outlets for the IMU samples
+    pub imu: bool,
+    /// Fuse the IMU samples into an orientation outlet with this filter
+    /// gain (see `Orientation`), `None` leaves it out
+    pub orientation_gain: Option<f64>,
+    /// How long to look for an outlet already using the EEG source_id
+    /// before creating the outlets, `None` skips the check
+    pub source_id_check: Option<Duration>,
+    /// Appended (after a `-`) to the source_ids when the check finds a
+    /// conflict, `None` keeps them
+    pub source_id_disambiguator: Option<String>,
+}
+
+impl Default for LslOptions {
+    fn default() -> Self {
+        Self {
+            dsp: DspOptions::default(),
+            js_dsp: None,
+            outlet_retries: 2,
+            rate_warning_pct: 10.0,
+            include_aux: true,
+            include_sample_index: false,
+            chunked_push: true,
+            bracket_markers: false,
+            jitter_buffer_secs: 0.0,
+            js_chunk_samples: 12,
+            timestamp_source: TimestampSource::Lsl,
+            dejitter_timestamps: false,
+            udp_target: None,
+            measure_latency: false,
+            preview_outlet: false,
+            source_id_suffix: String::new(),
+            eeg_int16: false,
+            epoch_quality: None,
+            stream_types: StreamTypes::default(),
+            bursts: false,
+            imu: false,
+            orientation_gain: None,
+            source_id_check: None,
+            source_id_disambiguator: None,
+        }
+    }
+}
+
+/// Content types that replace the outlets' defaults, `None` keeps the default
+#[derive(Debug, Clone, Default)]
+pub struct StreamTypes {
+    pub eeg: Option<String>,
+    pub ppg: Option<String>,
+    pub markers: Option<String>,
+    pub preview: Option<String>,
+    pub epoch_quality: Option<String>,
+    pub accelerometer: Option<String>,
+    pub gyroscope: Option<String>,
+    pub orientation: Option<String>,
+}
+
+impl LslOptions {
+    pub fn eeg_description(&self) -> StreamDescription {
+        eeg_stream_description(self.include_aux, self.include_sample_index)
+            .with_int16_scale(self.eeg_int16)
+            .customized(self, self.stream_types.eeg.as_deref())
+    }
+
+    pub fn ppg_description(&self) -> StreamDescription {
+        ppg_stream_description(
+            self.dsp.ppg_subtract_ambient,
+            self.dsp.ppg_drop_ambient,
+            self.include_sample_index,
+            self.dsp.ppg_decimation,
+        )
+        .customized(self, self.stream_types.ppg.as_deref())
+    }
+
+    /// None when `bracket_markers` is off, as are the other optional outlets
+    pub fn markers_description(&self) -> Option<StreamDescription> {
+        self.bracket_markers.then(|| {
+            markers_stream_description().customized(self, self.stream_types.markers.as_deref())
+        })
+    }
+
+    pub fn preview_description(&self) -> Option<StreamDescription> {
+        self.preview_outlet.then(|| {
+            eeg_preview_stream_description(self.include_aux)
+                .customized(self, self.stream_types.preview.as_deref())
+        })
+    }
+
+    pub fn epoch_quality_description(&self) -> Option<StreamDescription> {
+        self.epoch_quality.map(|thresholds| {
+            epoch_quality_stream_description(thresholds.epoch_samples)
+                .customized(self, self.stream_types.epoch_quality.as_deref())
+        })
+    }
+
+    pub fn accelerometer_description(&self) -> Option<StreamDescription> {
+        self.imu.then(|| {
+            accelerometer_stream_description()
+                .customized(self, self.stream_types.accelerometer.as_deref())
+        })
+    }
+
+    pub fn gyroscope_description(&self) -> Option<StreamDescription> {
+        self.imu.then(|| {
+            gyroscope_stream_description().customized(self, self.stream_types.gyroscope.as_deref())
+        })
+    }
+
+    pub fn orientation_description(&self) -> Option<StreamDescription> {
+        self.orientation_gain.filter(|_| self.imu).map(|_| {
+            orientation_stream_description()
+                .customized(self, self.stream_types.orientation.as_deref())
+        })
+    }
+
+    /// Looks for another outlet with the EEG source_id, which consumers
+    /// would confuse with this one, reporting it to `on_source_id_conflict`
+    /// and switching to disambiguated source_ids if configured. All outlets
+    /// share the suffix, so the EEG one stands for them
+    fn check_source_ids(&mut self, shared: &LslShared) {
+        let Some(wait) = self.source_id_check else {
+            return;
+        };
+        let source_id = self.eeg_description().source_id;
+        let found =
+            lsl::resolve_byprop("source_id", &source_id, 1, wait.as_secs_f64()).unwrap_or_default();
+        let Some(existing) = found.first() else {
+            return;
+        };
+
+        let renamed_to = self.source_id_disambiguator.clone().map(|disambiguator| {
+            self.source_id_suffix
+                .push_str(&format!("-{}", disambiguator));
+            self.eeg_description().source_id
+        });
+        match &renamed_to {
+            Some(renamed_to) => eprintln!(
+                "An outlet with source_id {} already exists on {}, using {}",
+                source_id,
+                existing.hostname(),
+                renamed_to
+            ),
+            None => eprintln!(
+                "An outlet with source_id {} already exists on {}; LSL consumers may mix the two up",
+                source_id,
+                existing.hostname()
+            ),
+        }
+        shared.callbacks.source_id_conflict.call(SourceIdConflict {
+            source_id,
+            hostname: existing.hostname(),
+            renamed_to,
+        });
+    }
+
+    /// Every outlet these options create, in the order they're created
+    pub fn stream_descriptions(&self) -> Vec<StreamDescription> {
+        [self.eeg_description(), self.ppg_description()]
+            .into_iter()
+            .chain(self.markers_description())
+            .chain(self.preview_description())
+            .chain(self.epoch_quality_description())
+            .chain(self.accelerometer_description())
+            .chain(self.gyroscope_description())
+            .chain(self.orientation_description())
+            .collect()
+    }
+}
+
+/// Sinks the samples fan out to that can be paused, by name
+#[derive(Default)]
+pub struct SinkPauses {
+    pub lsl: AtomicBool,
+    pub js: AtomicBool,
+    pub udp: AtomicBool,
+}
+
+impl SinkPauses {
+    pub fn get(&self, name: &str) -> Option<&AtomicBool> {
+        match name {
+            "lsl" => Some(&self.lsl),
+            "js" => Some(&self.js),
+            "udp" => Some(&self.udp),
+            _ => None,
+        }
+    }
+
+    /// Names of the paused sinks
+    pub fn paused(&self) -> Vec<String> {
+        [("lsl", &self.lsl), ("js", &self.js), ("udp", &self.udp)]
+            .into_iter()
+            .filter(|(_, paused)| paused.load(Ordering::Relaxed))
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+}
+
+/// State shared between `MuseDevice` and the LSL thread
+#[derive(Default)]
+pub struct LslShared {
+    /// The (EEG, PPG) uids of the live outlets
+    pub outlet_uids: Mutex<Option<(String, String)>>,
+    /// The source_id suffix the current or last stream's outlets used,
+    /// including any the source_id check appended
+    pub source_id_suffix: Mutex<String>,
+    pub callbacks: Callbacks,
+    pub stats: Mutex<StreamStats>,
+    /// When a sample was last pushed in the current stream
+    pub last_sample_at: Mutex<Option<Instant>>,
+    pub paused_sinks: SinkPauses,
+    /// Collects the raw EEG while `run_diagnostics` is streaming
+    pub diagnostics: Mutex<Option<SignalSummary>>,
+    /// The last complete second of raw EEG in the current stream
+    pub signal_quality: Mutex<Option<SignalSummary>>,
+    /// Markers waiting for the LSL thread, with their LSL timestamps
+    pub pending_markers: Mutex<Vec<(String, f64)>>,
+    /// Receipt-to-push latencies (in ms) of the current or last stream,
+    /// None unless `measure_latency` is on
+    pub latency: Mutex<Option<LatencyLog>>,
+    /// The newest EEG and PPG samples handed to JS in the current or last
+    /// stream, and the newest IMU readings (only kept for `getLatestSample`)
+    pub latest_eeg: Mutex<Option<Sample>>,
+    pub latest_ppg: Mutex<Option<Sample>>,
+    pub latest_accelerometer: Mutex<Option<Sample>>,
+    pub latest_gyro: Mutex<Option<Sample>>,
+    /// The newest fused orientation (w, x, y, z), with `orientation_gain` set
+    pub latest_orientation: Mutex<Option<Vec<f64>>>,
+}
+
+impl LslShared {
+    /// Reads `local_clock()` for a pulse of the external clock at
+    /// `external_time`, queuing a marker for the markers outlet while
+    /// streaming. Returns the LSL time
+    pub fn sync_pulse(&self, external_time: f64) -> f64 {
+        let local_time = lsl::local_clock();
+        self.queue_marker_at(
+            format!("{} {}", SYNC_PULSE_MARKER, external_time),
+            local_time,
+        );
+        local_time
+    }
+
+    /// Queues `marker` for the markers outlet, stamped now, while streaming
+    pub fn queue_marker(&self, marker: &str) {
+        self.queue_marker_at(marker.to_string(), lsl::local_clock());
+    }
+
+    fn queue_marker_at(&self, marker: String, timestamp: f64) {
+        if self.outlet_uids.lock().unwrap().is_some() {
+            self.pending_markers
+                .lock()
+                .unwrap()
+                .push((marker, timestamp));
+        }
+    }
+}
+
+/// Labels of the EEG channels pushed to the outlet, AUX last when included
+pub fn eeg_channel_labels(include_aux: bool) -> &'static [&'static str] {
+    if include_aux {
+        &EEG_CHANNEL_LABELS
+    } else {
+        &EEG_CHANNEL_LABELS[..4]
+    }
+}
+
+/// Labels of the PPG channels pushed to the outlet. The ambient channel is
+/// only dropped once it has been subtracted from the others
+pub fn ppg_channel_labels(subtract_ambient: bool, drop_ambient: bool) -> &'static [&'static str] {
+    match (subtract_ambient, drop_ambient) {
+        (false, _) => &PPG_CHANNEL_LABELS,
+        (true, false) => &PPG_SUBTRACTED_LABELS,
+        (true, true) => &PPG_SUBTRACTED_LABELS[1..],
+    }
+}
+
+/// Logs the time from BLE receipt until now, just before a push
+fn record_latency(receipt_time: Option<f64>, shared: &LslShared) {
+    let Some(receipt_time) = receipt_time else {
+        return;
+    };
+    if let Some(log) = shared.latency.lock().unwrap().as_mut() {
+        log.record((lsl::local_clock() - receipt_time) * 1000.0);
+    }
+}
+
+/// Label of the channel `include_sample_index` appends
+pub const SAMPLE_INDEX_LABEL: &str = "SampleIndex";
+// float32 represents every integer below 2^24 exactly
+const SAMPLE_INDEX_WRAP: u64 = 1 << 24;
+
+/// The sample index as pushed: it wraps at 2^24 (about 18 h of EEG) so the
+/// float32 channel stays exact, and consumers should allow for the wrap
+fn sample_index_value(index: u64) -> f32 {
+    (index % SAMPLE_INDEX_WRAP) as f32
+}
+
+/// A channel as listed in an outlet's `<channels>` metadata
+#[napi(object)]
+#[derive(Debug, Clone)]
+pub struct ChannelDescription {
+    pub label: String,
+    pub unit: String,
+    pub channel_type: String,
+    /// 10-20 position of an EEG electrode (e.g. "TP9"), null for AUX and
+    /// non-EEG channels
+    pub location: Option<String>,
+}
+
+/// The metadata an outlet's StreamInfo is built from
+#[napi(object)]
+#[derive(Debug, Clone)]
+pub struct StreamDescription {
+    pub name: String,
+    pub stream_type: String,
+    pub source_id: String,
+    pub nominal_rate: f64,
+    /// Samples per chunk the outlet transmits
+    pub chunk_size: u32,
+    pub manufacturer: String,
+    pub model: String,
+    pub channels: Vec<ChannelDescription>,
+    /// Channel units per count when samples are pushed as int16, null for floats
+    pub scale: Option<f64>,
+}
+
+impl StreamDescription {
+    /// Applies the options every outlet shares: the source_id suffix and,
+    /// when given, a replacement content type
+    fn customized(mut self, options: &LslOptions, stream_type: Option<&str>) -> Self {
+        self.source_id.push_str(&options.source_id_suffix);
+        if let Some(stream_type) = stream_type {
+            self.stream_type = stream_type.to_string();
+        }
+        self
+    }
+
+    fn with_int16_scale(mut self, int16: bool) -> Self {
+        self.scale = int16.then_some(EEG_INT16_SCALE);
+        self
+    }
+}
+
+/// Saturates outside ±1024; NaN (see `clamp_to_nan`) becomes 0
+pub fn eeg_to_int16(samples: &[f32]) -> Vec<i16> {
+    samples
+        .iter()
+        .map(|&value| (value as f64 / EEG_INT16_SCALE).round() as i16)
+        .collect()
+}
+
+fn channel_descriptions(
+    labels: &[&str],
+    unit: &str,
+    channel_type: &str,
+) -> Vec<ChannelDescription> {
+    labels
+        .iter()
+        .map(|label| ChannelDescription {
+            label: label.to_string(),
+            unit: unit.to_string(),
+            channel_type: channel_type.to_string(),
+            location: None,
+        })
+        .collect()
+}
+
+/// The EEG channels, each headband electrode with its 10-20 location
+fn eeg_channel_descriptions(include_aux: bool) -> Vec<ChannelDescription> {
+    let mut channels = channel_descriptions(eeg_channel_labels(include_aux), "count", "EEG");
+    for channel in &mut channels {
+        channel.location = channel
+            .label
+            .strip_prefix("EEG_")
+            .filter(|location| *location != "AUX")
+            .map(String::from);
+    }
+    channels
+}
+
+fn sample_index_description() -> ChannelDescription {
+    ChannelDescription {
+        label: SAMPLE_INDEX_LABEL.to_string(),
+        unit: "count".to_string(),
+        channel_type: "Misc".to_string(),
+        location: None,
+    }
+}
+
+pub fn eeg_stream_description(include_aux: bool, include_sample_index: bool) -> StreamDescription {
+    let mut channels = eeg_channel_descriptions(include_aux);
+    if include_sample_index {
+        channels.push(sample_index_description());
+    }
+    StreamDescription {
+        name: "Muse S Gen 2 EEG".to_string(),
+        stream_type: "EEG".to_string(),
+        source_id: "muse-eeg".to_string(),
+        nominal_rate: EEG_NOMINAL_RATE,
+        chunk_size: 12,
+        manufacturer: "Interaxon".to_string(),
+        model: "Muse S Gen 2".to_string(),
+        channels,
+        scale: None,
+    }
+}
+
+/// A 32 Hz copy of the raw EEG for live plots, typed "EEGPreview" so
+/// consumers resolving streams of type "EEG" don't pick it up
+pub fn eeg_preview_stream_description(include_aux: bool) -> StreamDescription {
+    StreamDescription {
+        name: "Muse S Gen 2 EEG Preview".to_string(),
+        stream_type: "EEGPreview".to_string(),
+        source_id: "muse-eeg-preview".to_string(),
+        nominal_rate: EEG_NOMINAL_RATE / PREVIEW_DECIMATION as f64,
+        chunk_size: 1,
+        manufacturer: "Interaxon".to_string(),
+        model: "Muse S Gen 2".to_string(),
+        channels: eeg_channel_descriptions(include_aux),
+        scale: None,
+    }
+}
+
+/// The PPG outlet's metadata; `decimation` divides the nominal rate
+pub fn ppg_stream_description(
+    subtract_ambient: bool,
+    drop_ambient: bool,
+    include_sample_index: bool,
+    decimation: usize,
+) -> StreamDescription {
+    let mut channels = channel_descriptions(
+        ppg_channel_labels(subtract_ambient, drop_ambient),
+        "N/A",
+        "PPG",
+    );
+    if include_sample_index {
+        channels.push(sample_index_description());
+    }
+    StreamDescription {
+        name: "Muse S Gen 2 PPG".to_string(),
+        stream_type: "PPG".to_string(),
+        source_id: "muse-s-ppg".to_string(),
+        nominal_rate: PPG_NOMINAL_RATE / decimation.max(1) as f64,
+        chunk_size: 6,
+        manufacturer: "Interaxon".to_string(),
+        model: "Muse S Gen 2".to_string(),
+        channels,
+        scale: None,
+    }
+}
+
+/// One value per EEG epoch, 1 when clean and 0 when it has an artifact,
+/// stamped with the epoch's first sample
+pub fn epoch_quality_stream_description(epoch_samples: usize) -> StreamDescription {
+    StreamDescription {
+        name: "Muse S Gen 2 Epoch Quality".to_string(),
+        stream_type: "EpochQuality".to_string(),
+        source_id: "muse-epoch-quality".to_string(),
+        nominal_rate: EEG_NOMINAL_RATE / epoch_samples as f64,
+        chunk_size: 1,
+        manufacturer: "Interaxon".to_string(),
+        model: "Muse S Gen 2".to_string(),
+        channels: channel_descriptions(&["EPOCH_CLEAN"], "N/A", "Misc"),
+        scale: None,
+    }
+}
+
+/// Head motion in g, one outlet sample per IMU sample
+pub fn accelerometer_stream_description() -> StreamDescription {
+    StreamDescription {
+        name: "Muse S Gen 2 Accelerometer".to_string(),
+        stream_type: "Accelerometer".to_string(),
+        source_id: "muse-accelerometer".to_string(),
+        nominal_rate: IMU_SAMPLE_RATE,
+        chunk_size: 3,
+        manufacturer: "Interaxon".to_string(),
+        model: "Muse S Gen 2".to_string(),
+        channels: channel_descriptions(&ACCELEROMETER_CHANNEL_LABELS, "g", "Accelerometer"),
+        scale: None,
+    }
+}
+
+/// Head rotation in deg/s
+pub fn gyroscope_stream_description() -> StreamDescription {
+    StreamDescription {
+        name: "Muse S Gen 2 Gyroscope".to_string(),
+        stream_type: "Gyroscope".to_string(),
+        source_id: "muse-gyroscope".to_string(),
+        nominal_rate: IMU_SAMPLE_RATE,
+        chunk_size: 3,
+        manufacturer: "Interaxon".to_string(),
+        model: "Muse S Gen 2".to_string(),
+        channels: channel_descriptions(&GYRO_CHANNEL_LABELS, "deg/s", "Gyroscope"),
+        scale: None,
+    }
+}
+
+/// Head orientation fused from the IMU, as a unit quaternion per
+/// gyroscope sample
+pub fn orientation_stream_description() -> StreamDescription {
+    StreamDescription {
+        name: "Muse S Gen 2 Orientation".to_string(),
+        stream_type: "Orientation".to_string(),
+        source_id: "muse-orientation".to_string(),
+        nominal_rate: IMU_SAMPLE_RATE,
+        chunk_size: 3,
+        manufacturer: "Interaxon".to_string(),
+        model: "Muse S Gen 2".to_string(),
+        channels: channel_descriptions(&ORIENTATION_CHANNEL_LABELS, "N/A", "Orientation"),
+        scale: None,
+    }
+}
+
+/// Irregular-rate string stream for session markers
+pub fn markers_stream_description() -> StreamDescription {
+    StreamDescription {
+        name: "Muse S Gen 2 Markers".to_string(),
+        stream_type: "Markers".to_string(),
+        source_id: "muse-markers".to_string(),
+        nominal_rate: lsl::IRREGULAR_RATE,
+        chunk_size: 1,
+        manufacturer: "Interaxon".to_string(),
+        model: "Muse S Gen 2".to_string(),
+        channels: channel_descriptions(&["Marker"], "N/A", "Marker"),
+        scale: None,
+    }
+}
+
+fn channels_f64(samples: &[f32]) -> Vec<f64> {
+    samples.iter().map(|&v| v as f64).collect()
+}
+
+/// Whether liblsl is loaded and able to build stream metadata
+pub fn lsl_available() -> bool {
+    lsl::library_version() > 0
+        && StreamInfo::new("probe", "probe", 1, 0.0, ChannelFormat::Float32, "probe").is_ok()
+}
+
+#[derive(Clone, Copy)]
+enum Stream {
+    Eeg,
+    Ppg,
+}
 
 pub struct LslStreamManager {
     eeg_outlet: StreamOutlet,
     ppg_outlet: StreamOutlet,
+    markers_outlet: Option<StreamOutlet>,
+    preview: Option<(StreamOutlet, Decimator)>,
+    epochs: Option<(StreamOutlet, EpochArtifacts)>,
+    /// The (accelerometer, gyroscope) outlets
+    imu: Option<(StreamOutlet, StreamOutlet)>,
+    orientation: Option<(StreamOutlet, Orientation)>,
+    // Closed with the manager when the stream stops
+    udp: Option<UdpSink>,
+    dsp: DspChain,
+    js_dsp: Option<DspChain>,
+    eeg_channel_count: usize,
+    eeg_int16: bool,
+    include_sample_index: bool,
+    measure_latency: bool,
+    chunked_push: bool,
+    eeg_pending: Vec<Vec<f32>>,
+    js_chunk_samples: usize,
+    js_eeg_chunk: EegChunk,
+    js_timestamp_offset: f64,
+    eeg_dejitter: Option<Dejitter>,
+    ppg_dejitter: Option<Dejitter>,
+    ppg_pending: Vec<Vec<f32>>,
+    // Decimated PPG is pushed a sample at a time, see `push_sample`
+    ppg_decimated: bool,
+    eeg_rate: RateMonitor,
+    quality: SignalSummary,
+    consumers: Debounced,
+    last_consumer_poll: Instant,
+    rate_warning_pct: f64,
+    bursts: bool,
+    last_eeg_timestamp: Option<f64>,
+    last_ppg_timestamp: Option<f64>,
 }
 
 impl LslStreamManager {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let eeg_outlet = Self::create_eeg_outlet()?;
-        let ppg_outlet = Self::create_ppg_outlet()?;
-        
+    pub fn new(options: &LslOptions) -> Result<Self, Box<dyn std::error::Error>> {
+        let eeg_format = if options.eeg_int16 {
+            ChannelFormat::Int16
+        } else {
+            ChannelFormat::Float32
+        };
+        let eeg_outlet = Self::create_outlet(&options.eeg_description(), eeg_format)?;
+        let ppg_outlet = Self::create_outlet(&options.ppg_description(), ChannelFormat::Float32)?;
+        let markers_outlet = match options.markers_description() {
+            Some(description) => Some(Self::create_outlet(&description, ChannelFormat::String)?),
+            None => None,
+        };
+        let preview = match options.preview_description() {
+            Some(description) => {
+                let decimator = Decimator::new(
+                    description.channels.len(),
+                    PREVIEW_DECIMATION,
+                    EEG_NOMINAL_RATE,
+                );
+                Some((
+                    Self::create_outlet(&description, ChannelFormat::Float32)?,
+                    decimator,
+                ))
+            }
+            None => None,
+        };
+        let epochs = match options
+            .epoch_quality_description()
+            .zip(options.epoch_quality)
+        {
+            Some((description, thresholds)) => {
+                let detector = EpochArtifacts::new(EPOCH_CHANNELS, thresholds);
+                Some((
+                    Self::create_outlet(&description, ChannelFormat::Float32)?,
+                    detector,
+                ))
+            }
+            None => None,
+        };
+        let imu = match options
+            .accelerometer_description()
+            .zip(options.gyroscope_description())
+        {
+            Some((accelerometer, gyroscope)) => Some((
+                Self::create_outlet(&accelerometer, ChannelFormat::Float32)?,
+                Self::create_outlet(&gyroscope, ChannelFormat::Float32)?,
+            )),
+            None => None,
+        };
+        let orientation = match options
+            .orientation_description()
+            .zip(options.orientation_gain)
+        {
+            Some((description, gain)) => Some((
+                Self::create_outlet(&description, ChannelFormat::Float32)?,
+                Orientation::new(gain as f32, IMU_SAMPLE_RATE),
+            )),
+            None => None,
+        };
+        let udp = match &options.udp_target {
+            Some(target) => Some(
+                UdpSink::new(target)
+                    .map_err(|e| format!("Failed to open UDP target {}: {}", target, e))?,
+            ),
+            None => None,
+        };
+
         Ok(Self {
             eeg_outlet,
             ppg_outlet,
+            markers_outlet,
+            preview,
+            epochs,
+            imu,
+            orientation,
+            udp,
+            dsp: DspChain::new(&options.dsp),
+            js_dsp: options.js_dsp.as_ref().map(DspChain::new),
+            eeg_channel_count: eeg_channel_labels(options.include_aux).len(),
+            eeg_int16: options.eeg_int16,
+            include_sample_index: options.include_sample_index,
+            measure_latency: options.measure_latency,
+            chunked_push: options.chunked_push,
+            eeg_pending: Vec::new(),
+            js_chunk_samples: options.js_chunk_samples.max(1),
+            js_eeg_chunk: EegChunk::default(),
+            js_timestamp_offset: options.timestamp_source.offset(),
+            eeg_dejitter: options.dejitter_timestamps.then(|| {
+                Dejitter::new(EEG_NOMINAL_RATE, DEJITTER_HALF_LIFE_SECS * EEG_NOMINAL_RATE)
+            }),
+            ppg_dejitter: options.dejitter_timestamps.then(|| {
+                Dejitter::new(PPG_NOMINAL_RATE, DEJITTER_HALF_LIFE_SECS * PPG_NOMINAL_RATE)
+            }),
+            ppg_pending: Vec::new(),
+            ppg_decimated: options.dsp.ppg_decimation > 1,
+            eeg_rate: RateMonitor::new(RATE_MONITOR_WINDOW),
+            quality: SignalSummary::new(eeg_channel_labels(options.include_aux).len(), EEG_RAILS),
+            consumers: Debounced::new(false, CONSUMER_DEBOUNCE),
+            last_consumer_poll: Instant::now(),
+            rate_warning_pct: options.rate_warning_pct,
+            bursts: options.bursts,
+            last_eeg_timestamp: None,
+            last_ppg_timestamp: None,
         })
     }
 
-    fn create_eeg_outlet() -> Result<StreamOutlet, Box<dyn std::error::Error>> {
-        // Create EEG StreamInfo with metadata
-        let mut eeg_info = StreamInfo::new(
-            "Muse S Gen 2 EEG",
-            "EEG",
-            5,     // 5 EEG channels
-            256.0, // EEG sample rate
-            ChannelFormat::Float32,
-            "muse-eeg",
-        )?;
+    /// Creates the outlets, retrying since creation can fail transiently while
+    /// network interfaces are still coming up (e.g. right after boot)
+    fn new_with_retry(options: &LslOptions) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut attempt = 0;
+        loop {
+            match Self::new(options) {
+                Ok(manager) => return Ok(manager),
+                Err(e) if attempt < options.outlet_retries => {
+                    attempt += 1;
+                    eprintln!(
+                        "Failed to create LSL outlets (attempt {}), retrying: {}",
+                        attempt, e
+                    );
+                    std::thread::sleep(OUTLET_RETRY_DELAY);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
 
-        // Add EEG metadata
-        eeg_info
-            .desc()
-            .append_child_value("manufacturer", "Interaxon");
+    /// Builds an outlet whose StreamInfo carries `description`'s metadata
+    fn create_outlet(
+        description: &StreamDescription,
+        format: ChannelFormat,
+    ) -> Result<StreamOutlet, Box<dyn std::error::Error>> {
+        let mut info = StreamInfo::new(
+            &description.name,
+            &description.stream_type,
+            description.channels.len() as u32,
+            description.nominal_rate,
+            format,
+            &description.source_id,
+        )?;
 
-        // Add EEG channel information
-        let mut eeg_channels = eeg_info.desc().append_child("channels");
-        let eeg_channel_names = ["EEG_TP9", "EEG_AF7", "EEG_AF8", "EEG_TP10", "EEG_AUX"];
+        info.desc()
+            .append_child_value("manufacturer", &description.manufacturer);
 
-        for channel_name in &eeg_channel_names {
-            eeg_channels
-                .append_child("channel")
-                .append_child_value("label", channel_name)
-                .append_child_value("unit", "microvolt")
-                .append_child_value("type", "EEG");
+        // Add channel information
+        let mut channels = info.desc().append_child("channels");
+        for channel in &description.channels {
+            let mut entry = channels.append_child("channel");
+            entry
+                .append_child_value("label", &channel.label)
+                .append_child_value("unit", &channel.unit)
+                .append_child_value("type", &channel.channel_type);
+            if let Some(location) = &channel.location {
+                entry.append_child_value("location", location);
+            }
+            // Consumers multiply the counts by this to get back the unit
+            if let Some(scale) = description.scale {
+                entry.append_child_value("scale", &scale.to_string());
+            }
         }
 
         // Add acquisition system metadata
-        eeg_info
-            .desc()
+        info.desc()
             .append_child("acquisition")
-            .append_child_value("manufacturer", "Interaxon")
-            .append_child_value("model", "Muse S Gen 2");
+            .append_child_value("manufacturer", &description.manufacturer)
+            .append_child_value("model", &description.model);
 
-        Ok(StreamOutlet::new(&eeg_info, 12, 360)?)
+        Ok(StreamOutlet::new(
+            &info,
+            description.chunk_size as i32,
+            360,
+        )?)
     }
 
-    fn create_ppg_outlet() -> Result<StreamOutlet, Box<dyn std::error::Error>> {
-        // Create PPG StreamInfo with metadata
-        let mut ppg_info = StreamInfo::new(
-            "Muse S Gen 2 PPG",
-            "PPG",
-            3,    // 3 PPG channels
-            64.0, // PPG sample rate
-            ChannelFormat::Float32,
-            "muse-s-ppg",
-        )?;
+    /// The uids liblsl assigned to the (EEG, PPG) outlets. These can't be
+    /// fixed up front: liblsl generates a fresh one for every outlet instance
+    pub fn outlet_uids(&self) -> Option<(String, String)> {
+        let eeg_uid = self.eeg_outlet.info().ok()?.uid();
+        let ppg_uid = self.ppg_outlet.info().ok()?.uid();
+        Some((eeg_uid, ppg_uid))
+    }
 
-        // Add PPG metadata
-        ppg_info
-            .desc()
-            .append_child_value("manufacturer", "Interaxon");
+    pub fn push_sample(
+        &mut self,
+        sample: TimestampedData,
+        shared: &LslShared,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let dejitter = match sample.data {
+            DataType::Eeg(_) => self.eeg_dejitter.as_mut(),
+            DataType::Ppg(_) => self.ppg_dejitter.as_mut(),
+            DataType::Accelerometer(_) | DataType::Gyro(_) => None,
+        };
+        let timestamp =
+            dejitter.map_or(sample.timestamp, |dejitter| dejitter.next(sample.timestamp));
+        let js_timestamp = timestamp + self.js_timestamp_offset;
+        // Only a chunk's last sample is stamped with its receipt time
+        let receipt_time =
+            (self.measure_latency && sample.last_in_chunk).then_some(sample.timestamp);
+        let lsl_paused = shared.paused_sinks.lsl.load(Ordering::Relaxed);
+        let js_paused = shared.paused_sinks.js.load(Ordering::Relaxed);
+        let udp_paused = shared.paused_sinks.udp.load(Ordering::Relaxed);
+        match sample.data {
+            DataType::Eeg(samples) => {
+                // AUX is always last, so dropping it is a truncation
+                let mut samples = samples[..self.eeg_channel_count].to_vec();
+                if let Some(summary) = shared.diagnostics.lock().unwrap().as_mut() {
+                    summary.record(&samples);
+                }
+                self.record_quality(&samples, shared);
+                if !lsl_paused {
+                    self.push_preview(&samples, timestamp)?;
+                }
+                self.push_epoch_quality(&samples, timestamp, shared, lsl_paused, js_paused)?;
+                // The JS chain works on its own copy of the raw sample
+                let mut js_samples = self.js_dsp.as_ref().map(|chain| {
+                    let mut js_samples = samples.clone();
+                    chain.eeg(&mut js_samples);
+                    js_samples
+                });
+                let clamped = self.dsp.eeg(&mut samples);
+                if clamped > 0 {
+                    shared.stats.lock().unwrap().clamped_eeg_values += clamped;
+                }
+                if self.include_sample_index {
+                    let index = sample_index_value(sample.index);
+                    samples.push(index);
+                    if let Some(js_samples) = js_samples.as_mut() {
+                        js_samples.push(index);
+                    }
+                }
+                if lsl_paused {
+                    // Resuming starts on a fresh chunk
+                    self.eeg_pending.clear();
+                } else {
+                    record_latency(receipt_time, shared);
+                    self.push_to_outlet(Stream::Eeg, &samples, timestamp, sample.last_in_chunk)?;
+                }
+                if let Some(udp) = self.udp.as_mut().filter(|_| !udp_paused) {
+                    udp.send(UDP_KIND_EEG, timestamp, &samples);
+                }
+                self.last_eeg_timestamp = Some(timestamp);
+                {
+                    let mut stats = shared.stats.lock().unwrap();
+                    stats.eeg_samples += 1;
+                    if let Some(dejitter) = &self.eeg_dejitter {
+                        stats.dejittered_eeg_rate = dejitter.rate();
+                    }
+                }
+                if !js_paused {
+                    let samples = js_samples.unwrap_or(samples);
+                    let sample = Sample {
+                        kind: "eeg".to_string(),
+                        timestamp: js_timestamp,
+                        channels: channels_f64(&samples),
+                    };
+                    *shared.latest_eeg.lock().unwrap() = Some(sample.clone());
+                    shared.callbacks.sample_streams.push(&sample);
+                    shared.callbacks.sample.call(sample);
+                    if shared.callbacks.eeg_chunk.is_set() {
+                        self.js_eeg_chunk.timestamps.push(js_timestamp);
+                        self.js_eeg_chunk.samples.push(channels_f64(&samples));
+                        if self.js_eeg_chunk.samples.len() >= self.js_chunk_samples {
+                            self.flush_eeg_chunk(shared);
+                        }
+                    }
+                    shared.callbacks.eeg.call(samples);
+                }
+                self.check_eeg_rate(shared);
+            }
+            DataType::Ppg(raw) => {
+                if !js_paused {
+                    let words = raw.iter().map(|&value| raw_24_bit_word(value)).collect();
+                    shared.callbacks.ppg_raw.call(words);
+                }
+                // Both chains decimate by the same factor, so they drop the same samples
+                let mut js_samples = self.js_dsp.as_mut().and_then(|chain| chain.ppg(raw));
+                let Some(mut samples) = self.dsp.ppg(raw) else {
+                    return Ok(());
+                };
+                if self.include_sample_index {
+                    let index = sample_index_value(sample.index);
+                    samples.push(index);
+                    if let Some(js_samples) = js_samples.as_mut() {
+                        js_samples.push(index);
+                    }
+                }
+                if lsl_paused {
+                    self.ppg_pending.clear();
+                } else {
+                    record_latency(receipt_time, shared);
+                    // A chunk's last sample is only kept every few chunks once
+                    // decimated, so waiting for it would hold samples back
+                    let end_of_chunk = sample.last_in_chunk || self.ppg_decimated;
+                    self.push_to_outlet(Stream::Ppg, &samples, timestamp, end_of_chunk)?;
+                }
+                if let Some(udp) = self.udp.as_mut().filter(|_| !udp_paused) {
+                    udp.send(UDP_KIND_PPG, timestamp, &samples);
+                }
+                self.last_ppg_timestamp = Some(timestamp);
+                shared.stats.lock().unwrap().ppg_samples += 1;
+                if !js_paused {
+                    let samples = js_samples.unwrap_or(samples);
+                    let sample = Sample {
+                        kind: "ppg".to_string(),
+                        timestamp: js_timestamp,
+                        channels: channels_f64(&samples),
+                    };
+                    *shared.latest_ppg.lock().unwrap() = Some(sample.clone());
+                    shared.callbacks.sample_streams.push(&sample);
+                    shared.callbacks.sample.call(sample);
+                    shared.callbacks.ppg.call(samples);
+                }
+            }
+            DataType::Accelerometer(axes) | DataType::Gyro(axes) => {
+                let accelerometer = matches!(sample.data, DataType::Accelerometer(_));
+                if let Some((accelerometer_outlet, gyroscope_outlet)) = &self.imu
+                    && !lsl_paused
+                {
+                    let outlet = if accelerometer {
+                        accelerometer_outlet
+                    } else {
+                        gyroscope_outlet
+                    };
+                    outlet.push_sample_ex(&axes.to_vec(), timestamp, true)?;
+                }
+                if !js_paused {
+                    let (kind, latest) = if accelerometer {
+                        ("accel", &shared.latest_accelerometer)
+                    } else {
+                        ("gyro", &shared.latest_gyro)
+                    };
+                    let sample = Sample {
+                        kind: kind.to_string(),
+                        timestamp: js_timestamp,
+                        channels: channels_f64(&axes),
+                    };
+                    *latest.lock().unwrap() = Some(sample.clone());
+                    shared.callbacks.sample_streams.push(&sample);
+                    shared.callbacks.sample.call(sample);
+                }
+                if let Some((outlet, filter)) = &mut self.orientation {
+                    if accelerometer {
+                        filter.accelerometer(axes);
+                    } else {
+                        let q = filter.gyroscope(axes);
+                        if !lsl_paused {
+                            outlet.push_sample_ex(&q.to_vec(), timestamp, true)?;
+                        }
+                        if !js_paused {
+                            *shared.latest_orientation.lock().unwrap() = Some(channels_f64(&q));
+                        }
+                    }
+                }
+                // Not a sign of the EEG or PPG still flowing
+                return Ok(());
+            }
+        }
 
-        // Add PPG channel information
-        let mut ppg_channels = ppg_info.desc().append_child("channels");
-        let ppg_channel_names = ["PPG_AMBIENT", "PPG_INFRARED", "PPG_RED"];
+        *shared.last_sample_at.lock().unwrap() = Some(Instant::now());
 
-        for channel_name in &ppg_channel_names {
-            ppg_channels
-                .append_child("channel")
-                .append_child_value("label", channel_name)
-                .append_child_value("unit", "N/A")
-                .append_child_value("type", "PPG");
+        if let (Some(eeg), Some(ppg)) = (self.last_eeg_timestamp, self.last_ppg_timestamp) {
+            shared.stats.lock().unwrap().eeg_ppg_offset_ms = Some((eeg - ppg) * 1000.0);
         }
+        Ok(())
+    }
 
-        // Add acquisition system metadata
-        ppg_info
-            .desc()
-            .append_child("acquisition")
-            .append_child_value("manufacturer", "Interaxon")
-            .append_child_value("model", "Muse S Gen 2");
+    /// Pushes one sample, or in chunked mode holds it until the end of its BLE
+    /// chunk and pushes the chunk stamped with the last sample's time, letting
+    /// liblsl deduce the earlier timestamps from the nominal rate
+    fn push_to_outlet(
+        &mut self,
+        stream: Stream,
+        samples: &[f32],
+        timestamp: f64,
+        last_in_chunk: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (outlet, pending, int16) = match stream {
+            Stream::Eeg => (&self.eeg_outlet, &mut self.eeg_pending, self.eeg_int16),
+            Stream::Ppg => (&self.ppg_outlet, &mut self.ppg_pending, false),
+        };
+        if !self.chunked_push {
+            if int16 {
+                outlet.push_sample_ex(&eeg_to_int16(samples), timestamp, true)?;
+            } else {
+                outlet.push_sample_ex(&samples.to_vec(), timestamp, true)?;
+            }
+            return Ok(());
+        }
 
-        Ok(StreamOutlet::new(&ppg_info, 6, 360)?)
+        pending.push(samples.to_vec());
+        if last_in_chunk {
+            let chunk = std::mem::take(pending);
+            if int16 {
+                let chunk: Vec<_> = chunk.iter().map(|sample| eeg_to_int16(sample)).collect();
+                outlet.push_chunk_ex(&chunk, timestamp, true)?;
+            } else {
+                outlet.push_chunk_ex(&chunk, timestamp, true)?;
+            }
+        }
+        Ok(())
     }
 
-    pub fn push_sample(&self, data_type: DataType) -> Result<(), Box<dyn std::error::Error>> {
-        match data_type {
-            DataType::Eeg(samples) => {
-                self.eeg_outlet.push_sample(&samples.to_vec())?;
+    /// Feeds a raw EEG sample to the preview decimator, pushing the ones it keeps
+    fn push_preview(
+        &mut self,
+        samples: &[f32],
+        timestamp: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some((outlet, decimator)) = self.preview.as_mut() else {
+            return Ok(());
+        };
+        let mut preview = samples.to_vec();
+        if decimator.push(&mut preview) {
+            outlet.push_sample_ex(&preview, timestamp, true)?;
+        }
+        Ok(())
+    }
+
+    /// Feeds a raw EEG sample to the artifact detector, reporting each epoch
+    /// it completes to the outlet and `on_epoch_quality`
+    fn push_epoch_quality(
+        &mut self,
+        samples: &[f32],
+        timestamp: f64,
+        shared: &LslShared,
+        lsl_paused: bool,
+        js_paused: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some((outlet, detector)) = self.epochs.as_mut() else {
+            return Ok(());
+        };
+        let Some((start, artifacts)) = detector.push(samples, timestamp) else {
+            return Ok(());
+        };
+        let clean = artifacts.is_empty();
+        if !lsl_paused {
+            outlet.push_sample_ex(&vec![if clean { 1.0f32 } else { 0.0 }], start, true)?;
+        }
+        if !js_paused {
+            shared.callbacks.epoch_quality.call(EpochQuality {
+                timestamp: start + self.js_timestamp_offset,
+                clean,
+                artifact_channels: artifacts
+                    .into_iter()
+                    .map(|channel| EEG_CHANNEL_LABELS[channel].to_string())
+                    .collect(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Hands the aggregated EEG samples to `on_eeg_chunk`, if there are any
+    fn flush_eeg_chunk(&mut self, shared: &LslShared) {
+        if !self.js_eeg_chunk.samples.is_empty() {
+            shared
+                .callbacks
+                .eeg_chunk
+                .call(std::mem::take(&mut self.js_eeg_chunk));
+        }
+    }
+
+    fn push_or_log(&mut self, sample: TimestampedData, shared: &LslShared) {
+        if let Err(e) = self.push_sample(sample, shared) {
+            eprintln!("Failed to push LSL sample: {}", e);
+        }
+    }
+
+    /// Releases samples `delay` seconds after their timestamps so short BLE
+    /// stalls are smoothed over, flushing whatever is left once the channel closes
+    fn process_buffered(
+        &mut self,
+        data_rx: &mpsc::Receiver<TimestampedData>,
+        delay: f64,
+        shared: &LslShared,
+    ) {
+        // Twice the samples the delay covers at the nominal rates, in case
+        // the timestamps run ahead of the local clock
+        let capacity = (delay * (EEG_NOMINAL_RATE + PPG_NOMINAL_RATE) * 2.0).ceil() as usize;
+        let mut buffer = JitterBuffer::new(delay, capacity);
+
+        loop {
+            let now = lsl::local_clock();
+            while let Some(sample) = buffer.pop_due(now) {
+                self.push_or_log(sample, shared);
             }
-            DataType::Ppg(samples) => {
-                self.ppg_outlet.push_sample(&samples.to_vec())?;
+
+            let wait = buffer
+                .next_due_in(lsl::local_clock())
+                .map_or(IDLE_WAIT, Duration::from_secs_f64);
+            match data_rx.recv_timeout(wait) {
+                Ok(sample) => buffer.push(sample.timestamp, sample),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
             }
+            self.check_consumers(shared);
+            self.push_pending_markers(shared);
+        }
+
+        for sample in buffer.drain() {
+            self.push_or_log(sample, shared);
+        }
+    }
+
+    /// Adds a raw EEG sample to the quality window, publishing the window
+    /// once it holds a second of samples
+    fn record_quality(&mut self, samples: &[f32], shared: &LslShared) {
+        self.quality.record(samples);
+        if self.quality.samples() >= QUALITY_WINDOW_SAMPLES {
+            let next = SignalSummary::new(samples.len(), EEG_RAILS);
+            *shared.signal_quality.lock().unwrap() =
+                Some(std::mem::replace(&mut self.quality, next));
+        }
+    }
+
+    /// Tells JS when the EEG or PPG outlet gets its first consumer or loses
+    /// its last, polling at most every `CONSUMER_POLL_INTERVAL`
+    fn check_consumers(&mut self, shared: &LslShared) {
+        if self.last_consumer_poll.elapsed() < CONSUMER_POLL_INTERVAL {
+            return;
+        }
+        self.last_consumer_poll = Instant::now();
+
+        let present = self.eeg_outlet.have_consumers() || self.ppg_outlet.have_consumers();
+        match self.consumers.update(present) {
+            Some(true) => shared.callbacks.consumer_connected.call(()),
+            Some(false) => shared.callbacks.consumer_disconnected.call(()),
+            None => {}
+        }
+    }
+
+    /// Pushes a string marker stamped now, if the markers outlet exists
+    fn push_marker(&self, marker: &str) {
+        self.push_marker_at(marker, lsl::local_clock());
+    }
+
+    fn push_marker_at(&self, marker: &str, timestamp: f64) {
+        let Some(outlet) = &self.markers_outlet else {
+            return;
+        };
+        if let Err(e) = outlet.push_sample_ex(&vec![marker], timestamp, true) {
+            eprintln!("Failed to push {} marker: {}", marker, e);
+        }
+    }
+
+    /// Pushes the markers queued from JS, dropping them without a markers outlet
+    fn push_pending_markers(&self, shared: &LslShared) {
+        let pending = std::mem::take(&mut *shared.pending_markers.lock().unwrap());
+        for (marker, timestamp) in pending {
+            self.push_marker_at(&marker, timestamp);
+        }
+    }
+
+    /// Warns once per monitoring window when the EEG rate drifts from nominal,
+    /// e.g. because of Bluetooth congestion
+    fn check_eeg_rate(&mut self, shared: &LslShared) {
+        let Some(measured_rate) = self.eeg_rate.tick() else {
+            return;
+        };
+        let deviation_pct = (measured_rate - EEG_NOMINAL_RATE).abs() / EEG_NOMINAL_RATE * 100.0;
+
+        let mut stats = shared.stats.lock().unwrap();
+        stats.measured_eeg_rate = measured_rate;
+        // Windows spanning a rest between bursts would always look slow
+        if deviation_pct > self.rate_warning_pct && !self.bursts {
+            stats.rate_warnings += 1;
+            shared.callbacks.rate_warning.call(RateWarning {
+                measured_rate,
+                nominal_rate: EEG_NOMINAL_RATE,
+                deviation_pct,
+            });
         }
-        Ok(())
     }
 
-    pub fn process_data_stream_simple(data_rx: mpsc::Receiver<DataType>) {
-        // Create the LSL manager
-        let lsl_manager = match Self::new() {
-            Ok(manager) => manager,
+    pub fn process_data_stream_simple(
+        data_rx: mpsc::Receiver<TimestampedData>,
+        mut options: LslOptions,
+        shared: Arc<LslShared>,
+        ready: oneshot::Sender<Result<(), String>>,
+    ) {
+        options.check_source_ids(&shared);
+        *shared.source_id_suffix.lock().unwrap() = options.source_id_suffix.clone();
+
+        // Create the LSL manager and report the outcome to start_streaming
+        let mut lsl_manager = match Self::new_with_retry(&options) {
+            Ok(manager) => {
+                let _ = ready.send(Ok(()));
+                manager
+            }
             Err(e) => {
                 eprintln!("Failed to create LSL manager: {}", e);
+                let _ = ready.send(Err(e.to_string()));
                 return;
             }
         };
+        *shared.outlet_uids.lock().unwrap() = lsl_manager.outlet_uids();
+        *shared.stats.lock().unwrap() = StreamStats::default();
+        *shared.last_sample_at.lock().unwrap() = None;
+        *shared.signal_quality.lock().unwrap() = None;
+        *shared.latest_eeg.lock().unwrap() = None;
+        *shared.latest_ppg.lock().unwrap() = None;
+        *shared.latest_accelerometer.lock().unwrap() = None;
+        *shared.latest_gyro.lock().unwrap() = None;
+        *shared.latest_orientation.lock().unwrap() = None;
+        shared.pending_markers.lock().unwrap().clear();
+        *shared.latency.lock().unwrap() = options
+            .measure_latency
+            .then(|| LatencyLog::new(LATENCY_LOG_CAPACITY));
+        lsl_manager.push_marker(STREAM_START_MARKER);
 
-        // Process incoming data using blocking recv
-        while let Ok(data_type) = data_rx.recv() {
-            if let Err(e) = lsl_manager.push_sample(data_type) {
-                eprintln!("Failed to push LSL sample: {}", e);
+        if options.jitter_buffer_secs > 0.0 {
+            lsl_manager.process_buffered(&data_rx, options.jitter_buffer_secs, &shared);
+        } else {
+            // Wake up without samples too, so consumer changes are still noticed
+            loop {
+                match data_rx.recv_timeout(IDLE_WAIT) {
+                    Ok(sample) => lsl_manager.push_or_log(sample, &shared),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+                lsl_manager.check_consumers(&shared);
+                lsl_manager.push_pending_markers(&shared);
             }
         }
 
-        // Explicit cleanup happens automatically when lsl_manager is dropped
+        // The stream is over, so the tail doesn't wait for a full chunk
+        lsl_manager.flush_eeg_chunk(&shared);
+        shared.callbacks.sample_streams.end_all();
+
+        // Before the outlets go away, so consumers actually receive it
+        lsl_manager.push_pending_markers(&shared);
+        lsl_manager.push_marker(STREAM_STOP_MARKER);
+
+        // The outlets are closing, which drops any consumers
+        if lsl_manager.consumers.value() {
+            shared.callbacks.consumer_disconnected.call(());
+        }
+
+        // Destroy the outlets before clearing the uids, so `lsl_active` only
+        // turns false once they're really gone
+        drop(lsl_manager);
+        *shared.outlet_uids.lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eeg_int16_round_trips_within_half_a_count() {
+        let samples = [0.0, 0.01, 127.5, 254.98, -3.3];
+        for (&value, count) in samples.iter().zip(eeg_to_int16(&samples)) {
+            let restored = count as f64 * EEG_INT16_SCALE;
+            assert!(
+                (restored - value as f64).abs() <= EEG_INT16_SCALE / 2.0,
+                "{} came back as {}",
+                value,
+                restored
+            );
+        }
+    }
+
+    #[test]
+    fn eeg_int16_saturates_and_zeroes_nan() {
+        assert_eq!(
+            eeg_to_int16(&[2000.0, -2000.0, f32::NAN, f32::INFINITY]),
+            vec![i16::MAX, i16::MIN, 0, i16::MAX]
+        );
+    }
+
+    fn labels(description: &StreamDescription) -> Vec<&str> {
+        description
+            .channels
+            .iter()
+            .map(|channel| channel.label.as_str())
+            .collect()
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn eeg_channels_carry_their_electrode_locations() {
+        let options = LslOptions {
+            include_sample_index: true,
+            preview_outlet: true,
+            ..LslOptions::default()
+        };
+        let locations = |description: StreamDescription| -> Vec<Option<String>> {
+            description
+                .channels
+                .into_iter()
+                .map(|channel| channel.location)
+                .collect()
+        };
+        let electrodes = ["TP9", "AF7", "AF8", "TP10"].map(|location| Some(location.to_string()));
+        // AUX and the sample index have no position on the head
+        assert_eq!(
+            locations(options.eeg_description()),
+            [&electrodes[..], &[None, None]].concat()
+        );
+        assert_eq!(
+            locations(options.preview_description().unwrap()),
+            [&electrodes[..], &[None]].concat()
+        );
+        assert!(
+            locations(options.ppg_description())
+                .iter()
+                .all(Option::is_none)
+        );
+    }
+
+    #[test]
+    fn four_channel_eeg_leaves_out_aux() {
+        let options = LslOptions {
+            include_aux: false,
+            preview_outlet: true,
+            ..LslOptions::default()
+        };
+        let four_channels = ["EEG_TP9", "EEG_AF7", "EEG_AF8", "EEG_TP10"];
+        assert_eq!(labels(&options.eeg_description()), four_channels);
+        assert_eq!(
+            labels(&options.preview_description().unwrap()),
+            four_channels
+        );
+
+        let with_aux = LslOptions::default().eeg_description();
+        assert_eq!(with_aux.channels.len(), 5);
+        assert_eq!(with_aux.channels[4].label, "EEG_AUX");
+    }
+
+    #[test]
+    fn every_timestamp_source_is_monotonic() {
+        // liblsl's clock through a stream. The offset is only taken at the
+        // start, so the wall clock being stepped back later can't show up
+        let lsl_times: Vec<f64> = (0..1000).map(|i| 5_000.0 + i as f64 / 256.0).collect();
+        let unix_at_start = 1_700_000_000.0;
+        for source in [
+            TimestampSource::Lsl,
+            TimestampSource::Unix,
+            TimestampSource::Monotonic,
+        ] {
+            assert_eq!(TimestampSource::parse(source.as_str()), Some(source));
+            let offset = source.offset_at(lsl_times[0], unix_at_start);
+            let converted: Vec<f64> = lsl_times.iter().map(|&time| time + offset).collect();
+            assert!(
+                converted.windows(2).all(|pair| pair[1] > pair[0]),
+                "{} went backwards",
+                source.as_str()
+            );
+            let start = match source {
+                TimestampSource::Lsl => 5_000.0,
+                TimestampSource::Unix => unix_at_start,
+                TimestampSource::Monotonic => 0.0,
+            };
+            assert_eq!(converted[0], start);
+        }
+        assert_eq!(TimestampSource::parse("utc"), None);
+    }
+
+    #[test]
+    fn outlets_advertise_their_rates() {
+        let options = LslOptions {
+            bracket_markers: true,
+            preview_outlet: true,
+            epoch_quality: Some(EpochThresholds {
+                epoch_samples: 512,
+                max_range: 150.0,
+                max_step: 50.0,
+            }),
+            imu: true,
+            orientation_gain: Some(0.1),
+            ..LslOptions::default()
+        };
+        let rates: Vec<(String, f64)> = options
+            .stream_descriptions()
+            .into_iter()
+            .map(|description| (description.stream_type, description.nominal_rate))
+            .collect();
+        let expected = [
+            ("EEG", 256.0),
+            ("PPG", 64.0),
+            ("Markers", lsl::IRREGULAR_RATE),
+            ("EEGPreview", 32.0),
+            ("EpochQuality", 0.5),
+            ("Accelerometer", 52.0),
+            ("Gyroscope", 52.0),
+            ("Orientation", 52.0),
+        ];
+        assert_eq!(
+            rates,
+            expected.map(|(stream_type, rate)| (stream_type.to_string(), rate))
+        );
+
+        let decimated = LslOptions {
+            dsp: DspOptions {
+                ppg_decimation: 4,
+                ..DspOptions::default()
+            },
+            ..LslOptions::default()
+        };
+        assert_eq!(decimated.ppg_description().nominal_rate, 16.0);
+    }
+}
@@ -0,0 +1,1050 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Per-channel moving average over the last `window` samples.
+///
+/// The output rate matches the input rate; each call produces one smoothed
+/// sample for each raw sample pushed in.
+pub struct MovingAverage {
+  window: usize,
+  history: Vec<VecDeque<f32>>,
+}
+
+impl MovingAverage {
+  pub fn new(channel_count: usize, window: usize) -> Self {
+    let window = window.max(1);
+    Self {
+      window,
+      history: (0..channel_count)
+        .map(|_| VecDeque::with_capacity(window))
+        .collect(),
+    }
+  }
+
+  /// Smooths `sample` in place, keeping the filter state for each channel
+  pub fn apply(&mut self, sample: &mut [f32]) {
+    for (value, history) in sample.iter_mut().zip(self.history.iter_mut()) {
+      if history.len() == self.window {
+        history.pop_front();
+      }
+      history.push_back(*value);
+
+      // Sum in f64 - raw PPG values are in the 1e5..1e6 range
+      let sum: f64 = history.iter().map(|&v| v as f64).sum();
+      *value = (sum / history.len() as f64) as f32;
+    }
+  }
+}
+
+/// A second-order low-pass section (RBJ cookbook), in transposed direct form II
+#[derive(Clone)]
+struct Biquad {
+  b0: f64,
+  b1: f64,
+  b2: f64,
+  a1: f64,
+  a2: f64,
+  z1: f64,
+  z2: f64,
+}
+
+impl Biquad {
+  fn low_pass(cutoff: f64, rate: f64, q: f64) -> Self {
+    let w0 = 2.0 * std::f64::consts::PI * cutoff / rate;
+    let alpha = w0.sin() / (2.0 * q);
+    let cos = w0.cos();
+    let a0 = 1.0 + alpha;
+    Self {
+      b0: (1.0 - cos) / 2.0 / a0,
+      b1: (1.0 - cos) / a0,
+      b2: (1.0 - cos) / 2.0 / a0,
+      a1: -2.0 * cos / a0,
+      a2: (1.0 - alpha) / a0,
+      z1: 0.0,
+      z2: 0.0,
+    }
+  }
+
+  /// Sets the state as if `value` had always been the input, so a stream
+  /// starting far from 0 (raw PPG is around 1e5) doesn't ring
+  fn settle(&mut self, value: f64) {
+    self.z1 = value * (1.0 - self.b0);
+    self.z2 = value * (self.b2 - self.a2);
+  }
+
+  fn process(&mut self, value: f64) -> f64 {
+    let out = self.b0 * value + self.z1;
+    self.z1 = self.b1 * value - self.a1 * out + self.z2;
+    self.z2 = self.b2 * value - self.a2 * out;
+    out
+  }
+}
+
+// Section Qs of a 4th-order Butterworth low-pass
+const BUTTERWORTH_4_Q: [f64; 2] = [0.5412, 1.3066];
+
+/// Reduces a stream's rate by an integer `factor`: low-passes each channel
+/// below the new Nyquist frequency, then keeps every `factor`th sample
+pub struct Decimator {
+  factor: usize,
+  phase: usize,
+  settled: bool,
+  channels: Vec<[Biquad; 2]>,
+}
+
+impl Decimator {
+  pub fn new(channel_count: usize, factor: usize, rate: f64) -> Self {
+    // 80% of the output Nyquist, leaving the filter room to roll off
+    let cutoff = 0.4 * rate / factor as f64;
+    let sections = BUTTERWORTH_4_Q.map(|q| Biquad::low_pass(cutoff, rate, q));
+    Self {
+      factor,
+      phase: 0,
+      settled: false,
+      channels: vec![sections; channel_count],
+    }
+  }
+
+  /// Filters `sample` in place, returning whether it's one of the samples kept
+  pub fn push(&mut self, sample: &mut [f32]) -> bool {
+    for (value, sections) in sample.iter_mut().zip(self.channels.iter_mut()) {
+      let mut filtered = *value as f64;
+      for section in sections.iter_mut() {
+        if !self.settled {
+          section.settle(filtered);
+        }
+        filtered = section.process(filtered);
+      }
+      *value = filtered as f32;
+    }
+    self.settled = true;
+
+    self.phase = (self.phase + 1) % self.factor;
+    self.phase == 0
+  }
+}
+
+/// Limits each value to `±limit`, or replaces it with NaN when `mark_nan`
+/// is set, returning how many values were out of range
+pub fn clamp_sample(sample: &mut [f32], limit: f32, mark_nan: bool) -> u32 {
+  let mut clamped = 0;
+  for value in sample.iter_mut().filter(|value| value.abs() > limit) {
+    *value = if mark_nan {
+      f32::NAN
+    } else {
+      value.clamp(-limit, limit)
+    };
+    clamped += 1;
+  }
+  clamped
+}
+
+/// Subtracts the ambient reading (channel 0) from the infrared and red
+/// channels of a PPG sample, leaving the ambient channel as is
+pub fn subtract_ambient(sample: &mut [f32]) {
+  if let Some((&mut ambient, lit)) = sample.split_first_mut() {
+    for value in lit {
+      *value -= ambient;
+    }
+  }
+}
+
+// Rate the PPG arrives at, before any decimation
+const PPG_INPUT_RATE: f64 = 64.0;
+
+/// Filter settings for one sink's chain. The default passes samples through
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DspOptions {
+  /// Moving-average window (in samples) for the PPG channels, 0 or 1 disables smoothing
+  pub ppg_smoothing_window: usize,
+  /// Subtract the ambient channel from the infrared and red channels
+  pub ppg_subtract_ambient: bool,
+  /// Leave the ambient channel out of the PPG output, only with `ppg_subtract_ambient`
+  pub ppg_drop_ambient: bool,
+  /// Largest absolute EEG value passed on (raw counts), `None` disables clamping
+  pub eeg_clamp: Option<f32>,
+  /// Replace out-of-range EEG values with NaN instead of the limit
+  pub eeg_clamp_nan: bool,
+  /// EEG channels (by index) that pass through the chain untouched
+  pub eeg_exclude: Vec<usize>,
+  /// Keep every nth PPG sample after anti-alias filtering, 0 or 1 keeps them all
+  pub ppg_decimation: usize,
+}
+
+/// One sink's filters, keeping their state separate from other sinks' chains
+pub struct DspChain {
+  options: DspOptions,
+  // Runs first, so the smoothing window counts output samples
+  ppg_decimator: Option<Decimator>,
+  // Smoothing doesn't change the rate, so the PPG outlet still advertises 64 Hz
+  ppg_smoother: Option<MovingAverage>,
+}
+
+impl DspChain {
+  pub fn new(options: &DspOptions) -> Self {
+    Self {
+      options: options.clone(),
+      ppg_decimator: (options.ppg_decimation > 1)
+        .then(|| Decimator::new(3, options.ppg_decimation, PPG_INPUT_RATE)),
+      ppg_smoother: (options.ppg_smoothing_window > 1)
+        .then(|| MovingAverage::new(3, options.ppg_smoothing_window)),
+    }
+  }
+
+  /// Clamps an EEG sample in place, returning how many values were out of
+  /// range. Channels in `eeg_exclude` are left as they are
+  pub fn eeg(&self, sample: &mut [f32]) -> u32 {
+    match self.options.eeg_clamp {
+      Some(limit) => sample
+        .iter_mut()
+        .enumerate()
+        .filter(|(channel, _)| !self.options.eeg_exclude.contains(channel))
+        .map(|(_, value)| {
+          clamp_sample(
+            std::slice::from_mut(value),
+            limit,
+            self.options.eeg_clamp_nan,
+          )
+        })
+        .sum(),
+      None => 0,
+    }
+  }
+
+  /// The processed PPG channels of a raw AMBIENT, INFRARED, RED sample, or
+  /// None when decimation drops it
+  pub fn ppg(&mut self, mut sample: [f32; 3]) -> Option<Vec<f32>> {
+    if let Some(decimator) = self.ppg_decimator.as_mut()
+      && !decimator.push(&mut sample)
+    {
+      return None;
+    }
+    if let Some(smoother) = self.ppg_smoother.as_mut() {
+      smoother.apply(&mut sample);
+    }
+    if !self.options.ppg_subtract_ambient {
+      return Some(sample.to_vec());
+    }
+    subtract_ambient(&mut sample);
+    let first_channel = if self.options.ppg_drop_ambient { 1 } else { 0 };
+    Some(sample[first_channel..].to_vec())
+  }
+}
+
+/// Measures a stream's sample rate over fixed wall-clock windows.
+///
+/// Only counts samples between windows, so it's cheap to call per sample.
+pub struct RateMonitor {
+  window: Duration,
+  window_start: Option<Instant>,
+  count: u32,
+}
+
+impl RateMonitor {
+  pub fn new(window: Duration) -> Self {
+    Self {
+      window,
+      window_start: None,
+      count: 0,
+    }
+  }
+
+  /// Counts one sample, returning the measured rate once each window completes
+  pub fn tick(&mut self) -> Option<f64> {
+    // The first window starts with the first sample, not with the stream setup
+    let window_start = *self.window_start.get_or_insert_with(Instant::now);
+    self.count += 1;
+
+    let elapsed = window_start.elapsed();
+    if elapsed < self.window {
+      return None;
+    }
+
+    let rate = self.count as f64 / elapsed.as_secs_f64();
+    self.window_start = Some(Instant::now());
+    self.count = 0;
+    Some(rate)
+  }
+}
+
+/// Counts packets missing from a channel's 16-bit sequence counter
+#[derive(Default)]
+pub struct SequenceTracker {
+  last: Option<u16>,
+}
+
+// Bigger jumps are treated as a counter reset (e.g. the device restarted
+// streaming) rather than as lost packets
+const MAX_SEQUENCE_GAP: u16 = 1000;
+
+impl SequenceTracker {
+  /// Whether `sequence` is the packet that was just seen, i.e. a retransmission
+  pub fn is_repeat(&self, sequence: u16) -> bool {
+    self.last == Some(sequence)
+  }
+
+  /// Records `sequence`, returning how many packets were skipped before it
+  pub fn observe(&mut self, sequence: u16) -> u32 {
+    let missed = match self.last {
+      Some(last) => {
+        let gap = sequence.wrapping_sub(last).wrapping_sub(1);
+        if gap < MAX_SEQUENCE_GAP {
+          gap as u32
+        } else {
+          0
+        }
+      }
+      None => 0,
+    };
+    self.last = Some(sequence);
+    missed
+  }
+}
+
+// Share of samples at the rails above which a channel counts as saturated
+const SATURATED_FRACTION: f64 = 0.05;
+// RMS noise (in raw decoder units) below which a channel counts as flat,
+// i.e. nothing is reaching the electrode
+const FLAT_RMS: f64 = 0.5;
+
+/// Running mean, spread and rail hits of one channel
+#[derive(Debug, Clone, Default)]
+pub struct ChannelSummary {
+  count: u32,
+  sum: f64,
+  sum_squares: f64,
+  pub saturated: u32,
+}
+
+impl ChannelSummary {
+  pub fn mean(&self) -> f64 {
+    if self.count == 0 {
+      0.0
+    } else {
+      self.sum / self.count as f64
+    }
+  }
+
+  /// RMS of the values around their mean, i.e. the noise once the DC offset is removed
+  pub fn rms_noise(&self) -> f64 {
+    if self.count == 0 {
+      return 0.0;
+    }
+    let mean = self.mean();
+    (self.sum_squares / self.count as f64 - mean * mean)
+      .max(0.0)
+      .sqrt()
+  }
+
+  /// "saturated" when more than 5% of the values hit the rails, "flat" when
+  /// the RMS noise is under 0.5, otherwise "ok"
+  pub fn quality(&self) -> &'static str {
+    if self.count > 0 && self.saturated as f64 / self.count as f64 > SATURATED_FRACTION {
+      "saturated"
+    } else if self.rms_noise() < FLAT_RMS {
+      "flat"
+    } else {
+      "ok"
+    }
+  }
+}
+
+/// Per-channel summaries of a stretch of samples, for signal-quality checks
+pub struct SignalSummary {
+  rails: (f32, f32),
+  pub channels: Vec<ChannelSummary>,
+}
+
+impl SignalSummary {
+  /// Values at or beyond either of `rails` count as saturated
+  pub fn new(channel_count: usize, rails: (f32, f32)) -> Self {
+    Self {
+      rails,
+      channels: vec![ChannelSummary::default(); channel_count],
+    }
+  }
+
+  pub fn record(&mut self, sample: &[f32]) {
+    for (&value, channel) in sample.iter().zip(self.channels.iter_mut()) {
+      channel.count += 1;
+      channel.sum += value as f64;
+      channel.sum_squares += value as f64 * value as f64;
+      if value <= self.rails.0 || value >= self.rails.1 {
+        channel.saturated += 1;
+      }
+    }
+  }
+
+  pub fn samples(&self) -> u32 {
+    self.channels.first().map_or(0, |channel| channel.count)
+  }
+}
+
+/// Limits a clean epoch must stay within on every channel
+#[derive(Debug, Clone, Copy)]
+pub struct EpochThresholds {
+  pub epoch_samples: usize,
+  /// Largest peak-to-peak range
+  pub max_range: f32,
+  /// Largest change between consecutive samples
+  pub max_step: f32,
+}
+
+/// Splits a stream into fixed-length epochs and flags the channels whose
+/// range or steepest step within an epoch break `EpochThresholds`, the
+/// usual amplitude and gradient criteria for rejecting blinks, movement
+/// and electrode pops
+pub struct EpochArtifacts {
+  thresholds: EpochThresholds,
+  count: usize,
+  start: f64,
+  min: Vec<f32>,
+  max: Vec<f32>,
+  max_step: Vec<f32>,
+  // Carried over, so a step across an epoch boundary counts in the new epoch
+  last: Option<Vec<f32>>,
+}
+
+impl EpochArtifacts {
+  pub fn new(channel_count: usize, thresholds: EpochThresholds) -> Self {
+    Self {
+      thresholds,
+      count: 0,
+      start: 0.0,
+      min: vec![f32::INFINITY; channel_count],
+      max: vec![f32::NEG_INFINITY; channel_count],
+      max_step: vec![0.0; channel_count],
+      last: None,
+    }
+  }
+
+  /// Adds a sample, returning the epoch's start timestamp and the indices of
+  /// its artifact channels (empty when clean) once the epoch is complete
+  pub fn push(&mut self, sample: &[f32], timestamp: f64) -> Option<(f64, Vec<usize>)> {
+    if self.count == 0 {
+      self.start = timestamp;
+    }
+    for (channel, &value) in sample.iter().enumerate().take(self.min.len()) {
+      self.min[channel] = self.min[channel].min(value);
+      self.max[channel] = self.max[channel].max(value);
+      if let Some(last) = &self.last {
+        self.max_step[channel] = self.max_step[channel].max((value - last[channel]).abs());
+      }
+    }
+    self.last = Some(sample.to_vec());
+    self.count += 1;
+    if self.count < self.thresholds.epoch_samples {
+      return None;
+    }
+
+    let artifacts = (0..self.min.len())
+      .filter(|&channel| {
+        self.max[channel] - self.min[channel] > self.thresholds.max_range
+          || self.max_step[channel] > self.thresholds.max_step
+      })
+      .collect();
+    self.count = 0;
+    self.min.fill(f32::INFINITY);
+    self.max.fill(f32::NEG_INFINITY);
+    self.max_step.fill(0.0);
+    Some((self.start, artifacts))
+  }
+}
+
+/// Head orientation as a unit quaternion (w, x, y, z), fused from the
+/// accelerometer and gyroscope with Madgwick's IMU filter: the gyroscope is
+/// integrated, and each step is nudged toward the attitude where gravity
+/// points along the measured acceleration, by `gain` (rad/s). Without a
+/// magnetometer nothing corrects yaw, so heading drifts with gyro bias
+pub struct Orientation {
+  q: [f32; 4],
+  gain: f32,
+  dt: f32,
+  // Paired with each gyroscope sample, the two arrive in separate packets
+  gravity: Option<[f32; 3]>,
+}
+
+impl Orientation {
+  pub fn new(gain: f32, rate: f64) -> Self {
+    Self {
+      q: [1.0, 0.0, 0.0, 0.0],
+      gain,
+      dt: (1.0 / rate) as f32,
+      gravity: None,
+    }
+  }
+
+  /// Takes an accelerometer sample (g), used by the following gyroscope steps
+  pub fn accelerometer(&mut self, accel: [f32; 3]) {
+    self.gravity = Some(accel);
+  }
+
+  /// Advances by one gyroscope sample (deg/s), returning the new orientation
+  pub fn gyroscope(&mut self, gyro: [f32; 3]) -> [f32; 4] {
+    let [q0, q1, q2, q3] = self.q;
+    let [gx, gy, gz] = gyro.map(f32::to_radians);
+
+    // Rate of change from the gyroscope alone
+    let mut dq = [
+      0.5 * (-q1 * gx - q2 * gy - q3 * gz),
+      0.5 * (q0 * gx + q2 * gz - q3 * gy),
+      0.5 * (q0 * gy - q1 * gz + q3 * gx),
+      0.5 * (q0 * gz + q1 * gy - q2 * gx),
+    ];
+
+    // Gradient descent step toward the measured gravity, skipped in free
+    // fall where there's no direction to correct to
+    if let Some(accel) = self.gravity
+      && let Some([ax, ay, az]) = normalized(accel)
+    {
+      let step = [
+        4.0 * q0 * q2 * q2 + 2.0 * q2 * ax + 4.0 * q0 * q1 * q1 - 2.0 * q1 * ay,
+        4.0 * q1 * q3 * q3 - 2.0 * q3 * ax + 4.0 * q0 * q0 * q1 - 2.0 * q0 * ay - 4.0 * q1
+          + 8.0 * q1 * q1 * q1
+          + 8.0 * q1 * q2 * q2
+          + 4.0 * q1 * az,
+        4.0 * q0 * q0 * q2 + 2.0 * q0 * ax + 4.0 * q2 * q3 * q3 - 2.0 * q3 * ay - 4.0 * q2
+          + 8.0 * q2 * q1 * q1
+          + 8.0 * q2 * q2 * q2
+          + 4.0 * q2 * az,
+        4.0 * q1 * q1 * q3 - 2.0 * q1 * ax + 4.0 * q2 * q2 * q3 - 2.0 * q2 * ay,
+      ];
+      if let Some(step) = normalized(step) {
+        for (rate, correction) in dq.iter_mut().zip(step) {
+          *rate -= self.gain * correction;
+        }
+      }
+    }
+
+    let mut q = self.q;
+    for (value, rate) in q.iter_mut().zip(dq) {
+      *value += rate * self.dt;
+    }
+    self.q = normalized(q).unwrap_or([1.0, 0.0, 0.0, 0.0]);
+    self.q
+  }
+}
+
+fn normalized<const N: usize>(v: [f32; N]) -> Option<[f32; N]> {
+  let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+  (norm > 0.0 && norm.is_finite()).then(|| v.map(|x| x / norm))
+}
+
+/// A flag that only changes once the new value has held for `hold`, so
+/// brief flaps are ignored
+pub struct Debounced {
+  hold: Duration,
+  value: bool,
+  changing_since: Option<Instant>,
+}
+
+impl Debounced {
+  pub fn new(value: bool, hold: Duration) -> Self {
+    Self {
+      hold,
+      value,
+      changing_since: None,
+    }
+  }
+
+  pub fn value(&self) -> bool {
+    self.value
+  }
+
+  /// Feeds the latest raw value, returning the new value when a change has held
+  pub fn update(&mut self, raw: bool) -> Option<bool> {
+    if raw == self.value {
+      self.changing_since = None;
+      return None;
+    }
+    let since = *self.changing_since.get_or_insert_with(Instant::now);
+    if since.elapsed() < self.hold {
+      return None;
+    }
+    self.value = raw;
+    self.changing_since = None;
+    Some(raw)
+  }
+}
+
+/// The most recent `capacity` latencies, for percentiles over a run
+pub struct LatencyLog {
+  capacity: usize,
+  values: VecDeque<f64>,
+}
+
+impl LatencyLog {
+  pub fn new(capacity: usize) -> Self {
+    Self {
+      capacity,
+      values: VecDeque::with_capacity(capacity),
+    }
+  }
+
+  pub fn record(&mut self, latency: f64) {
+    if self.values.len() == self.capacity {
+      self.values.pop_front();
+    }
+    self.values.push_back(latency);
+  }
+
+  pub fn len(&self) -> usize {
+    self.values.len()
+  }
+
+  /// The smallest, median, 99th percentile and largest latency, None while empty
+  pub fn percentiles(&self) -> Option<[f64; 4]> {
+    let mut sorted: Vec<f64> = self.values.iter().copied().collect();
+    sorted.sort_by(f64::total_cmp);
+    let at = |fraction: f64| sorted[((sorted.len() - 1) as f64 * fraction).round() as usize];
+    (!sorted.is_empty()).then(|| [at(0.0), at(0.5), at(0.99), at(1.0)])
+  }
+}
+
+/// Measures packet loss over fixed wall-clock windows, like `RateMonitor`
+pub struct LossMonitor {
+  window: Duration,
+  window_start: Option<Instant>,
+  received: u32,
+  missed: u32,
+}
+
+impl LossMonitor {
+  pub fn new(window: Duration) -> Self {
+    Self {
+      window,
+      window_start: None,
+      received: 0,
+      missed: 0,
+    }
+  }
+
+  /// Counts one received packet and the `missed` ones before it, returning
+  /// the loss in percent once each window completes
+  pub fn record(&mut self, missed: u32) -> Option<f64> {
+    let window_start = *self.window_start.get_or_insert_with(Instant::now);
+    self.received += 1;
+    self.missed += missed;
+
+    if window_start.elapsed() < self.window {
+      return None;
+    }
+
+    let expected = self.received + self.missed;
+    let loss_pct = self.missed as f64 / expected as f64 * 100.0;
+    self.window_start = Some(Instant::now());
+    self.received = 0;
+    self.missed = 0;
+    Some(loss_pct)
+  }
+}
+
+// A sample this much later than the fit predicts means packets were lost,
+// so the fit starts over instead of bending towards the gap
+const DEJITTER_GAP_SECS: f64 = 0.25;
+
+/// Replaces bursty receipt timestamps with a line fitted to them by sample
+/// index, approximating the device's regular sampling clock.
+///
+/// The fit is a least-squares regression with exponential forgetting, so it
+/// follows slow drift between the device and host clocks. Its output never
+/// goes backwards, even when the fit is restarted after a gap.
+pub struct Dejitter {
+  nominal_interval: f64,
+  decay: f64,
+  origin: Option<f64>,
+  index: f64,
+  weight: f64,
+  sum_x: f64,
+  sum_y: f64,
+  sum_xx: f64,
+  sum_xy: f64,
+  last: f64,
+}
+
+impl Dejitter {
+  /// `half_life` is how many samples it takes for a sample's weight in the fit to halve
+  pub fn new(nominal_rate: f64, half_life: f64) -> Self {
+    Self {
+      nominal_interval: 1.0 / nominal_rate,
+      decay: 0.5f64.powf(1.0 / half_life.max(1.0)),
+      origin: None,
+      index: 0.0,
+      weight: 0.0,
+      sum_x: 0.0,
+      sum_y: 0.0,
+      sum_xx: 0.0,
+      sum_xy: 0.0,
+      last: f64::NEG_INFINITY,
+    }
+  }
+
+  /// The smoothed timestamp of the next sample, which arrived stamped `timestamp`
+  pub fn next(&mut self, timestamp: f64) -> f64 {
+    if let Some(origin) = self.origin
+      && timestamp - origin - self.fitted(self.index) > DEJITTER_GAP_SECS
+    {
+      self.restart();
+    }
+    // Relative to the first sample so the sums stay well within f64 precision
+    let origin = *self.origin.get_or_insert(timestamp);
+    let (x, y) = (self.index, timestamp - origin);
+
+    self.weight = self.weight * self.decay + 1.0;
+    self.sum_x = self.sum_x * self.decay + x;
+    self.sum_y = self.sum_y * self.decay + y;
+    self.sum_xx = self.sum_xx * self.decay + x * x;
+    self.sum_xy = self.sum_xy * self.decay + x * y;
+    self.index += 1.0;
+
+    self.last = (origin + self.fitted(x)).max(self.last);
+    self.last
+  }
+
+  /// Sample rate the fit has settled on, nominal until it has a slope
+  pub fn rate(&self) -> f64 {
+    1.0 / self.interval()
+  }
+
+  fn interval(&self) -> f64 {
+    if self.weight == 0.0 {
+      return self.nominal_interval;
+    }
+    let mean_x = self.sum_x / self.weight;
+    let variance = self.sum_xx / self.weight - mean_x * mean_x;
+    let covariance = self.sum_xy / self.weight - mean_x * self.sum_y / self.weight;
+    let slope = covariance / variance;
+    // Too few samples to fit, or a fit that runs backwards
+    if variance > 0.0 && slope > 0.0 {
+      slope
+    } else {
+      self.nominal_interval
+    }
+  }
+
+  // Fitted time of sample `x`, relative to the origin
+  fn fitted(&self, x: f64) -> f64 {
+    if self.weight == 0.0 {
+      return 0.0;
+    }
+    let mean_x = self.sum_x / self.weight;
+    let mean_y = self.sum_y / self.weight;
+    mean_y + self.interval() * (x - mean_x)
+  }
+
+  fn restart(&mut self) {
+    self.origin = None;
+    self.index = 0.0;
+    self.weight = 0.0;
+    self.sum_x = 0.0;
+    self.sum_y = 0.0;
+    self.sum_xx = 0.0;
+    self.sum_xy = 0.0;
+  }
+}
+
+/// Holds timestamped items until a fixed delay after their timestamp, so
+/// bursts are released at their original spacing.
+///
+/// Release times follow the timestamps rather than arrival, so the added
+/// latency stays at `delay` instead of building up after a stall.
+pub struct JitterBuffer<T> {
+  delay: f64,
+  capacity: usize,
+  queue: VecDeque<(f64, T)>,
+}
+
+impl<T> JitterBuffer<T> {
+  /// `delay` is in seconds; beyond `capacity` items the oldest are released early
+  pub fn new(delay: f64, capacity: usize) -> Self {
+    Self {
+      delay,
+      capacity: capacity.max(1),
+      queue: VecDeque::new(),
+    }
+  }
+
+  pub fn push(&mut self, timestamp: f64, item: T) {
+    // Almost always the back; keeps each stream in timestamp order when
+    // several streams share the buffer
+    let position = self
+      .queue
+      .partition_point(|(queued, _)| *queued <= timestamp);
+    self.queue.insert(position, (timestamp, item));
+  }
+
+  /// Takes the next item that's due at `now` (or over capacity)
+  pub fn pop_due(&mut self, now: f64) -> Option<T> {
+    let (timestamp, _) = self.queue.front()?;
+    if timestamp + self.delay <= now || self.queue.len() > self.capacity {
+      self.queue.pop_front().map(|(_, item)| item)
+    } else {
+      None
+    }
+  }
+
+  /// Seconds from `now` until the next item is due
+  pub fn next_due_in(&self, now: f64) -> Option<f64> {
+    self
+      .queue
+      .front()
+      .map(|(timestamp, _)| (timestamp + self.delay - now).max(0.0))
+  }
+
+  pub fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+    self.queue.drain(..).map(|(_, item)| item)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn moving_average_warms_up_over_the_samples_seen() {
+    let mut average = MovingAverage::new(1, 4);
+    let outputs: Vec<f32> = [4.0, 8.0, 0.0]
+      .iter()
+      .map(|&value| {
+        let mut sample = [value];
+        average.apply(&mut sample);
+        sample[0]
+      })
+      .collect();
+    assert_eq!(outputs, vec![4.0, 6.0, 4.0]);
+  }
+
+  #[test]
+  fn moving_average_slides_over_the_window_per_channel() {
+    let mut average = MovingAverage::new(2, 2);
+    let mut outputs = Vec::new();
+    for value in [1.0, 3.0, 5.0, 7.0] {
+      let mut sample = [value, -value];
+      average.apply(&mut sample);
+      outputs.push(sample);
+    }
+    assert_eq!(
+      outputs,
+      vec![[1.0, -1.0], [2.0, -2.0], [4.0, -4.0], [6.0, -6.0]]
+    );
+  }
+
+  #[test]
+  fn moving_average_window_of_one_passes_through() {
+    for window in [0, 1] {
+      let mut average = MovingAverage::new(1, window);
+      for value in [100_000.0, 250_000.0] {
+        let mut sample = [value];
+        average.apply(&mut sample);
+        assert_eq!(sample[0], value);
+      }
+    }
+  }
+
+  #[test]
+  fn moving_average_restarts_with_each_chain() {
+    // Each stream builds its own chain, so smoothing starts over after a restart
+    let options = DspOptions {
+      ppg_smoothing_window: 4,
+      ..DspOptions::default()
+    };
+    let mut chain = DspChain::new(&options);
+    chain.ppg([0.0, 0.0, 0.0]);
+    assert_eq!(chain.ppg([8.0, 8.0, 8.0]), Some(vec![4.0, 4.0, 4.0]));
+
+    let mut chain = DspChain::new(&options);
+    assert_eq!(chain.ppg([8.0, 8.0, 8.0]), Some(vec![8.0, 8.0, 8.0]));
+  }
+
+  #[test]
+  fn subtract_ambient_leaves_the_ambient_channel() {
+    let mut sample = [1_000.0, 51_000.0, 31_000.0];
+    subtract_ambient(&mut sample);
+    assert_eq!(sample, [1_000.0, 50_000.0, 30_000.0]);
+  }
+
+  #[test]
+  fn ppg_chain_can_drop_the_ambient_channel() {
+    let subtract = DspOptions {
+      ppg_subtract_ambient: true,
+      ..DspOptions::default()
+    };
+    let raw = [200.0, 700.0, 100.0];
+    assert_eq!(
+      DspChain::new(&subtract).ppg(raw),
+      Some(vec![200.0, 500.0, -100.0])
+    );
+
+    let drop = DspOptions {
+      ppg_drop_ambient: true,
+      ..subtract
+    };
+    assert_eq!(DspChain::new(&drop).ppg(raw), Some(vec![500.0, -100.0]));
+  }
+
+  #[test]
+  fn dejitter_evens_out_bursty_arrivals() {
+    let rate = 256.0;
+    let mut dejitter = Dejitter::new(rate, 30.0 * rate);
+    let jitter = [0.0, 0.012, 0.003, 0.009, 0.015, 0.001];
+    let mut outputs = Vec::new();
+    // Packets of 12 samples arrive three at a time, each burst late by up to 15 ms
+    for burst in 0..300 {
+      let arrival = (burst + 1) as f64 * 36.0 / rate + jitter[burst % jitter.len()];
+      for _ in 0..36 {
+        outputs.push(dejitter.next(arrival));
+      }
+    }
+
+    assert!(outputs.windows(2).all(|pair| pair[1] >= pair[0]));
+    // Once the fit has settled, two seconds of output step at the true rate
+    for pair in outputs[outputs.len() - 512..].windows(2) {
+      let step = pair[1] - pair[0];
+      assert!((step * rate - 1.0).abs() < 0.02, "step {}", step);
+    }
+    assert!(
+      (dejitter.rate() - rate).abs() < 1.0,
+      "rate {}",
+      dejitter.rate()
+    );
+  }
+
+  #[test]
+  fn sequence_tracker_flags_repeats_across_the_wrap() {
+    let mut tracker = SequenceTracker::default();
+    assert!(!tracker.is_repeat(0xFFFF));
+    assert_eq!(tracker.observe(0xFFFE), 0);
+    assert_eq!(tracker.observe(0xFFFF), 0);
+    assert!(tracker.is_repeat(0xFFFF));
+    assert!(!tracker.is_repeat(0x0000));
+    assert_eq!(tracker.observe(0x0000), 0);
+    assert!(tracker.is_repeat(0x0000));
+    assert!(!tracker.is_repeat(0xFFFF));
+  }
+
+  #[test]
+  fn excluded_eeg_channels_skip_the_clamp() {
+    let chain = DspChain::new(&DspOptions {
+      eeg_clamp: Some(100.0),
+      eeg_exclude: vec![1, 4],
+      ..DspOptions::default()
+    });
+    let mut sample = [150.0, 150.0, -150.0, 50.0, -150.0];
+    assert_eq!(chain.eeg(&mut sample), 2);
+    assert_eq!(sample, [100.0, 150.0, -100.0, 50.0, -150.0]);
+  }
+
+  /// Peak deviation from `offset` of a tone at `frequency` after decimating
+  /// 64 Hz by 4, past the filter's settling time
+  fn decimated_amplitude(frequency: f64, offset: f64) -> f64 {
+    let mut decimator = Decimator::new(1, 4, 64.0);
+    let kept: Vec<f64> = (0..640)
+      .filter_map(|i| {
+        let t = i as f64 / 64.0;
+        let mut sample = [(offset + (2.0 * std::f64::consts::PI * frequency * t).sin()) as f32];
+        decimator.push(&mut sample).then_some(sample[0] as f64)
+      })
+      .collect();
+    assert_eq!(kept.len(), 160);
+    kept[40..]
+      .iter()
+      .map(|value| (value - offset).abs())
+      .fold(0.0, f64::max)
+  }
+
+  #[test]
+  fn decimator_keeps_every_nth_sample() {
+    let mut decimator = Decimator::new(3, 4, 64.0);
+    let kept: Vec<bool> = (0..8).map(|_| decimator.push(&mut [0.0; 3])).collect();
+    assert_eq!(
+      kept,
+      vec![false, false, false, true, false, false, false, true]
+    );
+  }
+
+  #[test]
+  fn decimator_filters_above_the_new_nyquist() {
+    // Passband tone, on a raw-PPG-sized offset the filter must settle to
+    assert!(decimated_amplitude(1.0, 100_000.0) > 0.9);
+    // 14 Hz would alias to 2 Hz at the 16 Hz output rate
+    assert!(decimated_amplitude(14.0, 0.0) < 0.05);
+  }
+
+  #[test]
+  fn signal_summary_tells_flat_from_saturated() {
+    let mut summary = SignalSummary::new(3, (0.0, 255.0));
+    for i in 0..100 {
+      // A live channel, one with nothing reaching it, one stuck at the rail
+      let live = 120.0 + (i % 7) as f32;
+      summary.record(&[live, 128.0, 255.0]);
+    }
+    assert_eq!(summary.samples(), 100);
+    let quality: Vec<&str> = summary
+      .channels
+      .iter()
+      .map(ChannelSummary::quality)
+      .collect();
+    assert_eq!(quality, vec!["ok", "flat", "saturated"]);
+    assert_eq!(summary.channels[1].rms_noise(), 0.0);
+  }
+
+  #[test]
+  fn signal_summary_reports_offset_noise_and_rail_hits() {
+    let mut summary = SignalSummary::new(2, (0.0, 255.0));
+    // A square wave of +-3 around 100, and a channel touching a rail once in four
+    for i in 0..400 {
+      let square = if i % 2 == 0 { 103.0 } else { 97.0 };
+      let railed = if i % 4 == 0 { 255.0 } else { 128.0 };
+      summary.record(&[square, railed]);
+    }
+    assert_eq!(summary.samples(), 400);
+    assert!((summary.channels[0].mean() - 100.0).abs() < 1e-9);
+    assert!((summary.channels[0].rms_noise() - 3.0).abs() < 1e-6);
+    assert_eq!(summary.channels[0].saturated, 0);
+    assert_eq!(summary.channels[1].saturated, 100);
+  }
+
+  /// Where gravity points in the body frame for orientation `q`
+  fn gravity_in_body(q: [f32; 4]) -> [f32; 3] {
+    let [w, x, y, z] = q;
+    [
+      2.0 * (x * z - w * y),
+      2.0 * (w * x + y * z),
+      w * w - x * x - y * y + z * z,
+    ]
+  }
+
+  #[test]
+  fn orientation_converges_to_the_measured_tilt() {
+    let mut orientation = Orientation::new(0.5, 52.0);
+    // Pitched 30 degrees, held still
+    let tilt = 30f32.to_radians();
+    let accel = [-tilt.sin(), 0.0, tilt.cos()];
+    let mut q = [1.0, 0.0, 0.0, 0.0];
+    for _ in 0..52 * 10 {
+      orientation.accelerometer(accel);
+      q = orientation.gyroscope([0.0; 3]);
+    }
+    // The correction has a fixed size, so the estimate hovers within a
+    // degree or so of the tilt rather than settling on it
+    for (estimated, measured) in gravity_in_body(q).iter().zip(accel) {
+      assert!((estimated - measured).abs() < 0.03, "{:?}", q);
+    }
+  }
+
+  #[test]
+  fn orientation_integrates_the_gyroscope() {
+    let mut orientation = Orientation::new(0.1, 52.0);
+    orientation.accelerometer([0.0, 0.0, 1.0]);
+    // A quarter turn about the vertical axis over one second
+    let mut q = [1.0, 0.0, 0.0, 0.0];
+    for _ in 0..52 {
+      q = orientation.gyroscope([0.0, 0.0, 90.0]);
+    }
+    let half = 45f32.to_radians();
+    let expected = [half.cos(), 0.0, 0.0, half.sin()];
+    for (actual, expected) in q.iter().zip(expected) {
+      assert!((actual - expected).abs() < 0.01, "{:?}", q);
+    }
+  }
+}
@@ -0,0 +1,55 @@
+use napi_derive::napi;
+
+/// Counters for the current streaming session, reset by `startStreaming`
+#[napi(object)]
+#[derive(Debug, Clone, Default)]
+pub struct StreamStats {
+  pub eeg_samples: u32,
+  pub ppg_samples: u32,
+  /// EEG rate measured over the last monitoring window, 0 until the first window completes
+  pub measured_eeg_rate: f64,
+  /// EEG rate estimated by `dejitterTimestamps`, 0 when that's off
+  pub dejittered_eeg_rate: f64,
+  /// Windows where the measured EEG rate deviated beyond `rateWarningPct`
+  pub rate_warnings: u32,
+  /// Latest EEG timestamp minus latest PPG timestamp, in milliseconds
+  pub eeg_ppg_offset_ms: Option<f64>,
+  /// EEG channels that sent no packets within `silentChannelTimeoutMs`
+  pub silent_eeg_channels: Vec<String>,
+  /// EEG values that exceeded `clampCounts`
+  pub clamped_eeg_values: u32,
+  /// EEG packet loss (in percent) over the last 5 s window, from the sequence counters
+  pub packet_loss_pct: f64,
+  /// Packets dropped for repeating the previous packet's sequence number
+  pub duplicate_packets: u32,
+  /// Times the link was re-established because of `reconnectOnLossPct`
+  pub loss_reconnects: u32,
+  /// Notifications from characteristics the crate doesn't decode
+  pub unhandled_notifications: u32,
+  /// Times the start commands were resent because no EEG arrived at all
+  pub resyncs: u32,
+  /// Packets dropped because they couldn't be decoded, e.g. a PPG payload
+  /// that isn't whole 24-bit samples, pointing at a wrong header size
+  pub malformed_packets: u32,
+}
+
+#[napi(object)]
+#[derive(Debug, Clone)]
+pub struct RateWarning {
+  pub measured_rate: f64,
+  pub nominal_rate: f64,
+  pub deviation_pct: f64,
+}
+
+/// Approximate task/thread counts for debugging the streaming lifecycle.
+/// Each stream gets its own LSL thread rather than sharing a runtime
+#[napi(object)]
+#[derive(Debug, Clone, Default)]
+pub struct RuntimeStats {
+  /// LSL threads plus BLE notification tasks still running
+  pub active_streaming_tasks: u32,
+  /// Should be at most 1; more means an earlier stream didn't shut down
+  pub lsl_threads: u32,
+  pub notification_task_running: bool,
+  pub keepalive_running: bool,
+}
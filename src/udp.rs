@@ -0,0 +1,55 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+
+/// `kind` byte of EEG datagrams
+pub const UDP_KIND_EEG: u8 = 0;
+/// `kind` byte of PPG datagrams
+pub const UDP_KIND_PPG: u8 = 1;
+
+/// Sends each sample as one UDP datagram, all little-endian:
+///
+/// | offset | type      | field                                   |
+/// |--------|-----------|-----------------------------------------|
+/// | 0      | `u8`      | kind, 0 = EEG, 1 = PPG                  |
+/// | 1      | `f64`     | timestamp, `lsl::local_clock()` seconds |
+/// | 9      | `f32` × n | channels, as pushed to the LSL outlet   |
+///
+/// `n` is `(datagram length - 9) / 4`. There is no sequence number or
+/// retransmission; lost datagrams are simply gone
+pub struct UdpSink {
+  socket: UdpSocket,
+  packet: Vec<u8>,
+}
+
+impl UdpSink {
+  /// Opens a socket sending to `target` (`host:port`, IPv4 or IPv6), bound
+  /// to the family of the address it resolves to
+  pub fn new(target: &str) -> std::io::Result<Self> {
+    let target = target.to_socket_addrs()?.next().ok_or_else(|| {
+      std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("{} resolves to no address", target),
+      )
+    })?;
+    let local = match target {
+      SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+      SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(target)?;
+    Ok(Self {
+      socket,
+      packet: Vec::with_capacity(9 + 5 * 4),
+    })
+  }
+
+  pub fn send(&mut self, kind: u8, timestamp: f64, channels: &[f32]) {
+    self.packet.clear();
+    self.packet.push(kind);
+    self.packet.extend_from_slice(&timestamp.to_le_bytes());
+    for value in channels {
+      self.packet.extend_from_slice(&value.to_le_bytes());
+    }
+    // Loss is acceptable on this transport, so failed sends are dropped too
+    let _ = self.socket.send(&self.packet);
+  }
+}